@@ -0,0 +1,222 @@
+// CircularBuffer<u8> come stream di byte: implementa Read, Write e BufRead così può stare
+// tra un socket e un parser a righe. Le copie avvengono a blocchi sui due pezzi contigui
+// del buffer invece che un elemento alla volta.
+// Attenzione: i metodi inerenti read() e write() nascondono quelli dei trait
+// con lo stesso nome, per quelli si usa la forma Read::read(&mut buf, ..) oppure
+// read_exact / write_all / lines che non hanno conflitti.
+
+use std::io::{self, BufRead, Read, Write};
+use std::ptr;
+
+use crate::{CircularBuffer, WritePolicy};
+
+impl CircularBuffer<u8> {
+
+    // copia in coda quanti più byte possibile, senza guardare la policy
+    fn push_bytes(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.capacity - self.size);
+        let mut written = 0;
+        while written < n {
+            let index = self.head % self.capacity;
+            let chunk = (n - written).min(self.capacity - index); // fino alla fine del vec, poi si riparte da 0
+            // SAFETY: gli slot index..index + chunk sono liberi e u8 non ha drop
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    data[written..].as_ptr(),
+                    self.buffer[index..].as_mut_ptr() as *mut u8,
+                    chunk,
+                );
+            }
+            self.head = (index + chunk) % self.capacity;
+            self.size += chunk;
+            written += chunk;
+        }
+        written
+    }
+
+    // scarta i primi n byte, u8 non ha drop quindi basta spostare tail
+    fn discard(&mut self, n: usize) {
+        let n = n.min(self.size);
+        if n == 0 {
+            return;
+        }
+        self.tail = (self.tail + n) % self.capacity;
+        self.size -= n;
+        if self.size == 0 { // buffer vuoto: riparto da 0 così fill_buf restituisce pezzi più lunghi
+            self.head = 0;
+            self.tail = 0;
+        }
+    }
+
+    // i primi n byte (o meno, se non ci sono) senza consumarli; se attraversano la fine
+    // del vec il buffer viene reso contiguo sul posto
+    pub fn peek(&mut self, n: usize) -> &[u8] {
+        let n = n.min(self.size);
+        if self.tail + n > self.capacity {
+            self.make_contiguous();
+        }
+        &self.as_slices().0[..n]
+    }
+
+    // estrae i byte fino al delimitatore compreso; se il delimitatore non è ancora
+    // arrivato restituisce None e non consuma nulla (diverso da BufRead::read_until)
+    pub fn read_until_delim(&mut self, delim: u8) -> Option<Vec<u8>> {
+        let (first, second) = self.as_slices();
+        let len = first.iter().chain(second).position(|&b| b == delim)? + 1;
+        let from_first = len.min(first.len());
+        let mut line = Vec::with_capacity(len);
+        line.extend_from_slice(&first[..from_first]);
+        line.extend_from_slice(&second[..len - from_first]);
+        self.discard(len);
+        Some(line)
+    }
+}
+
+impl Read for CircularBuffer<u8> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let (first, second) = self.as_slices();
+        let n1 = first.len().min(out.len());
+        out[..n1].copy_from_slice(&first[..n1]);
+        let n2 = second.len().min(out.len() - n1);
+        out[n1..n1 + n2].copy_from_slice(&second[..n2]);
+        self.discard(n1 + n2);
+        Ok(n1 + n2) // 0 con buffer vuoto: per Read equivale a EOF
+    }
+}
+
+impl Write for CircularBuffer<u8> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self.policy {
+            // scrive quello che entra, Ok(0) se pieno (write_all restituisce WriteZero)
+            WritePolicy::Fixed => Ok(self.push_bytes(data)),
+            WritePolicy::Grow { max } => {
+                let needed = self.size + data.len();
+                if needed > self.capacity && self.capacity < max {
+                    self.relocate(needed.max(self.capacity * 2).min(max));
+                }
+                Ok(self.push_bytes(data))
+            }
+            // accetta sempre tutto, tenendo solo gli ultimi capacity byte
+            WritePolicy::Overwrite => {
+                let tail = &data[data.len().saturating_sub(self.capacity)..];
+                let excess = (self.size + tail.len()).saturating_sub(self.capacity);
+                self.discard(excess);
+                self.push_bytes(tail);
+                Ok(data.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(()) // i dati sono già nel buffer
+    }
+}
+
+impl BufRead for CircularBuffer<u8> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.as_slices().0) // il primo pezzo contiguo, nessuna copia
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.discard(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // porta tail a metà buffer così i dati successivi attraversano la fine del vec
+    fn wrapped(capacity: usize, skip: usize) -> CircularBuffer<u8> {
+        let mut buffer = CircularBuffer::new(capacity);
+        buffer.write_all(&vec![0; skip]).unwrap();
+        for _ in 0..skip {
+            buffer.read(); // read() inerente: un byte alla volta, non riporta tail a 0
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_write_all_and_read_exact() {
+        let mut buffer = wrapped(8, 5);
+        buffer.write_all(b"abcdef").unwrap();
+        assert_eq!(buffer.as_slices(), (&b"abc"[..], &b"def"[..]));
+        let mut out = [0; 6];
+        buffer.read_exact(&mut out).unwrap();
+        assert_eq!(&out, b"abcdef");
+        assert_eq!(Read::read(&mut buffer, &mut out).unwrap(), 0); // vuoto: EOF
+    }
+
+    #[test]
+    fn test_fixed_write_stops_when_full() {
+        let mut buffer = CircularBuffer::new(4);
+        assert_eq!(Write::write(&mut buffer, b"abcdef").unwrap(), 4);
+        let err = buffer.write_all(b"g").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn test_overwrite_policy_keeps_last_bytes() {
+        let mut buffer = CircularBuffer::with_policy(4, WritePolicy::Overwrite);
+        buffer.write_all(b"abc").unwrap();
+        buffer.write_all(b"de").unwrap();
+        assert_eq!(buffer.peek(4), b"bcde");
+        buffer.write_all(b"0123456789").unwrap();
+        let mut out = Vec::new();
+        buffer.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"6789");
+    }
+
+    #[test]
+    fn test_grow_policy_write() {
+        let mut buffer = CircularBuffer::with_policy(2, WritePolicy::Grow { max: 16 });
+        buffer.write_all(b"hello world").unwrap();
+        assert_eq!(buffer.capacity(), 11);
+        assert!(buffer.write_all(b"0123456789").is_err()); // entra solo fino a max
+        assert_eq!(buffer.size(), 16);
+    }
+
+    #[test]
+    fn test_peek_across_the_end() {
+        let mut buffer = wrapped(6, 4);
+        buffer.write_all(b"wxyz").unwrap();
+        assert_eq!(buffer.as_slices().0, b"wx");
+        assert_eq!(buffer.peek(3), b"wxy"); // rende contiguo sul posto
+        assert_eq!(buffer.peek(10), b"wxyz");
+        assert_eq!(buffer.size(), 4); // peek non consuma
+    }
+
+    #[test]
+    fn test_read_until_delim_waits_for_delimiter() {
+        let mut buffer = wrapped(8, 6);
+        buffer.write_all(b"GET ").unwrap();
+        assert_eq!(buffer.read_until_delim(b'\n'), None);
+        assert_eq!(buffer.size(), 4);
+        buffer.write_all(b"/\nX").unwrap();
+        assert_eq!(buffer.read_until_delim(b'\n').unwrap(), b"GET /\n");
+        assert_eq!(buffer.peek(8), b"X");
+
+        // BufRead::read_until resta raggiungibile con la sintassi dei metodi
+        let mut line = Vec::new();
+        assert_eq!(buffer.read_until(b'\n', &mut line).unwrap(), 1);
+        assert_eq!(line, b"X");
+    }
+
+    #[test]
+    fn test_buf_read_lines() {
+        let mut buffer = wrapped(16, 10);
+        buffer.write_all(b"one\ntwo\nthree").unwrap();
+        let lines: Vec<String> = buffer.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_io_copy_between_streams() {
+        let mut buffer = CircularBuffer::with_policy(4, WritePolicy::Grow { max: 1024 });
+        let data: Vec<u8> = (0..=255).collect();
+        io::copy(&mut io::Cursor::new(&data), &mut buffer).unwrap();
+        let mut out = Vec::new();
+        io::copy(&mut buffer, &mut out).unwrap();
+        assert_eq!(out, data);
+    }
+}
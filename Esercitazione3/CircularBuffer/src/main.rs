@@ -1,38 +1,79 @@
+use std::fmt;
+use std::mem::MaybeUninit;
 use std::ops::{Deref, DerefMut, Index, IndexMut};
 use crate::BufferError::Full;
 
+pub mod bytes; // Read/Write/BufRead per CircularBuffer<u8>, vedi bytes.rs
+pub mod spsc; // variante lock-free produttore/consumatore, vedi spsc.rs
+pub mod stats; // statistiche su finestra scorrevole, vedi stats.rs
+
 #[derive(Debug)]
-enum BufferError {
+pub enum BufferError {
     Full,
     Empty,
 }
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+
+// cosa fa write quando il buffer è pieno, scelto alla costruzione
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    #[default]
+    Fixed, // capacità fissa, write restituisce Err(Full)
+    Overwrite, // write si comporta come overwrite, scarta il più vecchio
+    Grow { max: usize }, // raddoppia la capacità fino a max, poi Err(Full)
+}
+
 pub struct CircularBuffer<T> { // Struct per il buffer circolare GENERICA
-    buffer: Vec<T>,
+    buffer: Vec<MaybeUninit<T>>, // slot non inizializzati: sono vivi solo gli elementi tra tail e tail + size
     head: usize,
     tail: usize,
     size: usize,
     capacity: usize,
+    policy: WritePolicy,
 }
 
-impl<T: Clone + Default> CircularBuffer<T> { // implementazione generica
+impl<T> CircularBuffer<T> { // implementazione generica, nessun vincolo su T
 
     pub fn new(capacity: usize) -> Self {
+        Self::with_policy(capacity, WritePolicy::Fixed)
+    }
+
+    pub fn with_policy(capacity: usize, policy: WritePolicy) -> Self {
         CircularBuffer {
-            buffer: vec![Default::default(); capacity], // inizializza il buffer con valori di default
+            buffer: (0..capacity).map(|_| MaybeUninit::uninit()).collect(), // niente Default: gli slot restano vuoti
             head: 0,
             tail: 0,
             size: 0,
             capacity,
+            policy,
         }
     }
 
+    // indice reale nel vec dell'i-esimo elemento a partire da tail
+    fn slot(&self, index: usize) -> usize {
+        (self.tail + index) % self.capacity
+    }
+
     pub fn write(&mut self, item: T) -> Result<(), BufferError> {
         if self.size == self.capacity {
-            return Err(Full); // buffer pieno
+            match self.policy {
+                WritePolicy::Fixed => return Err(Full), // buffer pieno
+                // senza slot non c'è niente da sovrascrivere: l'elemento non entrerebbe mai
+                WritePolicy::Overwrite if self.capacity == 0 => return Err(Full),
+                WritePolicy::Overwrite => {
+                    self.overwrite(item);
+                    return Ok(());
+                }
+                WritePolicy::Grow { max } => {
+                    if self.capacity >= max {
+                        return Err(Full); // raggiunto il limite massimo
+                    }
+                    self.relocate((self.capacity * 2).clamp(1, max)); // raddoppio mantenendo l'ordine
+                }
+            }
         }
-        self.buffer[self.head] = item;
-        self.head = (self.head + 1) % self.capacity; // resto della divisione, se head raggiunge capacity torna a 0
+        let index = self.head % self.capacity; // head può valere capacity dopo make_contiguous
+        self.buffer[index].write(item); // lo slot era libero, non c'è nulla da droppare
+        self.head = (index + 1) % self.capacity; // resto della divisione, se head raggiunge capacity torna a 0
         self.size += 1;
         Ok(())
     }
@@ -40,52 +81,162 @@ impl<T: Clone + Default> CircularBuffer<T> { // implementazione generica
         if self.size == 0 {
             return None; // buffer vuoto
         }
-        let item = self.buffer[self.tail].clone(); // clona l'elemento corrente (non posso usare il riferimento senza Copy)
+        // SAFETY: lo slot in tail è vivo (size > 0), dopo la lettura lo consideriamo libero
+        // quindi il valore viene mosso fuori una sola volta, niente clone
+        let item = unsafe { self.buffer[self.tail].assume_init_read() };
         self.tail = (self.tail + 1) % self.capacity; // resto della divisione, se tail raggiunge capacity torna a 0
         self.size -= 1; // decremento la dimensione
         Some(item) // restituisco l'elemento letto
     }
     pub fn clear(&mut self) {
+        while self.read().is_some() {} // droppa gli elementi ancora vivi
         self.head = 0;
         self.tail = 0;
-        self.size = 0;
     }
     pub fn size(&self) -> usize{
         self.size
     }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn policy(&self) -> WritePolicy {
+        self.policy
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &T> { // dal più vecchio al più recente
+        (0..self.size).map(move |i| &self[i])
+    }
+    // gli elementi vivi in due pezzi contigui (da tail alla fine del vec, poi dall'inizio), come VecDeque
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let first_len = self.size.min(self.capacity - self.tail);
+        let first = &self.buffer[self.tail..self.tail + first_len];
+        let second = &self.buffer[..self.size - first_len];
+        // SAFETY: sono esattamente gli slot vivi, MaybeUninit<T> ha lo stesso layout di T
+        unsafe {
+            (
+                &*(first as *const [MaybeUninit<T>] as *const [T]),
+                &*(second as *const [MaybeUninit<T>] as *const [T]),
+            )
+        }
+    }
     // può essere usata quando il buffer è pieno per forzare una
     // scrittura riscrivendo l’elemento più vecchio (leggo e scrivo)
     pub fn overwrite(&mut self, item: T) {
+        if self.capacity == 0 {
+            return; // nessuno slot disponibile, l'elemento viene semplicemente droppato
+        }
         if self.size == self.capacity {
+            // SAFETY: il buffer è pieno, quindi lo slot in tail è vivo e va droppato prima di riscriverlo
+            unsafe { self.buffer[self.tail].assume_init_drop() };
             self.tail = (self.tail + 1) % self.capacity; // se il buffer è pieno, sovrascrivo l'elemento più vecchio
         } else {
             self.size += 1; // altrimenti incremento la dimensione
         }
-        self.buffer[self.head] = item;
-        self.head = (self.head + 1) % self.capacity; // resto della divisione, se head raggiunge capacity torna a 0
+        let index = self.head % self.capacity;
+        self.buffer[index].write(item);
+        self.head = (index + 1) % self.capacity; // resto della divisione, se head raggiunge capacity torna a 0
     }
     // vedi sotto*
     pub fn make_contiguous(&mut self) {
-        if self.tail < self.head { // Se tail è minore di head, il buffer è già contiguo
+        if self.tail + self.size <= self.capacity { // gli elementi vivi non attraversano la fine del vec
             return; // non è necessario fare nulla
         }
-        let mut new_buffer = vec![Default::default(); self.size]; // Crea un nuovo buffer con la dimensione attuale
-        let mut index = 0; // Inizializza l'indice a 0
-        for i in self.tail..self.capacity { // Copia gli elementi dal tail alla fine del buffer
-            new_buffer[index] = self.buffer[i].clone(); // Copia l'elemento corrente nel nuovo buffer
-            index += 1; // Incrementa l'indice
-        }
-        for i in 0..self.head { // Copia gli elementi dall'inizio del buffer fino a head
-            new_buffer[index] = self.buffer[i].clone(); // Copia l'elemento corrente nel nuovo buffer
-            index += 1; // Incrementa l'indice
-        }
-        self.buffer = new_buffer; // Sostituisce il buffer originale con il nuovo buffer
+        // ruoto il vec sul posto: tail finisce in 0 e la parte da 0 a head finisce in coda.
+        // MaybeUninit non ha drop, quindi spostare gli slot non tocca gli elementi
+        self.buffer.rotate_left(self.tail);
         self.head = self.size; // Imposta head alla dimensione attuale
         self.tail = 0; // Imposta tail a 0
     } // spezzo il buffer in due, la parte contigua è quella che va da tail a head, quindi piazzo
-    // la metà che va da tail a capacity all'inizio del nuovo buffer e la metà che va da 0 a head alla fine
+    // la metà che va da tail a capacity all'inizio del buffer e la metà che va da 0 a head alla fine
+
+    // garantisce spazio per almeno additional scritture senza riallocare
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.size + additional;
+        if needed > self.capacity {
+            self.relocate(needed);
+        }
+    }
+
+    // riduce la capacità al numero di elementi presenti
+    pub fn shrink_to_fit(&mut self) {
+        if self.size < self.capacity {
+            self.relocate(self.size);
+        }
+    }
+
+    // cambia la capacità; se è minore della dimensione scarta gli elementi più vecchi
+    pub fn resize(&mut self, new_capacity: usize) {
+        while self.size > new_capacity {
+            self.read();
+        }
+        if new_capacity != self.capacity {
+            self.relocate(new_capacity);
+        }
+    }
+
+    // sposta gli elementi vivi, in ordine, all'inizio di un vec con la nuova capacità
+    fn relocate(&mut self, new_capacity: usize) {
+        debug_assert!(new_capacity >= self.size);
+        self.make_contiguous();
+        let mut buffer: Vec<MaybeUninit<T>> = (0..new_capacity).map(|_| MaybeUninit::uninit()).collect();
+        for (i, dst) in buffer.iter_mut().enumerate().take(self.size) {
+            // SAFETY: dopo make_contiguous gli elementi vivi sono in tail..tail + size;
+            // il vecchio vec viene scartato senza droppare nulla, quindi ogni valore è mosso una volta sola
+            dst.write(unsafe { self.buffer[self.tail + i].assume_init_read() });
+        }
+        self.buffer = buffer;
+        self.capacity = new_capacity;
+        self.tail = 0;
+        self.head = if new_capacity == 0 { 0 } else { self.size % new_capacity };
+    }
+}
+
+impl<T> Drop for CircularBuffer<T> {
+    fn drop(&mut self) {
+        for i in 0..self.size { // droppo solo gli slot vivi, quelli liberi non sono inizializzati
+            let index = self.slot(i);
+            // SAFETY: i < size, quindi lo slot contiene un valore valido
+            unsafe { self.buffer[index].assume_init_drop() };
+        }
+    }
+}
+
+impl<T> Default for CircularBuffer<T> {
+    fn default() -> Self {
+        CircularBuffer::new(0)
+    }
+}
+
+impl<T: Clone> Clone for CircularBuffer<T> { // Clone serve solo per clonare il buffer, non per usarlo
+    fn clone(&self) -> Self {
+        let mut buffer: Vec<MaybeUninit<T>> = (0..self.capacity).map(|_| MaybeUninit::uninit()).collect();
+        for i in 0..self.size { // stessa disposizione in memoria dell'originale
+            buffer[self.slot(i)].write(self[i].clone());
+        }
+        CircularBuffer {
+            buffer,
+            head: self.head,
+            tail: self.tail,
+            size: self.size,
+            capacity: self.capacity,
+            policy: self.policy,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CircularBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish() // stampo solo gli elementi vivi
+    }
 }
 
+impl<T: PartialEq> PartialEq for CircularBuffer<T> { // due buffer sono uguali se contengono gli stessi elementi nello stesso ordine
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for CircularBuffer<T> {}
+
 impl<T> Index<usize> for CircularBuffer<T> { // permette di fare indexing
     type Output = T; // attento all'output
 
@@ -93,9 +244,10 @@ impl<T> Index<usize> for CircularBuffer<T> { // permette di fare indexing
         if index >= self.size { // se l'indice è maggiore della dimensione del buffer
             panic!("Index out of bounds");
         }
-        // buff[0] legge l'elemento in teta
-        let real_index = (self.head + index + 1) % self.capacity; // calcolo l'indice reale
-        &self.buffer[real_index]
+        // buff[0] legge l'elemento in testa (il più vecchio, in tail)
+        let real_index = self.slot(index); // calcolo l'indice reale
+        // SAFETY: index < size, lo slot è vivo
+        unsafe { self.buffer[real_index].assume_init_ref() }
     }
 }
 
@@ -105,8 +257,9 @@ impl<T> IndexMut<usize> for CircularBuffer<T> {
         if index >= self.size {
             panic!("Index out of bounds");
         }
-        let real_index = (self.head + index + 1) % self.capacity;
-        &mut self.buffer[real_index] // mutabile!
+        let real_index = self.slot(index);
+        // SAFETY: index < size, lo slot è vivo
+        unsafe { self.buffer[real_index].assume_init_mut() } // mutabile!
     }
 }
 
@@ -117,12 +270,11 @@ impl<T> Deref for CircularBuffer<T> { // permette di dereferenziare il buffer a
     fn deref(&self) -> &Self::Target {
         if self.size == 0 { // buffer vuoto
             &[]
-        } else if self.head < self.tail {
+        } else if self.tail + self.size <= self.capacity {
             // dati contigui, possiamo usare una slice diretta
-            &self.buffer[(self.head + 1)..self.tail]
-        } else if self.tail == 0 {
-            // wrap-around completo, ma dati contigui da head+1 a fine
-            &self.buffer[(self.head + 1)..]
+            let live = &self.buffer[self.tail..self.tail + self.size];
+            // SAFETY: tutti gli slot in live sono inizializzati e MaybeUninit<T> ha lo stesso layout di T
+            unsafe { &*(live as *const [MaybeUninit<T>] as *const [T]) }
         } else {
             panic!("Buffer is not contiguous, cannot deref");
         }
@@ -134,12 +286,11 @@ impl<T> DerefMut for CircularBuffer<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         if self.size == 0 { // buffer vuoto
             &mut []
-        } else if self.head < self.tail {
+        } else if self.tail + self.size <= self.capacity {
             // dati contigui, possiamo usare una slice diretta
-            &mut self.buffer[(self.head + 1)..self.tail]
-        } else if self.tail == 0 {
-            // wrap-around completo, ma dati contigui da head+1 a fine
-            &mut self.buffer[(self.head + 1)..]
+            let live = &mut self.buffer[self.tail..self.tail + self.size];
+            // SAFETY: come in deref, ma con accesso esclusivo
+            unsafe { &mut *(live as *mut [MaybeUninit<T>] as *mut [T]) }
         } else {
             panic!("Buffer is not contiguous, cannot deref");
         }
@@ -163,9 +314,16 @@ fn main() {
     buffer.write(4).unwrap();
 }
 
+// il codice unsafe (qui, in bytes.rs e in spsc.rs) passa anche sotto Miri, che segnala
+// letture di slot non inizializzati, doppi drop e data race:
+//     rustup +nightly component add miri
+//     cargo +nightly miri test
+// gli stress test di spsc.rs fanno meno iterazioni sotto Miri, che è molto più lento
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
     use cnumbers::solution::ComplexNumber; // package cnumbers, pub mod solution, dependency ../cnumbers, workspace nella directory più alta
 
     #[test]
@@ -233,6 +391,14 @@ mod tests {
         assert_eq!(buffer.read(), Some(4));
     }
 
+    #[test]
+    fn test_overwrite_policy_without_capacity() {
+        // nessuno slot da sovrascrivere: write fallisce anche con Overwrite
+        let mut empty = CircularBuffer::with_policy(0, WritePolicy::Overwrite);
+        assert!(matches!(empty.write(1), Err(Full)));
+        assert_eq!(empty.size(), 0);
+    }
+
     #[test]
     fn test_make_contiguous() {
         let mut buffer = CircularBuffer::new(3);
@@ -246,6 +412,98 @@ mod tests {
         assert_eq!(buffer.tail, 0);
     }
 
+    #[test]
+    fn test_make_contiguous_in_place() {
+        let mut buffer = CircularBuffer::new(5);
+        for i in 1..=5 {
+            buffer.write(i).unwrap();
+        }
+        buffer.read();
+        buffer.read();
+        buffer.write(6).unwrap();
+        let before = buffer.buffer.as_ptr();
+        buffer.make_contiguous();
+        assert_eq!(buffer.buffer.as_ptr(), before); // nessuna nuova allocazione
+        assert_eq!(&*buffer, &[3, 4, 5, 6]);
+        buffer.write(7).unwrap();
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let mut buffer = CircularBuffer::with_policy(2, WritePolicy::Overwrite);
+        for i in 1..=4 {
+            buffer.write(i).unwrap();
+        }
+        assert_eq!(buffer.read(), Some(3));
+        assert_eq!(buffer.read(), Some(4));
+    }
+
+    #[test]
+    fn test_grow_policy() {
+        let mut buffer = CircularBuffer::with_policy(2, WritePolicy::Grow { max: 5 });
+        buffer.write(1).unwrap();
+        buffer.write(2).unwrap();
+        buffer.read();
+        buffer.write(3).unwrap(); // il contenuto ora attraversa la fine del vec
+        buffer.write(4).unwrap();
+        assert_eq!(buffer.capacity(), 4);
+        buffer.write(5).unwrap();
+        buffer.write(6).unwrap();
+        assert_eq!(buffer.capacity(), 5); // limitato da max
+        assert!(buffer.write(7).is_err());
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_grow_from_zero() {
+        let mut buffer = CircularBuffer::with_policy(0, WritePolicy::Grow { max: 8 });
+        for i in 0..8 {
+            buffer.write(i).unwrap();
+        }
+        assert_eq!(buffer.capacity(), 8);
+        assert_eq!(buffer.read(), Some(0));
+    }
+
+    #[test]
+    fn test_reserve_shrink_resize() {
+        let mut buffer = CircularBuffer::new(3);
+        for i in 1..=3 {
+            buffer.write(i).unwrap();
+        }
+        buffer.read();
+        buffer.write(4).unwrap();
+        buffer.reserve(2);
+        assert_eq!(buffer.capacity(), 5);
+        buffer.write(5).unwrap();
+        buffer.write(6).unwrap();
+        assert!(buffer.write(7).is_err()); // la policy resta Fixed
+        buffer.read();
+        buffer.shrink_to_fit();
+        assert_eq!(buffer.capacity(), 4);
+        assert_eq!(&*buffer, &[3, 4, 5, 6]);
+        buffer.resize(2); // scarta i più vecchi
+        assert_eq!(buffer.capacity(), 2);
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![5, 6]);
+        buffer.resize(4);
+        buffer.write(8).unwrap();
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![5, 6, 8]);
+    }
+
+    #[test]
+    fn test_resize_drops_discarded_elements() {
+        let drops = Rc::new(Cell::new(0));
+        let mut buffer = CircularBuffer::with_policy(2, WritePolicy::Grow { max: 16 });
+        for _ in 0..6 {
+            buffer.write(counted(&drops)).unwrap();
+        }
+        assert_eq!(drops.get(), 0); // crescere sposta gli elementi senza dropparli
+        buffer.resize(1);
+        assert_eq!(drops.get(), 5);
+        drop(buffer);
+        assert_eq!(drops.get(), 6);
+    }
+
     #[test]
     fn test_circular_buffer_with_complex_number() {
         let mut buffer = CircularBuffer::new(3);
@@ -262,6 +520,163 @@ mod tests {
         assert_eq!(buffer.read(), Some(complex3));
     }
 
+    #[test]
+    fn test_index_follows_tail() {
+        let mut buffer = CircularBuffer::new(3);
+        for i in 1..=3 {
+            buffer.write(i).unwrap();
+        }
+        buffer.read();
+        buffer.write(4).unwrap();
+        assert_eq!(buffer[0], 2);
+        assert_eq!(buffer[2], 4);
+        buffer[1] = 30;
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), vec![2, 30, 4]);
+    }
+
+    #[test]
+    fn test_deref_after_make_contiguous() {
+        let mut buffer = CircularBuffer::new(3);
+        for i in 1..=3 {
+            buffer.write(i).unwrap();
+        }
+        buffer.overwrite(4);
+        buffer.make_contiguous();
+        assert_eq!(&*buffer, &[2, 3, 4]);
+        buffer.overwrite(5); // head era a capacity, deve ripartire da 0
+        assert_eq!(buffer.read(), Some(3));
+        assert_eq!(buffer.read(), Some(4));
+        assert_eq!(buffer.read(), Some(5));
+    }
+
+    // tipo non Clone e non Default: prima non si poteva inserire nel buffer
+    struct Counted {
+        drops: Rc<Cell<usize>>,
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    fn counted(drops: &Rc<Cell<usize>>) -> Counted {
+        Counted { drops: drops.clone() }
+    }
+
+    #[test]
+    fn test_read_moves_out() {
+        let drops = Rc::new(Cell::new(0));
+        let mut buffer = CircularBuffer::new(2);
+        buffer.write(counted(&drops)).unwrap();
+        let item = buffer.read().unwrap();
+        assert_eq!(drops.get(), 0); // nessuna copia droppata dal buffer
+        drop(item);
+        assert_eq!(drops.get(), 1);
+        assert!(buffer.read().is_none());
+    }
+
+    #[test]
+    fn test_drop_only_live_elements() {
+        let drops = Rc::new(Cell::new(0));
+        {
+            let mut buffer = CircularBuffer::new(4);
+            for _ in 0..3 {
+                buffer.write(counted(&drops)).unwrap();
+            }
+            drop(buffer.read());
+            assert_eq!(drops.get(), 1);
+        } // restano 2 elementi vivi su 4 slot
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    fn test_overwrite_and_clear_drop_elements() {
+        let drops = Rc::new(Cell::new(0));
+        let mut buffer = CircularBuffer::new(2);
+        buffer.write(counted(&drops)).unwrap();
+        buffer.write(counted(&drops)).unwrap();
+        buffer.overwrite(counted(&drops)); // il più vecchio viene droppato
+        assert_eq!(drops.get(), 1);
+        buffer.clear();
+        assert_eq!(drops.get(), 3);
+        assert_eq!(buffer.size(), 0);
+        drop(buffer);
+        assert_eq!(drops.get(), 3); // nessun doppio drop
+    }
+
+    #[test]
+    fn test_make_contiguous_moves_without_drop() {
+        let drops = Rc::new(Cell::new(0));
+        let mut buffer = CircularBuffer::new(3);
+        for _ in 0..3 {
+            buffer.write(counted(&drops)).unwrap();
+        }
+        drop(buffer.read());
+        buffer.write(counted(&drops)).unwrap();
+        buffer.make_contiguous();
+        assert_eq!(drops.get(), 1);
+        assert_eq!(buffer.len(), 3); // len() dello slice tramite Deref
+        drop(buffer);
+        assert_eq!(drops.get(), 4);
+    }
+
+    trait Shape {
+        fn area(&self) -> f64;
+    }
+
+    struct Square(f64);
+    struct Circle(f64);
+
+    impl Shape for Square {
+        fn area(&self) -> f64 {
+            self.0 * self.0
+        }
+    }
+
+    impl Shape for Circle {
+        fn area(&self) -> f64 {
+            std::f64::consts::PI * self.0 * self.0
+        }
+    }
+
+    #[test]
+    fn test_heterogeneous_buffer() {
+        // vedi risposta domanda 5: buffer eterogeneo con trait object
+        let mut buffer: CircularBuffer<Box<dyn Shape>> = CircularBuffer::new(2);
+        buffer.write(Box::new(Square(2.0))).unwrap();
+        buffer.write(Box::new(Circle(1.0))).unwrap();
+        buffer.overwrite(Box::new(Square(3.0)));
+        let areas: Vec<f64> = buffer.iter().map(|s| s.area()).collect();
+        assert_eq!(areas, vec![std::f64::consts::PI, 9.0]);
+    }
+
+    #[test]
+    fn test_clone_and_eq() {
+        let mut buffer = CircularBuffer::new(3);
+        for i in 1..=3 {
+            buffer.write(String::from("s") + &i.to_string()).unwrap();
+        }
+        buffer.read();
+        let copy = buffer.clone();
+        assert_eq!(copy, buffer);
+        assert_eq!(format!("{:?}", copy), r#"["s2", "s3"]"#);
+        let mut other = CircularBuffer::new(3);
+        other.write(String::from("s2")).unwrap();
+        other.write(String::from("s3")).unwrap();
+        assert_eq!(other, buffer); // stessi elementi, posizioni diverse
+    }
+
+    #[test]
+    fn test_zero_capacity() {
+        let mut buffer: CircularBuffer<i32> = CircularBuffer::default();
+        assert!(buffer.write(1).is_err());
+        buffer.overwrite(1);
+        assert_eq!(buffer.read(), None);
+        assert_eq!(&*buffer, &[] as &[i32]);
+    }
+
+
 
 }
 
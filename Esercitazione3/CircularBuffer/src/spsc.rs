@@ -0,0 +1,410 @@
+// variante concorrente del buffer circolare: un solo produttore e un solo consumatore
+// su thread diversi, senza Mutex. Ogni slot ha un numero di sequenza atomico (schema di Vyukov):
+// - seq == pos       -> slot libero, il produttore può scrivere la posizione pos
+// - seq == pos + 1   -> slot pieno, il consumatore può leggere la posizione pos
+// - seq == pos + cap -> slot letto, torna libero per il giro successivo
+// head è scritto solo dal produttore, tail viene incrementato con CAS perché anche il
+// produttore, in overwrite, può "rubare" l'elemento più vecchio al posto del consumatore.
+// write, read e i metodi slice non aspettano mai l'altra metà. overwrite aspetta soltanto se
+// il consumatore ha già spostato tail ma sta ancora copiando l'elemento dallo slot in head:
+// una manciata di istruzioni senza codice dell'utente, ma se il consumatore viene sospeso
+// proprio lì il produttore gira finché non riparte.
+
+use std::cell::UnsafeCell;
+use std::hint;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::BufferError;
+
+struct Slot<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct SpscRingBuffer<T> {
+    slots: Box<[Slot<T>]>,
+    capacity: usize,
+    head: AtomicUsize, // prossima posizione da scrivere (contatore che non torna mai a 0)
+    tail: AtomicUsize, // prossima posizione da leggere
+}
+
+// SAFETY: l'accesso ai valori negli slot è regolato dai numeri di sequenza, un solo thread
+// alla volta possiede uno slot; basta che T si possa spostare tra thread
+unsafe impl<T: Send> Send for SpscRingBuffer<T> {}
+unsafe impl<T: Send> Sync for SpscRingBuffer<T> {}
+
+impl<T> SpscRingBuffer<T> {
+
+    // crea il buffer e lo divide subito nelle due metà: non esiste altro modo di usarlo
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(capacity: usize) -> (Producer<T>, Consumer<T>) {
+        assert!(capacity > 0, "capacity must be greater than 0");
+        let slots = (0..capacity)
+            .map(|i| Slot { seq: AtomicUsize::new(i), value: UnsafeCell::new(MaybeUninit::uninit()) })
+            .collect();
+        let inner = Arc::new(SpscRingBuffer {
+            slots,
+            capacity,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        });
+        (Producer { inner: inner.clone() }, Consumer { inner })
+    }
+
+    fn len(&self) -> usize {
+        // letture non atomiche tra loro: è solo una stima se l'altra metà sta lavorando
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        head.wrapping_sub(tail).min(self.capacity)
+    }
+
+    // inserisce in head se lo slot è libero, altrimenti restituisce l'elemento
+    fn push(&self, item: T) -> Result<(), T> {
+        let pos = self.head.load(Ordering::Relaxed); // solo il produttore scrive head
+        let slot = &self.slots[pos % self.capacity];
+        if slot.seq.load(Ordering::Acquire) != pos {
+            return Err(item); // slot non ancora letto (o in lettura): pieno
+        }
+        // SAFETY: seq == pos, nessun altro thread accede allo slot fino al prossimo store su seq
+        unsafe { (*slot.value.get()).write(item) };
+        slot.seq.store(pos.wrapping_add(1), Ordering::Release); // pubblica il valore al consumatore
+        self.head.store(pos.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    // prende l'elemento in pos solo se è ancora il più vecchio (tail == pos): il produttore
+    // in overwrite non deve scartare quello dopo se il consumatore ha appena preso pos
+    fn steal(&self, pos: usize) -> Option<T> {
+        let slot = &self.slots[pos % self.capacity];
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+        self.tail.compare_exchange(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed).ok()?;
+        // SAFETY: la CAS riuscita ci rende unici proprietari dello slot pos
+        let item = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.seq.store(pos.wrapping_add(self.capacity), Ordering::Release);
+        Some(item)
+    }
+
+    // legge da tail; usato dal consumatore
+    fn pop(&self) -> Option<T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.capacity];
+            let seq = slot.seq.load(Ordering::Acquire);
+            if seq != pos.wrapping_add(1) {
+                let current = self.tail.load(Ordering::Relaxed);
+                if current == pos {
+                    return None; // lo slot in tail non è ancora stato scritto: vuoto
+                }
+                pos = current; // qualcun altro ha già preso pos, riprovo dal nuovo tail
+                continue;
+            }
+            match self.tail.compare_exchange_weak(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => {
+                    // SAFETY: la CAS riuscita ci rende unici proprietari dello slot pos
+                    let item = unsafe { (*slot.value.get()).assume_init_read() };
+                    slot.seq.store(pos.wrapping_add(self.capacity), Ordering::Release); // libera lo slot
+                    return Some(item);
+                }
+                Err(current) => pos = current,
+            }
+        }
+    }
+}
+
+impl<T> Drop for SpscRingBuffer<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {} // droppa gli elementi rimasti, gli slot liberi non vanno toccati
+    }
+}
+
+// metà che scrive: non è Clone, quindi esiste un solo produttore
+pub struct Producer<T> {
+    inner: Arc<SpscRingBuffer<T>>,
+}
+
+// metà che legge: non è Clone, quindi esiste un solo consumatore
+pub struct Consumer<T> {
+    inner: Arc<SpscRingBuffer<T>>,
+}
+
+impl<T> Producer<T> {
+
+    pub fn write(&mut self, item: T) -> Result<(), BufferError> {
+        self.inner.push(item).map_err(|_| BufferError::Full)
+    }
+
+    // come CircularBuffer::overwrite: se il buffer è pieno scarta l'elemento più vecchio,
+    // che viene restituito invece di essere droppato
+    pub fn overwrite(&mut self, item: T) -> Option<T> {
+        let mut item = item;
+        let mut evicted = None;
+        loop {
+            match self.inner.push(item) {
+                Ok(()) => return evicted,
+                Err(back) => item = back,
+            }
+            let head = self.inner.head.load(Ordering::Relaxed);
+            let tail = self.inner.tail.load(Ordering::Acquire);
+            if head.wrapping_sub(tail) >= self.inner.capacity {
+                // pieno davvero: rubo il più vecchio, a meno che il consumatore non l'abbia
+                // appena preso lui; dopo un furto riuscito lo slot in head è libero
+                if let Some(old) = self.inner.steal(tail) {
+                    evicted = Some(old);
+                }
+            } else {
+                // c'è posto, ma il consumatore sta ancora copiando l'elemento dallo slot in head
+                hint::spin_loop();
+            }
+        }
+    }
+
+    // scrive quanti più elementi possibile, restituisce quanti ne sono entrati
+    pub fn push_slice(&mut self, items: &[T]) -> usize
+    where
+        T: Clone,
+    {
+        let mut written = 0;
+        for item in items {
+            if self.inner.push(item.clone()).is_err() {
+                break;
+            }
+            written += 1;
+        }
+        written
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.inner.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+}
+
+impl<T> Consumer<T> {
+
+    pub fn read(&mut self) -> Result<T, BufferError> {
+        self.inner.pop().ok_or(BufferError::Empty)
+    }
+
+    // legge fino a riempire out, restituisce quanti elementi sono stati letti
+    pub fn pop_slice(&mut self, out: &mut [T]) -> usize {
+        let mut read = 0;
+        for dst in out.iter_mut() {
+            match self.inner.pop() {
+                Some(item) => *dst = item,
+                None => break,
+            }
+            read += 1;
+        }
+        read
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_write_and_read() {
+        let (mut p, mut c) = SpscRingBuffer::new(2);
+        p.write(1).unwrap();
+        p.write(2).unwrap();
+        assert!(matches!(p.write(3), Err(BufferError::Full)));
+        assert_eq!(c.read().unwrap(), 1);
+        assert_eq!(c.read().unwrap(), 2);
+        assert!(matches!(c.read(), Err(BufferError::Empty)));
+    }
+
+    #[test]
+    fn test_overwrite_returns_oldest() {
+        let (mut p, mut c) = SpscRingBuffer::new(3);
+        for i in 1..=3 {
+            assert_eq!(p.overwrite(i), None);
+        }
+        assert_eq!(p.overwrite(4), Some(1));
+        assert_eq!(c.len(), 3);
+        assert_eq!(c.read().unwrap(), 2);
+        assert_eq!(c.read().unwrap(), 3);
+        assert_eq!(c.read().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_push_and_pop_slice() {
+        let (mut p, mut c) = SpscRingBuffer::new(4);
+        assert_eq!(p.push_slice(&[1, 2, 3, 4, 5, 6]), 4);
+        assert!(p.is_full());
+        let mut out = [0; 3];
+        assert_eq!(c.pop_slice(&mut out), 3);
+        assert_eq!(out, [1, 2, 3]);
+        assert_eq!(p.push_slice(&[7, 8]), 2);
+        let mut out = [0; 8];
+        assert_eq!(c.pop_slice(&mut out), 3);
+        assert_eq!(&out[..3], &[4, 7, 8]);
+        assert!(c.is_empty());
+    }
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_drop_remaining_elements() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut p, mut c) = SpscRingBuffer::new(4);
+        for _ in 0..3 {
+            p.write(Counted(drops.clone())).unwrap();
+        }
+        drop(c.read().unwrap());
+        drop(p);
+        assert_eq!(drops.load(Ordering::SeqCst), 1); // il consumatore tiene ancora vivo il buffer
+        drop(c);
+        assert_eq!(drops.load(Ordering::SeqCst), 3);
+    }
+
+    // stress test: il consumatore deve vedere tutti i valori, in ordine, senza perdite
+    #[test]
+    fn test_stress_write_read() {
+        const N: usize = if cfg!(miri) { 2_000 } else { 200_000 }; // miri è ~1000 volte più lento
+        let (mut p, mut c) = SpscRingBuffer::new(64);
+
+        let producer = thread::spawn(move || {
+            for i in 0..N {
+                while p.write(i).is_err() {
+                    thread::yield_now(); // buffer pieno, aspetto il consumatore
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < N {
+            match c.read() {
+                Ok(v) => {
+                    assert_eq!(v, expected);
+                    expected += 1;
+                }
+                Err(_) => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert!(c.is_empty());
+    }
+
+    // stress test con overwrite: i valori letti sono crescenti e ogni valore viene
+    // letto dal consumatore oppure scartato dal produttore, mai entrambe le cose
+    #[test]
+    fn test_stress_overwrite() {
+        const N: usize = if cfg!(miri) { 2_000 } else { 200_000 };
+        let (mut p, mut c) = SpscRingBuffer::new(8);
+
+        let producer = thread::spawn(move || {
+            let mut evicted = Vec::new();
+            for i in 0..N {
+                if let Some(old) = p.overwrite(i) {
+                    evicted.push(old);
+                }
+            }
+            evicted
+        });
+
+        let mut seen = Vec::new();
+        loop {
+            match c.read() {
+                Ok(v) => seen.push(v),
+                Err(_) if producer.is_finished() => break,
+                Err(_) => thread::yield_now(),
+            }
+        }
+        let evicted = producer.join().unwrap();
+        while let Ok(v) = c.read() {
+            seen.push(v);
+        }
+
+        assert!(seen.windows(2).all(|w| w[0] < w[1]));
+        assert!(evicted.windows(2).all(|w| w[0] < w[1]));
+        let mut all: Vec<usize> = seen.into_iter().chain(evicted).collect();
+        all.sort();
+        assert_eq!(all, (0..N).collect::<Vec<_>>());
+    }
+
+    // overwrite non deve scartare niente se la coda non è piena, nemmeno quando il consumatore
+    // ha già spostato tail ma non ha ancora liberato lo slot: qui quel momento è fermato a mano
+    #[test]
+    fn test_overwrite_waits_for_slot_being_read() {
+        let (mut p, mut c) = SpscRingBuffer::new(2);
+        p.write(0).unwrap();
+        p.write(1).unwrap();
+
+        // primi due passi di pop: CAS su tail e copia dello slot, senza lo store finale su seq
+        let inner = c.inner.clone();
+        inner.tail.compare_exchange(0, 1, Ordering::Relaxed, Ordering::Relaxed).unwrap();
+        let first = unsafe { (*inner.slots[0].value.get()).assume_init_read() };
+
+        let producer = thread::spawn(move || p.overwrite(2));
+        thread::sleep(Duration::from_millis(50));
+        inner.slots[0].seq.store(2, Ordering::Release);
+
+        assert_eq!(producer.join().unwrap(), None);
+        assert_eq!(first, 0);
+        assert_eq!(c.read().unwrap(), 1);
+        assert_eq!(c.read().unwrap(), 2);
+        assert!(matches!(c.read(), Err(BufferError::Empty)));
+    }
+
+    // stress test sui drop: con tipi non Copy non ci devono essere né leak né doppi drop
+    #[test]
+    fn test_stress_no_leaks() {
+        const N: usize = if cfg!(miri) { 1_000 } else { 50_000 };
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut p, mut c) = SpscRingBuffer::new(16);
+
+        let counter = drops.clone();
+        let producer = thread::spawn(move || {
+            for _ in 0..N {
+                drop(p.overwrite(Counted(counter.clone())));
+            }
+        });
+        let consumer = thread::spawn(move || {
+            for _ in 0..N {
+                drop(c.read());
+            }
+            c // lo restituisco così il buffer viene droppato solo alla fine
+        });
+
+        producer.join().unwrap();
+        drop(consumer.join().unwrap());
+        assert_eq!(drops.load(Ordering::SeqCst), N);
+    }
+}
@@ -0,0 +1,312 @@
+// statistiche su una finestra scorrevole: il CircularBuffer tiene gli ultimi N campioni,
+// WindowStats aggiorna somma, media, varianza, energia, min e max a ogni write/overwrite
+// senza riscandire la finestra.
+// - media e varianza con l'algoritmo di Welford, che si può anche "disfare" quando un campione esce
+// - min e max con due deque monotone: ogni campione entra ed esce al più una volta (O(1) ammortizzato)
+// Per i complessi i campioni sono trattati come coppie (re, im): la varianza diventa E|z - media|^2.
+
+use std::collections::VecDeque;
+
+use cnumbers::solution::ComplexNumber;
+
+use crate::{BufferError, CircularBuffer};
+
+// un campione della finestra visto come (parte reale, parte immaginaria)
+pub trait Sample: Copy {
+    type Output; // tipo restituito da sum e mean: f64 per i reali, ComplexNumber per i complessi
+    fn parts(self) -> (f64, f64);
+    fn output(re: f64, im: f64) -> Self::Output;
+}
+
+macro_rules! impl_real_sample {
+    ($($t:ty),*) => {
+        $(
+            impl Sample for $t {
+                type Output = f64;
+                fn parts(self) -> (f64, f64) {
+                    (self as f64, 0.0)
+                }
+                fn output(re: f64, _im: f64) -> f64 {
+                    re
+                }
+            }
+        )*
+    };
+}
+
+impl_real_sample!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+impl Sample for ComplexNumber {
+    type Output = ComplexNumber;
+    fn parts(self) -> (f64, f64) {
+        self.to_tuple()
+    }
+    fn output(re: f64, im: f64) -> ComplexNumber {
+        ComplexNumber::new(re, im)
+    }
+}
+
+pub struct WindowStats<T> {
+    window: CircularBuffer<T>,
+    seq: u64, // numero progressivo del prossimo campione, identifica i campioni nelle deque
+    oldest: u64, // numero progressivo del campione più vecchio ancora nella finestra
+    sum: (f64, f64),
+    mean: (f64, f64),
+    m2: f64, // somma dei quadrati degli scarti dalla media (Welford)
+    energy: f64, // somma di |x|^2
+    min: VecDeque<(u64, T)>, // valori crescenti dal fronte al fondo
+    max: VecDeque<(u64, T)>, // valori decrescenti dal fronte al fondo
+}
+
+impl<T: Sample + PartialOrd> WindowStats<T> {
+
+    pub fn new(capacity: usize) -> Self {
+        WindowStats {
+            window: CircularBuffer::new(capacity), // policy Fixed: gli scarti li gestiamo noi
+            seq: 0,
+            oldest: 0,
+            sum: (0.0, 0.0),
+            mean: (0.0, 0.0),
+            m2: 0.0,
+            energy: 0.0,
+            min: VecDeque::new(),
+            max: VecDeque::new(),
+        }
+    }
+
+    pub fn write(&mut self, item: T) -> Result<(), BufferError> {
+        self.window.write(item)?;
+        self.add(item);
+        Ok(())
+    }
+
+    // finestra piena: il campione più vecchio esce dalle statistiche e viene restituito
+    pub fn overwrite(&mut self, item: T) -> Option<T> {
+        if self.window.capacity() == 0 {
+            return None;
+        }
+        let evicted = if self.window.size() == self.window.capacity() { self.read() } else { None };
+        self.write(item).expect("window has a free slot");
+        evicted
+    }
+
+    // toglie il campione più vecchio dalla finestra
+    pub fn read(&mut self) -> Option<T> {
+        let item = self.window.read()?;
+        self.remove(item);
+        Some(item)
+    }
+
+    fn add(&mut self, item: T) {
+        let (re, im) = item.parts();
+        let n = self.window.size() as f64; // il campione è già nel buffer
+        self.sum.0 += re;
+        self.sum.1 += im;
+        self.energy += re * re + im * im;
+        let delta = (re - self.mean.0, im - self.mean.1);
+        self.mean.0 += delta.0 / n;
+        self.mean.1 += delta.1 / n;
+        self.m2 += delta.0 * (re - self.mean.0) + delta.1 * (im - self.mean.1);
+
+        // tolgo dal fondo i valori che non potranno più essere il minimo (o il massimo)
+        while self.min.back().is_some_and(|(_, v)| *v >= item) {
+            self.min.pop_back();
+        }
+        self.min.push_back((self.seq, item));
+        while self.max.back().is_some_and(|(_, v)| *v <= item) {
+            self.max.pop_back();
+        }
+        self.max.push_back((self.seq, item));
+        self.seq += 1;
+    }
+
+    fn remove(&mut self, item: T) {
+        let (re, im) = item.parts();
+        let n = self.window.size() as f64; // il campione è già uscito dal buffer
+        self.sum.0 -= re;
+        self.sum.1 -= im;
+        self.energy -= re * re + im * im;
+        if n == 0.0 {
+            // finestra vuota: riparto da zero invece di accumulare errori di arrotondamento
+            self.sum = (0.0, 0.0);
+            self.mean = (0.0, 0.0);
+            self.m2 = 0.0;
+            self.energy = 0.0;
+        } else {
+            // Welford al contrario: stessi passi di add, ricavando la media precedente
+            let delta = (re - self.mean.0, im - self.mean.1);
+            self.mean.0 -= delta.0 / n;
+            self.mean.1 -= delta.1 / n;
+            self.m2 -= delta.0 * (re - self.mean.0) + delta.1 * (im - self.mean.1);
+            self.m2 = self.m2.max(0.0);
+        }
+
+        if self.min.front().is_some_and(|(s, _)| *s == self.oldest) {
+            self.min.pop_front();
+        }
+        if self.max.front().is_some_and(|(s, _)| *s == self.oldest) {
+            self.max.pop_front();
+        }
+        self.oldest += 1;
+    }
+}
+
+impl<T: Sample> WindowStats<T> {
+
+    pub fn window(&self) -> &CircularBuffer<T> {
+        &self.window
+    }
+
+    pub fn len(&self) -> usize {
+        self.window.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.size() == 0
+    }
+
+    pub fn sum(&self) -> T::Output {
+        T::output(self.sum.0, self.sum.1)
+    }
+
+    pub fn mean(&self) -> Option<T::Output> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(T::output(self.mean.0, self.mean.1))
+    }
+
+    // varianza della popolazione (divisa per n)
+    pub fn variance(&self) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.m2 / self.len() as f64)
+    }
+
+    // varianza campionaria (divisa per n - 1)
+    pub fn sample_variance(&self) -> Option<f64> {
+        if self.len() < 2 {
+            return None;
+        }
+        Some(self.m2 / (self.len() - 1) as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    // somma di |x|^2 sulla finestra
+    pub fn energy(&self) -> f64 {
+        self.energy
+    }
+
+    pub fn min(&self) -> Option<T> {
+        self.min.front().map(|(_, v)| *v)
+    }
+
+    pub fn max(&self) -> Option<T> {
+        self.max.front().map(|(_, v)| *v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * (1.0 + a.abs().max(b.abs()))
+    }
+
+    // ricalcola tutto sulla finestra, per confronto
+    fn brute(values: &[f64]) -> (f64, f64, f64, f64, f64) {
+        let n = values.len() as f64;
+        let sum: f64 = values.iter().sum();
+        let mean = sum / n;
+        let var = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n;
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        (sum, mean, var, min, max)
+    }
+
+    #[test]
+    fn test_empty_window() {
+        let stats: WindowStats<i32> = WindowStats::new(3);
+        assert_eq!(stats.sum(), 0.0);
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.variance(), None);
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.max(), None);
+    }
+
+    #[test]
+    fn test_write_until_full() {
+        let mut stats = WindowStats::new(3);
+        stats.write(2).unwrap();
+        stats.write(4).unwrap();
+        stats.write(9).unwrap();
+        assert!(stats.write(1).is_err());
+        assert_eq!(stats.sum(), 15.0);
+        assert_eq!(stats.mean(), Some(5.0));
+        assert!(close(stats.variance().unwrap(), 26.0 / 3.0));
+        assert_eq!(stats.sample_variance(), Some(13.0));
+        assert_eq!(stats.min(), Some(2));
+        assert_eq!(stats.max(), Some(9));
+    }
+
+    #[test]
+    fn test_sliding_window_matches_brute_force() {
+        let samples: Vec<f64> = (0..500).map(|i| ((i * 37 % 101) as f64 - 50.0) * 0.75).collect();
+        let size = 7;
+        let mut stats = WindowStats::new(size);
+        for (i, &x) in samples.iter().enumerate() {
+            let evicted = stats.overwrite(x);
+            if i >= size {
+                assert_eq!(evicted, Some(samples[i - size]));
+            }
+            let start = (i + 1).saturating_sub(size);
+            let (sum, mean, var, min, max) = brute(&samples[start..=i]);
+            assert!(close(stats.sum(), sum));
+            assert!(close(stats.mean().unwrap(), mean));
+            assert!(close(stats.variance().unwrap(), var));
+            assert_eq!(stats.min(), Some(min));
+            assert_eq!(stats.max(), Some(max));
+        }
+    }
+
+    #[test]
+    fn test_monotonic_min_max_after_evictions() {
+        let mut stats = WindowStats::new(3);
+        for x in [5, 1, 3, 2, 8, 7, 6] {
+            stats.overwrite(x);
+        }
+        // finestra: 8, 7, 6
+        assert_eq!(stats.min(), Some(6));
+        assert_eq!(stats.max(), Some(8));
+        stats.read();
+        assert_eq!(stats.max(), Some(7));
+        stats.read();
+        stats.read();
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.mean(), None);
+        stats.write(4).unwrap();
+        assert_eq!(stats.mean(), Some(4.0));
+        assert_eq!(stats.variance(), Some(0.0));
+    }
+
+    #[test]
+    fn test_complex_mean_and_energy() {
+        let mut stats = WindowStats::new(2);
+        stats.write(ComplexNumber::new(1.0, 2.0)).unwrap();
+        stats.write(ComplexNumber::new(3.0, -2.0)).unwrap();
+        assert_eq!(stats.mean(), Some(ComplexNumber::new(2.0, 0.0)));
+        assert_eq!(stats.energy(), 5.0 + 13.0);
+        assert_eq!(stats.overwrite(ComplexNumber::new(0.0, 4.0)), Some(ComplexNumber::new(1.0, 2.0)));
+        assert_eq!(stats.sum(), ComplexNumber::new(3.0, 2.0));
+        assert_eq!(stats.mean(), Some(ComplexNumber::new(1.5, 1.0)));
+        assert!(close(stats.energy(), 13.0 + 16.0));
+        // E|z - media|^2 = (1.5^2 + 3^2 + 1.5^2 + 3^2) / 2
+        assert!(close(stats.variance().unwrap(), 11.25));
+        assert_eq!(stats.max(), Some(ComplexNumber::new(3.0, -2.0))); // ordinamento di Ord
+    }
+}
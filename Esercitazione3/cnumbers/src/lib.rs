@@ -86,6 +86,7 @@ pub mod solution { // modulo solution
         }
     }
 
+    #[allow(clippy::needless_lifetimes)] // lifetime espliciti lasciati apposta, vedi commento sotto
     impl<'a, 'b> Add<&'b ComplexNumber> for &'a ComplexNumber {
         // Add viene implementato per il tipo riferimento a ComplexNumber con ciclo di vita 'a
         // a cui viene addizionato un riferimento a ComplexNumber con ciclo di vita 'b
@@ -135,13 +136,7 @@ pub mod solution { // modulo solution
 
     impl PartialOrd<Self> for ComplexNumber { // supertrait di Ord (per il sorting)
         fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-            if self.real == other.real && self.imag == other.imag {
-                Some(Ordering::Equal)
-            } else if self.real > other.real || (self.real == other.real && self.imag > other.imag) {
-                Some(Ordering::Greater)
-            } else {
-                Some(Ordering::Less)
-            } // implemento l'ordinamento parziale
+            Some(self.cmp(other)) // l'ordinamento parziale coincide con quello totale di Ord
         }
     }
 
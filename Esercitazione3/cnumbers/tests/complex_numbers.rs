@@ -1,3 +1,5 @@
+#![allow(clippy::op_ref, clippy::assertions_on_constants)] // i test usano apposta i riferimenti e assert costanti
+
 use cnumbers::solution::{ComplexNumber, ComplexNumberError};
 
 // for this execise see https://doc.rust-lang.org/beta/std/primitive.f64.html
@@ -204,7 +206,7 @@ pub fn test_sorting() {
     let a = ComplexNumber::new(1.0, 2.0);
     let b = ComplexNumber::new(2.0, 4.0);
     let c = ComplexNumber::new(3.0, 6.0);
-    let mut v = vec![c, b, a];
+    let mut v = [c, b, a];
 
     v.sort();
 
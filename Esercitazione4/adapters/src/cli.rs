@@ -0,0 +1,603 @@
+// a grep command built on GrepIter:
+//
+//   adapters [OPTIONS] PATTERN [PATH...]
+//
+// the walker is a walkdir iterator pruned by --include/--exclude globs and by the
+// .gitignore/.ignore files found on the way, GrepIter does the matching and this module
+// only formats the results. Exit codes follow grep: 0 match, 1 no match, 2 error.
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::Regex;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::parallel::{self, ParallelOptions};
+use crate::{GrepError, GrepIter, GrepOptions, LineKind, Match};
+
+const USAGE: &str = "\
+usage: adapters [OPTIONS] PATTERN [PATH...]
+
+  -i, --ignore-case         case insensitive match
+  -F, --fixed-strings       PATTERN is a literal string
+  -v, --invert-match        select non-matching lines
+  -A, --after-context N     print N lines after each match
+  -B, --before-context N    print N lines before each match
+  -C, --context N           print N lines before and after each match
+  -c, --count               print the number of matching lines per file
+  -l, --files-with-matches  print only the names of files with matches
+      --json                print one JSON object per line
+      --color WHEN          highlight matches: auto, always, never
+      --include GLOB        search only files matching GLOB (repeatable)
+      --exclude GLOB        skip files and directories matching GLOB (repeatable)
+      --max-depth N         descend at most N directories
+  -L, --follow              follow symbolic links
+      --no-ignore           don't read .gitignore and .ignore files
+  -a, --text                search binary files too
+  -j, --threads N           search with N threads (default 1)
+      --unordered           with -j, print results as soon as they are found
+      --bench N             time the search with 1..=N threads instead of printing it
+  -h, --help                print this help";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    Lines,
+    Count,
+    FilesWithMatches,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub pattern: String,
+    pub paths: Vec<PathBuf>,
+    pub grep: GrepOptions,
+    pub mode: OutputMode,
+    pub json: bool,
+    pub color: ColorChoice,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub max_depth: Option<usize>,
+    pub follow_links: bool,
+    pub no_ignore: bool,
+    pub threads: usize,
+    pub unordered: bool,
+    pub bench: Option<usize>,
+    pub help: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            pattern: String::new(),
+            paths: vec![],
+            grep: GrepOptions { skip_binary: true, ..Default::default() },
+            mode: OutputMode::Lines,
+            json: false,
+            color: ColorChoice::Auto,
+            include: vec![],
+            exclude: vec![],
+            max_depth: None,
+            follow_links: false,
+            no_ignore: false,
+            threads: 1,
+            unordered: false,
+            bench: None,
+            help: false,
+        }
+    }
+}
+
+fn number(flag: &str, value: Option<String>) -> Result<usize, String> {
+    let value = value.ok_or(format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("{}: invalid number '{}'", flag, value))
+}
+
+// -iv is -i -v and -C3 is -C 3, the rest is left as it is
+fn expand_short(arg: String) -> Vec<String> {
+    let is_cluster = arg.len() > 2 && arg.starts_with('-') && !arg.starts_with("--");
+    if !is_cluster {
+        return vec![arg];
+    }
+    let rest = &arg[1..];
+    if rest.chars().all(|c| "iFvclLah".contains(c)) {
+        return rest.chars().map(|c| format!("-{}", c)).collect();
+    }
+    if let Some(n) = rest.strip_prefix(['A', 'B', 'C', 'j']).filter(|n| n.chars().all(|c| c.is_ascii_digit())) {
+        return vec![arg[..2].to_string(), n.to_string()];
+    }
+    vec![arg]
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut positional = vec![];
+    let mut args = args.into_iter().flat_map(expand_short);
+
+    while let Some(arg) = args.next() {
+        // --flag=value is the same as --flag value
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if f.starts_with("--") => (f.to_string(), Some(v.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline.clone().or_else(|| args.next());
+
+        match flag.as_str() {
+            "-i" | "--ignore-case" => config.grep.ignore_case = true,
+            "-F" | "--fixed-strings" => config.grep.fixed_strings = true,
+            "-v" | "--invert-match" => config.grep.invert_match = true,
+            "-A" | "--after-context" => config.grep.after_context = number(&flag, value())?,
+            "-B" | "--before-context" => config.grep.before_context = number(&flag, value())?,
+            "-C" | "--context" => config.grep = config.grep.clone().context(number(&flag, value())?),
+            "-c" | "--count" => config.mode = OutputMode::Count,
+            "-l" | "--files-with-matches" => config.mode = OutputMode::FilesWithMatches,
+            "--json" => config.json = true,
+            "--color" | "--colour" => {
+                config.color = match value().as_deref() {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    other => return Err(format!("--color: invalid value '{}'", other.unwrap_or(""))),
+                }
+            }
+            "--include" => config.include.push(value().ok_or("--include needs a value")?),
+            "--exclude" => config.exclude.push(value().ok_or("--exclude needs a value")?),
+            "--max-depth" => config.max_depth = Some(number(&flag, value())?),
+            "-L" | "--follow" => config.follow_links = true,
+            "--no-ignore" => config.no_ignore = true,
+            "-a" | "--text" => config.grep.skip_binary = false,
+            "-j" | "--threads" => config.threads = number(&flag, value())?,
+            "--unordered" => config.unordered = true,
+            "--bench" => config.bench = Some(number(&flag, value())?),
+            "-h" | "--help" => config.help = true,
+            "--" => positional.extend(args.by_ref()),
+            f if f.starts_with('-') && f.len() > 1 => return Err(format!("unknown option '{}'", f)),
+            _ => positional.push(arg),
+        }
+    }
+
+    if config.help {
+        return Ok(config);
+    }
+    let mut positional = positional.into_iter();
+    config.pattern = positional.next().ok_or("missing PATTERN")?;
+    config.paths = positional.map(PathBuf::from).collect();
+    if config.paths.is_empty() {
+        config.paths.push(PathBuf::from("."));
+    }
+    Ok(config)
+}
+
+fn glob_set(globs: &[String]) -> Result<Option<GlobSet>, String> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for g in globs {
+        builder.add(Glob::new(g).map_err(|e| e.to_string())?);
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+// the .gitignore/.ignore files of the directories between the root and the current entry.
+// walkdir visits a directory right before its children, so a stack is enough: the levels
+// that are not ancestors of the current entry are popped
+#[derive(Default)]
+struct IgnoreStack {
+    levels: Vec<(PathBuf, Gitignore)>,
+}
+
+impl IgnoreStack {
+    fn is_ignored(&mut self, entry: &DirEntry) -> bool {
+        let path = entry.path();
+        while self.levels.last().is_some_and(|(dir, _)| !path.starts_with(dir) || path == dir) {
+            self.levels.pop();
+        }
+        let is_dir = entry.file_type().is_dir();
+        if is_dir && entry.depth() > 0 && entry.file_name() == ".git" {
+            return true;
+        }
+        // the deepest file decides, a whitelist (!pattern) wins over the parent directories
+        for (_, rules) in self.levels.iter().rev() {
+            let m = rules.matched(path, is_dir);
+            if m.is_ignore() {
+                return true;
+            }
+            if m.is_whitelist() {
+                break;
+            }
+        }
+        if is_dir {
+            let mut builder = GitignoreBuilder::new(path);
+            let mut found = false;
+            for name in [".gitignore", ".ignore"] {
+                let file = path.join(name);
+                if file.is_file() {
+                    found |= builder.add(file).is_none();
+                }
+            }
+            if found && let Ok(rules) = builder.build() {
+                self.levels.push((path.to_path_buf(), rules));
+            }
+        }
+        false
+    }
+}
+
+// matches the glob against the file name and against the path relative to the root
+fn glob_matches(set: &GlobSet, entry: &DirEntry, root: &Path) -> bool {
+    let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+    set.is_match(entry.file_name()) || set.is_match(relative)
+}
+
+struct Printer<'a, W: Write> {
+    out: &'a mut W,
+    regex: &'a Regex,
+    config: &'a Config,
+    color: bool,
+    last: Option<(String, usize)>, // last line printed, a line with many hits is printed once
+    counts: HashMap<String, usize>, // matching lines of the files with at least one
+}
+
+const RESET: &str = "\x1b[0m";
+const FILE_COLOR: &str = "\x1b[35m";
+const LINE_COLOR: &str = "\x1b[32m";
+const MATCH_COLOR: &str = "\x1b[1;31m";
+
+impl<W: Write> Printer<'_, W> {
+    fn print(&mut self, m: &Match) -> io::Result<()> {
+        let key = (m.file.clone(), m.line);
+        if self.last.as_ref() == Some(&key) {
+            return Ok(());
+        }
+        let gap = match &self.last {
+            Some((file, line)) => file != &m.file || line + 1 != m.line,
+            None => false,
+        };
+        self.last = Some(key);
+
+        let count = match m.kind {
+            LineKind::Match => {
+                let n = self.counts.entry(m.file.clone()).or_default();
+                *n += 1;
+                *n
+            }
+            LineKind::Context => 0,
+        };
+
+        match self.config.mode {
+            OutputMode::Count => Ok(()),
+            OutputMode::FilesWithMatches => {
+                if count == 1 {
+                    if self.config.json {
+                        writeln!(self.out, "{}", serde_json::json!({ "file": m.file }))
+                    } else {
+                        writeln!(self.out, "{}", self.paint(&m.file, FILE_COLOR))
+                    }
+                } else {
+                    Ok(())
+                }
+            }
+            OutputMode::Lines if self.config.json => {
+                let kind = if m.kind == LineKind::Match { "match" } else { "context" };
+                let value = serde_json::json!({
+                    "type": kind, "file": m.file, "line": m.line, "column": m.column, "text": m.text,
+                });
+                writeln!(self.out, "{}", value)
+            }
+            OutputMode::Lines => {
+                let context = self.config.grep.before_context + self.config.grep.after_context > 0;
+                if context && gap {
+                    writeln!(self.out, "--")?;
+                }
+                let sep = if m.kind == LineKind::Match { ':' } else { '-' };
+                let text = if m.kind == LineKind::Match && !self.config.grep.invert_match {
+                    self.highlight(&m.text)
+                } else {
+                    m.text.clone()
+                };
+                writeln!(
+                    self.out,
+                    "{}{}{}{}{}",
+                    self.paint(&m.file, FILE_COLOR),
+                    sep,
+                    self.paint(&m.line.to_string(), LINE_COLOR),
+                    sep,
+                    text
+                )
+            }
+        }
+    }
+
+    // scanned: every file searched, in walk order, so the ones without matches get a 0
+    fn finish(&mut self, scanned: &[String]) -> io::Result<()> {
+        if self.config.mode != OutputMode::Count {
+            return Ok(());
+        }
+        for file in scanned {
+            let n = self.counts.get(file).copied().unwrap_or(0);
+            if self.config.json {
+                writeln!(self.out, "{}", serde_json::json!({ "file": file, "count": n }))?;
+            } else {
+                writeln!(self.out, "{}:{}", self.paint(file, FILE_COLOR), n)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn paint(&self, s: &str, color: &str) -> String {
+        if self.color { format!("{}{}{}", color, s, RESET) } else { s.to_string() }
+    }
+
+    fn highlight(&self, text: &str) -> String {
+        if !self.color {
+            return text.to_string();
+        }
+        self.regex.replace_all(text, |c: &regex::Captures| self.paint(&c[0], MATCH_COLOR)).into_owned()
+    }
+}
+
+// runs the command, returns the exit code
+pub fn run<I, W, E>(args: I, out: &mut W, err: &mut E) -> i32
+where
+    I: IntoIterator<Item = String>,
+    W: Write,
+    E: Write,
+{
+    let config = match parse_args(args) {
+        Ok(c) => c,
+        Err(e) => {
+            let _ = writeln!(err, "adapters: {}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    if config.help {
+        let _ = writeln!(out, "{}", USAGE);
+        return 0;
+    }
+    match search(&config, out, err) {
+        Ok(code) => code,
+        Err(e) => {
+            let _ = writeln!(err, "adapters: {}", e);
+            2
+        }
+    }
+}
+
+// the walker of one root, pruned by the globs and by the ignore files
+fn walk<'a>(
+    config: &'a Config,
+    root: &'a Path,
+    include: &'a Option<GlobSet>,
+    exclude: &'a Option<GlobSet>,
+) -> impl Iterator<Item = walkdir::Result<DirEntry>> + Send + 'a {
+    let mut walk = WalkDir::new(root).follow_links(config.follow_links).sort_by_file_name();
+    if let Some(depth) = config.max_depth {
+        walk = walk.max_depth(depth);
+    }
+    let mut ignores = IgnoreStack::default();
+    walk.into_iter().filter_entry(move |e| {
+        if e.depth() == 0 {
+            if !config.no_ignore {
+                ignores.is_ignored(e); // loads the ignore files of the root
+            }
+            return true; // the paths on the command line are always searched
+        }
+        if exclude.as_ref().is_some_and(|set| glob_matches(set, e, root)) {
+            return false;
+        }
+        if !config.no_ignore && ignores.is_ignored(e) {
+            return false;
+        }
+        let is_file = !e.file_type().is_dir();
+        !(is_file && include.as_ref().is_some_and(|set| !glob_matches(set, e, root)))
+    })
+}
+
+fn search<W: Write, E: Write>(config: &Config, out: &mut W, err: &mut E) -> Result<i32, String> {
+    let regex = config.grep.build_regex(&config.pattern).map_err(|e| e.to_string())?;
+    let include = glob_set(&config.include)?;
+    let exclude = glob_set(&config.exclude)?;
+    let color = match config.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !config.json && io::stdout().is_terminal(),
+    };
+    let mut grep = config.grep.clone();
+    if config.mode == OutputMode::FilesWithMatches {
+        grep.max_count = Some(1); // the first hit is enough, the rest of the file is not read
+    }
+    // the files as GrepIter and par_grep name them, for the counts of -c
+    let scanned = Mutex::new(Vec::new());
+    let record = |e: &walkdir::Result<DirEntry>| {
+        if config.mode == OutputMode::Count
+            && let Ok(e) = e
+            && e.file_type().is_file()
+        {
+            scanned.lock().unwrap().push(e.path().display().to_string());
+        }
+    };
+    // all the roots in a single walk, one after the other
+    let walker = || config.paths.iter().flat_map(|root| walk(config, root, &include, &exclude)).inspect(record);
+
+    if let Some(max_threads) = config.bench {
+        parallel::benchmark(walker, &regex, &grep, max_threads, out).map_err(|e| e.to_string())?;
+        return Ok(0);
+    }
+
+    let mut printer = Printer { out, regex: &regex, config, color, last: None, counts: HashMap::new() };
+    let mut found = false;
+    let mut errors = false;
+    let mut closed = false;
+
+    let mut handle = |result: Result<Match, GrepError>| {
+        match result {
+            Ok(m) => {
+                found |= m.kind == LineKind::Match;
+                // a closed pipe (e.g. | head) is not an error worth reporting: just stop
+                closed = printer.print(&m).is_err();
+            }
+            Err(e) => {
+                errors = true;
+                let _ = writeln!(err, "adapters: {}", e);
+            }
+        }
+        !closed
+    };
+
+    if config.threads > 1 {
+        let par = ParallelOptions { threads: config.threads, ordered: !config.unordered, ..Default::default() };
+        parallel::par_grep(walker(), &regex, &grep, par, handle);
+    } else {
+        for result in GrepIter::new(walker(), regex.clone(), grep.clone()) {
+            if !handle(result) {
+                break;
+            }
+        }
+    }
+    if !closed {
+        let _ = printer.finish(&scanned.lock().unwrap());
+    }
+
+    Ok(if errors { 2 } else if found { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDir;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    // runs the command in the test directory, returns exit code and stdout
+    fn grep(dir: &TestDir, cmd: &str) -> (i32, String) {
+        let mut a = args(cmd);
+        a.push(dir.0.display().to_string());
+        let mut out = vec![];
+        let mut err = vec![];
+        let code = run(a, &mut out, &mut err);
+        let root = format!("{}/", dir.0.display());
+        (code, String::from_utf8(out).unwrap().replace(&root, ""))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let c = parse_args(args("-iv -C 2 --color=never foo src lib")).unwrap();
+        assert!(c.grep.ignore_case && c.grep.invert_match);
+        assert_eq!((c.grep.before_context, c.grep.after_context), (2, 2));
+        assert_eq!(c.color, ColorChoice::Never);
+        assert_eq!(c.pattern, "foo");
+        assert_eq!(c.paths, vec![PathBuf::from("src"), PathBuf::from("lib")]);
+
+        let c = parse_args(args("foo -ic")).unwrap();
+        assert!(c.grep.ignore_case);
+        assert_eq!(c.mode, OutputMode::Count);
+        assert_eq!(c.pattern, "foo");
+        assert_eq!(c.paths, vec![PathBuf::from(".")]);
+
+        let c = parse_args(args("-B1 foo")).unwrap();
+        assert_eq!(c.grep.before_context, 1);
+        assert!(parse_args(args("-A x foo")).is_err());
+        assert!(parse_args(args("--bogus foo")).is_err());
+        assert!(parse_args(args("-i")).is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let dir = TestDir::new("cli_exit", &[("a.txt", "hello\n")]);
+        assert_eq!(grep(&dir, "hello").0, 0);
+        assert_eq!(grep(&dir, "bye").0, 1);
+        assert_eq!(grep(&dir, "(").0, 2);
+        let mut err = vec![];
+        assert_eq!(run(args("hello /does/not/exist"), &mut vec![], &mut err), 2);
+        assert!(!err.is_empty());
+    }
+
+    #[test]
+    fn test_lines_and_context() {
+        let dir = TestDir::new("cli_lines", &[("a.txt", "x\nfoo foo\ny\nz\nfoo\n")]);
+        let (_, out) = grep(&dir, "foo --color never");
+        assert_eq!(out, "a.txt:2:foo foo\na.txt:5:foo\n");
+        let (_, out) = grep(&dir, "-A 1 foo --color never");
+        assert_eq!(out, "a.txt:2:foo foo\na.txt-3-y\n--\na.txt:5:foo\n");
+        let (_, out) = grep(&dir, "--color always o+");
+        assert!(out.contains("f\x1b[1;31moo\x1b[0m f\x1b[1;31moo\x1b[0m"));
+    }
+
+    #[test]
+    fn test_count_and_files_with_matches() {
+        let dir = TestDir::new("cli_count", &[("a.txt", "foo foo\nfoo\n"), ("b.txt", "bar\n"), ("c.txt", "foo\n")]);
+        assert_eq!(grep(&dir, "-c foo").1, "a.txt:2\nb.txt:0\nc.txt:1\n");
+        assert_eq!(grep(&dir, "-c -j 3 --unordered foo").1, "a.txt:2\nb.txt:0\nc.txt:1\n");
+        assert_eq!(grep(&dir, "-c nothing").1, "a.txt:0\nb.txt:0\nc.txt:0\n");
+        assert_eq!(grep(&dir, "-l foo").1, "a.txt\nc.txt\n");
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let dir = TestDir::new("cli_json", &[("a.txt", "say \"hi\"\n")]);
+        let (_, out) = grep(&dir, "--json hi");
+        let value: serde_json::Value = serde_json::from_str(out.lines().next().unwrap()).unwrap();
+        assert_eq!(value["type"], "match");
+        assert_eq!(value["line"], 1);
+        assert_eq!(value["column"], 6);
+        assert_eq!(value["text"], "say \"hi\"");
+    }
+
+    #[test]
+    fn test_globs_depth_and_binary() {
+        let dir = TestDir::new("cli_globs", &[
+            ("a.rs", "foo\n"),
+            ("a.txt", "foo\n"),
+            ("sub/b.rs", "foo\n"),
+            ("sub/deep/c.rs", "foo\n"),
+            ("target/d.rs", "foo\n"),
+            ("e.bin", "foo\0\n"),
+        ]);
+        assert_eq!(grep(&dir, "-l --include *.rs --exclude target foo").1, "a.rs\nsub/b.rs\nsub/deep/c.rs\n");
+        assert_eq!(grep(&dir, "-l --include *.rs --max-depth 2 foo").1, "a.rs\nsub/b.rs\ntarget/d.rs\n");
+        assert_eq!(grep(&dir, "-l --include *.bin foo").0, 1);
+        assert_eq!(grep(&dir, "-l -a --include *.bin foo").1, "e.bin\n");
+    }
+
+    #[test]
+    fn test_ignore_files() {
+        let dir = TestDir::new("cli_ignore", &[
+            (".gitignore", "target/\n*.log\n"),
+            ("a.txt", "foo\n"),
+            ("app.log", "foo\n"),
+            ("target/x.txt", "foo\n"),
+            ("sub/.ignore", "*.txt\n!keep.txt\n"),
+            ("sub/drop.txt", "foo\n"),
+            ("sub/keep.txt", "foo\n"),
+            (".git/config", "foo\n"),
+        ]);
+        assert_eq!(grep(&dir, "-l foo").1, "a.txt\nsub/keep.txt\n");
+        let (_, out) = grep(&dir, "-l --no-ignore foo");
+        assert_eq!(out.lines().count(), 6); // .git included
+    }
+
+    #[test]
+    fn test_threads_same_output() {
+        let files: Vec<(String, String)> = (0..20).map(|i| (format!("f{:02}.txt", i), format!("a\nfoo {}\nb\n", i))).collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), c.as_str())).collect();
+        let dir = TestDir::new("cli_threads", &files);
+        let sequential = grep(&dir, "-C1 --color never foo");
+        assert_eq!(grep(&dir, "-j4 -C1 --color never foo"), sequential);
+        let (code, out) = grep(&dir, "-j 4 --unordered -c foo");
+        assert_eq!(code, 0);
+        assert_eq!(out.lines().count(), 20);
+    }
+}
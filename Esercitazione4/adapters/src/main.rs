@@ -1,11 +1,9 @@
-
-
 // to warm up: the define step by step an adapter for filtering even numbers
 
 pub mod simple_even_iter {
     // (1) let start with a simple iterator adapter for just one type, "i32"
     // see the adapter pattern example in the pdf "Adapter Pattern..."
-    struct EvenIter<I>
+    pub struct EvenIter<I>
     where
         I: Iterator<Item=i32>
     {
@@ -18,7 +16,7 @@ pub mod simple_even_iter {
     where
         I: Iterator<Item=i32>
     {
-        fn new(iter: I) -> Self {
+        pub fn new(iter: I) -> Self {
             EvenIter { inner: iter }
         }
     }
@@ -49,7 +47,7 @@ pub mod simple_even_iter {
 
 
     // (2) now let's add the adapter to all Iterator<Item=i32> (advanced)
-    trait AddEvenIter: Iterator<Item = i32> + Sized
+    pub trait AddEvenIter: Iterator<Item = i32> + Sized
     {
         // add even() to anyone implementing this trait
         // usage: v.into_iter().even() ....
@@ -86,7 +84,7 @@ pub mod even_iter {
     // the generic parameters I and U are already defined for you in the struct deinition
     // (5) write in a comment in plain english the meaning of the generic parameters
     // and their constraints
-    struct EvenIter<I, U>
+    pub struct EvenIter<I, U>
     where
         I: Iterator<Item=U> // generic parameters: I is an iterator over U, U is a number type
     {
@@ -104,43 +102,409 @@ pub mod even_iter {
             // (5) implement the next() method
             // hint: use the num::Integer trait to check if a number is even
             // hint: use the Copy trait to copy the value of U
-            self.iter.next().and_then(|line| {
-                if line.is_even() {
-                    Some(line)
-                } else {
-                    None
-                }
-            })
+            // find skips the odd numbers, returning None only when the inner iterator ends
+            self.iter.find(|x| x.is_even())
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, self.iter.size_hint().1) // any number of items may be skipped
+        }
+    }
+
+    impl<I, U> DoubleEndedIterator for EvenIter<I, U>
+    where
+        U: num::Integer + Copy,
+        I: DoubleEndedIterator<Item=U>
+    {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.iter.rfind(|x| x.is_even())
+        }
+    }
+
+    // the same pattern for the other filters: only the test on the number changes
+
+    pub struct OddIter<I, U>
+    where
+        I: Iterator<Item=U>
+    {
+        iter: I
+    }
+
+    impl<I, U> Iterator for OddIter<I, U>
+    where
+        U: num::Integer + Copy,
+        I: Iterator<Item=U>
+    {
+        type Item = U;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.iter.find(|x| x.is_odd())
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, self.iter.size_hint().1)
+        }
+    }
+
+    impl<I, U> DoubleEndedIterator for OddIter<I, U>
+    where
+        U: num::Integer + Copy,
+        I: DoubleEndedIterator<Item=U>
+    {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.iter.rfind(|x| x.is_odd())
+        }
+    }
+
+    pub struct MultiplesOf<I, U>
+    where
+        I: Iterator<Item=U>
+    {
+        iter: I,
+        k: U, // with k == 0 only 0 is a multiple
+    }
+
+    impl<I, U> MultiplesOf<I, U>
+    where
+        U: num::Integer + Copy,
+        I: Iterator<Item=U>
+    {
+        fn accepts(k: U, x: U) -> bool {
+            if k.is_zero() { x.is_zero() } else { x.is_multiple_of(&k) }
+        }
+    }
+
+    impl<I, U> Iterator for MultiplesOf<I, U>
+    where
+        U: num::Integer + Copy,
+        I: Iterator<Item=U>
+    {
+        type Item = U;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let k = self.k;
+            self.iter.find(|&x| Self::accepts(k, x))
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, self.iter.size_hint().1)
+        }
+    }
+
+    impl<I, U> DoubleEndedIterator for MultiplesOf<I, U>
+    where
+        U: num::Integer + Copy,
+        I: DoubleEndedIterator<Item=U>
+    {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            let k = self.k;
+            self.iter.rfind(|&x| Self::accepts(k, x))
+        }
+    }
+
+    // trial division up to sqrt(n), negative numbers, 0 and 1 are not primes
+    pub fn is_prime<U: num::Integer + Copy>(n: U) -> bool {
+        let two = U::one() + U::one();
+        if n < two {
+            return false;
+        }
+        let mut d = two;
+        while d <= n / d { // d * d <= n without overflow
+            if n.is_multiple_of(&d) {
+                return false;
+            }
+            d = d + U::one();
+        }
+        true
+    }
+
+    pub struct Primes<I, U>
+    where
+        I: Iterator<Item=U>
+    {
+        iter: I
+    }
+
+    impl<I, U> Iterator for Primes<I, U>
+    where
+        U: num::Integer + Copy,
+        I: Iterator<Item=U>
+    {
+        type Item = U;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.iter.find(|&x| is_prime(x))
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, self.iter.size_hint().1)
+        }
+    }
+
+    impl<I, U> DoubleEndedIterator for Primes<I, U>
+    where
+        U: num::Integer + Copy,
+        I: DoubleEndedIterator<Item=U>
+    {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.iter.rfind(|&x| is_prime(x))
+        }
+    }
+
+    // the partial sums x0, x0 + x1, x0 + x1 + x2, ...
+    // one item for each item of the inner iterator, so the size_hint is the same;
+    // it can't go backwards: the last sum needs all the items before it
+    pub struct RunningSum<I, U>
+    where
+        I: Iterator<Item=U>
+    {
+        iter: I,
+        sum: U,
+    }
+
+    impl<I, U> Iterator for RunningSum<I, U>
+    where
+        U: num::Integer + Copy,
+        I: Iterator<Item=U>
+    {
+        type Item = U;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let x = self.iter.next()?;
+            self.sum = self.sum + x;
+            Some(self.sum)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.iter.size_hint()
+        }
+    }
+
+    impl<I, U> ExactSizeIterator for RunningSum<I, U>
+    where
+        U: num::Integer + Copy,
+        I: ExactSizeIterator<Item=U>
+    {
+    }
+
+    // gcd of each pair of consecutive items: (x0, x1), (x1, x2), ...
+    // n items give n - 1 gcds
+    pub struct PairwiseGcd<I, U>
+    where
+        I: Iterator<Item=U>
+    {
+        iter: I,
+        prev: Option<U>, // None only before the first item
+    }
+
+    impl<I, U> Iterator for PairwiseGcd<I, U>
+    where
+        U: num::Integer + Copy,
+        I: Iterator<Item=U>
+    {
+        type Item = U;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let prev = match self.prev {
+                Some(prev) => prev,
+                None => self.iter.next()?,
+            };
+            let x = self.iter.next()?;
+            self.prev = Some(x);
+            Some(prev.gcd(&x))
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            let (low, high) = self.iter.size_hint();
+            match self.prev {
+                Some(_) => (low, high),
+                None => (low.saturating_sub(1), high.map(|h| h.saturating_sub(1))),
+            }
         }
     }
 
+    impl<I, U> ExactSizeIterator for PairwiseGcd<I, U>
+    where
+        U: num::Integer + Copy,
+        I: ExactSizeIterator<Item=U>
+    {
+    }
+
+    // all the adapters for any iterator over integers
+    // usage: (1..100u64).primes().running_sum() ...
+    pub trait NumAdapters<U>: Iterator<Item = U> + Sized
+    where
+        U: num::Integer + Copy
+    {
+        fn even(self) -> EvenIter<Self, U> {
+            EvenIter { iter: self }
+        }
+
+        fn odd(self) -> OddIter<Self, U> {
+            OddIter { iter: self }
+        }
+
+        fn multiples_of(self, k: U) -> MultiplesOf<Self, U> {
+            MultiplesOf { iter: self, k }
+        }
+
+        fn primes(self) -> Primes<Self, U> {
+            Primes { iter: self }
+        }
+
+        fn running_sum(self) -> RunningSum<Self, U> {
+            RunningSum { iter: self, sum: U::zero() }
+        }
+
+        fn pairwise_gcd(self) -> PairwiseGcd<Self, U> {
+            PairwiseGcd { iter: self, prev: None }
+        }
+    }
+
+    impl<I, U> NumAdapters<U> for I
+    where
+        U: num::Integer + Copy,
+        I: Iterator<Item=U>
+    {
+    }
 
     // (6) once implemented, the test will compile and pass
     #[test]
     fn test_even_iter() {
-        let mut v: Vec<u64> = vec![1, 2, 3, 4, 5];
-        let mut it = EvenIter { iter: v.into_iter() };
+        let v: Vec<u64> = vec![1, 2, 3, 4, 5];
+        let it = EvenIter { iter: v.into_iter() };
         for i in it {
             println!("i: {}", i);
         }
     }
 
+    #[test]
+    fn test_even_does_not_stop_at_odd() {
+        let v: Vec<u64> = vec![1, 2, 3, 4, 5, 6];
+        assert_eq!(v.into_iter().even().collect::<Vec<_>>(), vec![2, 4, 6]);
+        assert_eq!((-5i8..5).odd().collect::<Vec<_>>(), vec![-5, -3, -1, 1, 3]);
+    }
+
+    #[test]
+    fn test_filters_backwards() {
+        assert_eq!((1..=20u32).even().rev().take(3).collect::<Vec<_>>(), vec![20, 18, 16]);
+        let mut it = (1..=30i64).multiples_of(7);
+        assert_eq!(it.next(), Some(7));
+        assert_eq!(it.next_back(), Some(28));
+        assert_eq!(it.collect::<Vec<_>>(), vec![14, 21]);
+        assert_eq!((-3..3).multiples_of(0).collect::<Vec<_>>(), vec![0]);
+        assert_eq!((0..30usize).primes().rev().collect::<Vec<_>>(), vec![29, 23, 19, 17, 13, 11, 7, 5, 3, 2]);
+    }
+
+    #[test]
+    fn test_is_prime_edges() {
+        assert!(!is_prime(-7i32));
+        assert!(!is_prime(1u8));
+        assert!(is_prime(251u8)); // largest u8 prime, d * d would overflow
+        assert!(is_prime(2i64));
+        assert!(!is_prime(u32::MAX));
+    }
+
+    #[test]
+    fn test_running_sum() {
+        let sums = vec![3, 1, 4, 1, 5].into_iter().running_sum();
+        assert_eq!(sums.len(), 5);
+        assert_eq!(sums.collect::<Vec<i32>>(), vec![3, 4, 8, 9, 14]);
+        assert_eq!((1..=10u64).even().running_sum().last(), Some(30));
+    }
+
+    #[test]
+    fn test_pairwise_gcd() {
+        let mut it = vec![12u32, 18, 27, 7].into_iter().pairwise_gcd();
+        assert_eq!(it.len(), 3);
+        assert_eq!(it.next(), Some(6));
+        assert_eq!(it.len(), 2);
+        assert_eq!(it.collect::<Vec<_>>(), vec![9, 1]);
+        assert_eq!(vec![5i16].into_iter().pairwise_gcd().next(), None);
+        assert_eq!(Vec::<i16>::new().into_iter().pairwise_gcd().size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_size_hint_of_filters() {
+        assert_eq!((0..10u8).primes().size_hint(), (0, Some(10)));
+        assert_eq!((0..).odd().size_hint(), (0, None));
+    }
 }
 
 // finally let's implement the grep command
 // (1) install the "walkdir" crate for walking over directories using an iterator
 // install also the "regex" crate for regular expressions
 
-use walkdir;
-use regex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use regex::{Regex, RegexBuilder};
 
 // (2) define the match result
-struct Match {
-    file: String,
-    line: usize,
-    text: String
+// a Match is either a line hit by the pattern (one Match per hit, with its column)
+// or a context line printed around the hits because of -A/-B/-C
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub file: String,
+    pub line: usize,   // 1-based line number
+    pub column: usize, // 1-based column of the hit, counted in chars (0 for context and inverted lines)
+    pub text: String,  // the whole line, without the line terminator
+    pub kind: LineKind,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Match,
+    Context,
+}
+
+// options of the grep adapter, the same meaning of the grep flags
+#[derive(Debug, Clone, Default)]
+pub struct GrepOptions {
+    pub ignore_case: bool,    // -i
+    pub fixed_strings: bool,  // -F: the pattern is a literal string, not a regex
+    pub invert_match: bool,   // -v: yield the lines NOT matching the pattern
+    pub before_context: usize, // -B
+    pub after_context: usize,  // -A
+    pub skip_binary: bool,    // skip files with a NUL byte in the first block, like grep -I
+    pub max_count: Option<usize>, // stop reading a file after this many matching lines, like grep -m
+}
+
+impl GrepOptions {
+    // -C n sets both before and after context
+    pub fn context(mut self, n: usize) -> Self {
+        self.before_context = n;
+        self.after_context = n;
+        self
+    }
+
+    // compile the pattern according to the options
+    pub fn build_regex(&self, pattern: &str) -> Result<Regex, regex::Error> {
+        let pattern = if self.fixed_strings { regex::escape(pattern) } else { pattern.to_string() };
+        RegexBuilder::new(&pattern).case_insensitive(self.ignore_case).build()
+    }
+}
+
+// errors from walking the directory tree or from reading a file
+#[derive(Debug)]
+pub enum GrepError {
+    Walk(walkdir::Error),
+    Io { file: String, error: io::Error },
+}
+
+impl fmt::Display for GrepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrepError::Walk(e) => write!(f, "{}", e),
+            GrepError::Io { file, error } => write!(f, "{}: {}", file, error),
+        }
+    }
+}
+
+impl std::error::Error for GrepError {}
+
 // (3) test walkdir iterator, see how errors are handled
 #[test]
 fn test_walk_dir() {
@@ -156,75 +520,345 @@ fn test_walk_dir() {
 
 // (3) define the grep adapter for the iterator
 // add anything you need implement it
-struct GrepIter {
-    inner: walkdir::IntoIter,
+// the walker gives the files, each regular file is read a line at a time: next() reads
+// lines of the current file only until one of them yields something (the hits of that line
+// and the context before it), so a file is never scanned ahead of what is consumed.
+// W is any walkdir iterator: the plain IntoIter or a FilterEntry pruning ignored directories
+pub struct GrepIter<W = walkdir::IntoIter> {
+    inner: W,
+    regex: Regex,
+    options: GrepOptions,
+    current: Option<Scanner<BufReader<File>>>, // the file being read
+    pending: VecDeque<Result<Match, GrepError>>, // what the last line read has yielded
 }
 
-impl GrepIter {
-    fn new(iter: walkdir::IntoIter) -> Self {
-        GrepIter { inner: iter }
+impl<W> GrepIter<W>
+where
+    W: Iterator<Item = walkdir::Result<walkdir::DirEntry>>,
+{
+    pub fn new(iter: W, regex: Regex, options: GrepOptions) -> Self {
+        GrepIter { inner: iter, regex, options, current: None, pending: VecDeque::new() }
     }
 }
 
-impl Iterator for GrepIter {
+// scan one whole file: matches and context lines in order, an I/O error (if any) after
+// the matches found before it; par_grep sends the results of a file all together
+pub fn grep_file(file: &str, regex: &Regex, options: &GrepOptions) -> Vec<Result<Match, GrepError>> {
+    let mut found = vec![];
+    let result = File::open(file)
+        .and_then(|f| scan_lines(BufReader::new(f), file, regex, options, |m| found.push(Ok(m))));
+    if let Err(error) = result {
+        found.push(Err(GrepError::Io { file: file.to_string(), error }));
+    }
+    found
+}
+
+// the core of grep, separated from the walker so the same logic works on any reader
+pub fn scan_lines<R, F>(reader: R, file: &str, regex: &Regex, options: &GrepOptions, mut emit: F) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(Match),
+{
+    let mut scanner = Scanner::new(reader, file.to_string());
+    while scanner.step(regex, options, &mut emit)? {}
+    Ok(())
+}
+
+// scan_lines stopped between two lines: before keeps at most before_context lines,
+// after_left counts the context lines still to print after the last hit, so memory stays
+// bounded whatever the size of the file
+struct Scanner<R> {
+    reader: R,
+    file: String,
+    before: VecDeque<(usize, String)>,
+    after_left: usize,
+    buf: Vec<u8>,
+    line_no: usize,
+    matched: usize, // matching lines, for max_count
+}
+
+impl<R: BufRead> Scanner<R> {
+    fn new(reader: R, file: String) -> Self {
+        Scanner { reader, file, before: VecDeque::new(), after_left: 0, buf: Vec::new(), line_no: 0, matched: 0 }
+    }
 
-    type Item = Result<Match, walkdir::Error>;
+    // reads one line and emits what it yields; false at the end of the file, or once
+    // max_count lines have matched and their context after has been printed
+    fn step<F: FnMut(Match)>(&mut self, regex: &Regex, options: &GrepOptions, emit: &mut F) -> io::Result<bool> {
+        let Scanner { reader, file, before, after_left, buf, line_no, matched } = self;
+        if *line_no == 0 && options.skip_binary && reader.fill_buf()?.contains(&0) {
+            return Ok(false); // binary file: same heuristic as grep, a NUL byte in the first block
+        }
+        if options.max_count.is_some_and(|max| *matched >= max) && *after_left == 0 {
+            return Ok(false); // the rest of the file is not even read
+        }
+
+        buf.clear();
+        if reader.read_until(b'\n', buf)? == 0 {
+            return Ok(false);
+        }
+        *line_no += 1;
+        let line_no = *line_no;
+        // strip the terminator, \r\n included; invalid UTF-8 is replaced, not an error
+        let end = buf.len() - buf.ends_with(b"\n") as usize;
+        let end = end - buf[..end].ends_with(b"\r") as usize;
+        let text = String::from_utf8_lossy(&buf[..end]).into_owned();
+
+        let columns: Vec<usize> = if options.max_count.is_some_and(|max| *matched >= max) {
+            vec![] // only the context after the last allowed match is still read
+        } else if options.invert_match {
+            if regex.is_match(&text) { vec![] } else { vec![0] }
+        } else {
+            // byte offset -> 1-based char column
+            regex.find_iter(&text).map(|m| text[..m.start()].chars().count() + 1).collect()
+        };
+
+        if columns.is_empty() {
+            if *after_left > 0 {
+                *after_left -= 1;
+                emit(Match { file: file.clone(), line: line_no, column: 0, text, kind: LineKind::Context });
+            } else if options.before_context > 0 {
+                if before.len() == options.before_context {
+                    before.pop_front();
+                }
+                before.push_back((line_no, text));
+            }
+            return Ok(true);
+        }
+
+        for (line, text) in before.drain(..) {
+            emit(Match { file: file.clone(), line, column: 0, text, kind: LineKind::Context });
+        }
+        for column in columns {
+            emit(Match { file: file.clone(), line: line_no, column, text: text.clone(), kind: LineKind::Match });
+        }
+        *matched += 1;
+        *after_left = options.after_context;
+        Ok(true)
+    }
+}
+
+impl<W> Iterator for GrepIter<W>
+where
+    W: Iterator<Item = walkdir::Result<walkdir::DirEntry>>,
+{
+
+    type Item = Result<Match, GrepError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // (4) implement the next() method
         // hint: use the regex crate to match the lines
         // hint: use the walkdir crate to get the file name and line number
-        if let Some(entry) = self.inner.next() {
-            match entry {
+        loop {
+            if let Some(m) = self.pending.pop_front() {
+                return Some(m);
+            }
+            if let Some(scanner) = &mut self.current {
+                let pending = &mut self.pending;
+                match scanner.step(&self.regex, &self.options, &mut |m| pending.push_back(Ok(m))) {
+                    Ok(true) => {}
+                    Ok(false) => self.current = None,
+                    Err(error) => {
+                        pending.push_back(Err(GrepError::Io { file: scanner.file.clone(), error }));
+                        self.current = None;
+                    }
+                }
+                continue;
+            }
+            match self.inner.next()? {
                 Ok(e) => {
+                    // only regular files: directories are walked, fifos and devices would block
+                    if !e.file_type().is_file() {
+                        continue;
+                    }
                     let file = e.path().display().to_string();
-                    let line = 0; // TODO: implement line number
-                    let text = e.file_name().to_string_lossy().to_string(); // TODO: implement text
-                    Some(Ok(Match { file, line, text }))
+                    match File::open(&file) {
+                        Ok(f) => self.current = Some(Scanner::new(BufReader::new(f), file)),
+                        Err(error) => return Some(Err(GrepError::Io { file, error })),
+                    }
                 }
-                Err(e) => Some(Err(e)),
+                Err(e) => return Some(Err(GrepError::Walk(e))),
             }
-        } else {
-            None
         }
     }
 }
 
+// a small directory tree for the tests, removed when dropped
+#[cfg(test)]
+struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let root = std::env::temp_dir().join(format!("adapters_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        TestDir(root)
+    }
+
+    fn walk(&self) -> walkdir::IntoIter {
+        walkdir::WalkDir::new(&self.0).sort_by_file_name().into_iter()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+fn hits(iter: impl Iterator<Item = Result<Match, GrepError>>) -> Vec<(String, usize, usize, String)> {
+    iter.map(|m| m.unwrap())
+        .map(|m| {
+            let file = std::path::Path::new(&m.file).file_name().unwrap().to_string_lossy().to_string();
+            (file, m.line, m.column, m.text)
+        })
+        .collect()
+}
+
 #[test]
 fn test_grep_iter() {
-    let wdir = walkdir::WalkDir::new("/tmp");
-    let grep_iter = GrepIter::new(wdir.into_iter());
-    for entry in grep_iter {
-        match entry {
-            Ok(m) => { println!("File: {}, Line: {}, Text: {}", m.file, m.line, m.text); }
-            Err(e) => { println!("Error: {}", e); }
+    let dir = TestDir::new("grep_iter", &[
+        ("a.txt", "hello world\nnothing here\nworld, world\n"),
+        ("sub/b.txt", "the World\r\nworld\n"),
+    ]);
+    let regex = Regex::new("world").unwrap();
+    let grep_iter = GrepIter::new(dir.walk(), regex, GrepOptions::default());
+    assert_eq!(hits(grep_iter), vec![
+        ("a.txt".to_string(), 1, 7, "hello world".to_string()),
+        ("a.txt".to_string(), 3, 1, "world, world".to_string()),
+        ("a.txt".to_string(), 3, 8, "world, world".to_string()),
+        ("b.txt".to_string(), 2, 1, "world".to_string()),
+    ]);
+}
+
+#[test]
+fn test_grep_columns_are_chars() {
+    let dir = TestDir::new("grep_columns", &[("it.txt", "perché sì: città\n")]);
+    let found = hits(dir.walk().grep("città", GrepOptions::default()).unwrap());
+    assert_eq!(found[0].2, 12);
+}
+
+#[test]
+fn test_grep_options() {
+    let dir = TestDir::new("grep_options", &[("a.txt", "a.b\nAXB\naxb\n")]);
+    let lines = |options: GrepOptions| {
+        hits(dir.walk().grep("a.b", options).unwrap()).into_iter().map(|h| h.1).collect::<Vec<_>>()
+    };
+    assert_eq!(lines(GrepOptions::default()), vec![1, 3]);
+    assert_eq!(lines(GrepOptions { fixed_strings: true, ..Default::default() }), vec![1]);
+    assert_eq!(lines(GrepOptions { ignore_case: true, ..Default::default() }), vec![1, 2, 3]);
+    assert_eq!(lines(GrepOptions { invert_match: true, fixed_strings: true, ..Default::default() }), vec![2, 3]);
+}
+
+#[test]
+fn test_grep_context() {
+    let text = "1\n2\nhit 3\n4\n5\n6\nhit 7\nhit 8\n9\n10\n11\n";
+    let regex = Regex::new("hit").unwrap();
+    let mut found = vec![];
+    let options = GrepOptions::default().context(1);
+    scan_lines(text.as_bytes(), "f", &regex, &options, |m| found.push((m.line, m.kind))).unwrap();
+    assert_eq!(found, vec![
+        (2, LineKind::Context), (3, LineKind::Match), (4, LineKind::Context),
+        (6, LineKind::Context), (7, LineKind::Match), (8, LineKind::Match), (9, LineKind::Context),
+    ]);
+
+    let mut found = vec![];
+    let options = GrepOptions { before_context: 2, ..Default::default() };
+    scan_lines(text.as_bytes(), "f", &regex, &options, |m| found.push(m.line)).unwrap();
+    assert_eq!(found, vec![1, 2, 3, 5, 6, 7, 8]);
+}
+
+#[test]
+fn test_grep_iter_reads_only_what_is_consumed() {
+    // well beyond the buffer of BufReader, so reading ahead would reach the end of the file
+    let filler = "nothing\n".repeat(10_000);
+    let dir = TestDir::new("grep_lazy", &[("a.txt", &format!("hit 1\n{}", filler))]);
+    let mut grep_iter = dir.walk().grep("hit", GrepOptions::default()).unwrap();
+    assert_eq!(grep_iter.next().unwrap().unwrap().line, 1);
+    // a line written after the first hit has been consumed is still found
+    let mut file = std::fs::OpenOptions::new().append(true).open(dir.0.join("a.txt")).unwrap();
+    io::Write::write_all(&mut file, b"hit 2\n").unwrap();
+    assert_eq!(grep_iter.next().unwrap().unwrap().line, 10_002);
+    assert!(grep_iter.next().is_none());
+}
+
+#[test]
+fn test_grep_max_count_stops_reading() {
+    // a reader that fails after the first two lines
+    struct Broken;
+    impl io::Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read past the end of the search"))
         }
     }
+    let reader = || BufReader::new(io::Read::chain(&b"hit 1\nhit 2\n"[..], Broken));
+    let regex = Regex::new("hit").unwrap();
+    let scan = |options: GrepOptions| {
+        let mut found = vec![];
+        let result = scan_lines(reader(), "f", &regex, &options, |m| found.push((m.line, m.kind)));
+        result.map(|_| found)
+    };
+    assert!(scan(GrepOptions::default()).is_err());
+    let first = GrepOptions { max_count: Some(1), ..Default::default() };
+    assert_eq!(scan(first.clone()).unwrap(), vec![(1, LineKind::Match)]);
+    // the context after the last allowed match is still printed, its hits are context too
+    let with_context = GrepOptions { after_context: 1, ..first };
+    assert_eq!(scan(with_context).unwrap(), vec![(1, LineKind::Match), (2, LineKind::Context)]);
 }
 
 // (5) add grep() to IntoIter  (see the first example in EvenIter for i32)
 
-trait Grep {
-    fn grep(self) -> GrepIter;
+pub trait Grep: Sized {
+    fn grep(self, pattern: &str, options: GrepOptions) -> Result<GrepIter<Self>, regex::Error>;
 }
 
-impl Grep for walkdir::IntoIter {
-    fn grep(self) -> GrepIter {
-        GrepIter::new(self)
+impl<W> Grep for W
+where
+    W: Iterator<Item = walkdir::Result<walkdir::DirEntry>>,
+{
+    fn grep(self, pattern: &str, options: GrepOptions) -> Result<GrepIter<Self>, regex::Error> {
+        let regex = options.build_regex(pattern)?;
+        Ok(GrepIter::new(self, regex, options))
     }
 }
 
 
 #[test]
 fn test_grep() {
-    let wdir = walkdir::WalkDir::new("/tmp");
-    let grep_iter = wdir.into_iter().grep();
+    let dir = TestDir::new("grep", &[("a.txt", "fn main() {}\n"), ("b.rs", "// fn\n")]);
+    let grep_iter = dir.walk().grep("fn", GrepOptions::default()).unwrap();
     for entry in grep_iter {
         match entry {
             Ok(m) => { println!("File: {}, Line: {}, Text: {}", m.file, m.line, m.text); }
             Err(e) => { println!("Error: {}", e); }
         }
     }
+    assert!(dir.walk().grep("(", GrepOptions::default()).is_err());
+    assert_eq!(dir.walk().grep("(", GrepOptions { fixed_strings: true, ..Default::default() }).unwrap().count(), 1);
+}
+
+#[test]
+fn test_grep_skip_binary() {
+    let dir = TestDir::new("grep_binary", &[("a.bin", "match\0\x01\n"), ("b.txt", "match\n")]);
+    let options = GrepOptions { skip_binary: true, ..Default::default() };
+    let found = hits(dir.walk().grep("match", options).unwrap());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, "b.txt");
+    assert_eq!(dir.walk().grep("match", GrepOptions::default()).unwrap().count(), 2);
 }
 
-fn main() {}
\ No newline at end of file
+pub mod cli; // the grep command line front end, see cli.rs
+pub mod parallel; // the same search over a pool of threads, see parallel.rs
+pub mod text; // adapters for streams of lines and words, see text.rs
+
+fn main() {
+    let code = cli::run(std::env::args().skip(1), &mut io::stdout().lock(), &mut io::stderr());
+    std::process::exit(code);
+}
@@ -0,0 +1,323 @@
+// parallel grep: the same results of GrepIter, computed by a pool of scoped threads.
+//
+// - one thread walks the directories and pushes the files (numbered in walk order)
+//   into a global Injector queue, but never more than channel_size + threads files ahead
+//   of the last one whose results were emitted: then it parks until the consumer catches up
+// - N workers take files in batches into their local queue, when both are empty they
+//   steal from the other workers: a worker stuck on a big file does not slow down the others.
+//   With nothing to steal they park, the walker wakes them up after each push
+// - each worker sends the matches of a whole file through a bounded channel
+// - the caller's thread receives the results: in unordered mode they are emitted as they
+//   arrive, in ordered mode they are re-sequenced with the walk number so the output is
+//   the same as GrepIter's
+// so a slow consumer stops the walker and the workers instead of filling the memory: at most
+// channel_size + threads files are in flight (queued, being scanned, in the channel or waiting
+// for an earlier file to be re-sequenced), each one holding all its matches
+// like verify() in lab06 everything runs inside thread::scope, so the walker, the regex
+// and the options are borrowed and not moved into 'static threads
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::iter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+use crossbeam_deque::{Injector, Stealer, Worker};
+use regex::Regex;
+use walkdir::DirEntry;
+
+use crate::{grep_file, GrepError, GrepIter, GrepOptions, Match};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParallelOptions {
+    pub threads: usize,
+    pub ordered: bool,       // re-sequence the results in walk order
+    pub channel_size: usize, // files scanned but not yet consumed, per the whole pool
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        ParallelOptions {
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            ordered: true,
+            channel_size: 64,
+        }
+    }
+}
+
+// what the walker gives to the workers
+enum Task {
+    File(String),
+    Error(walkdir::Error),
+}
+
+// the task of a worker: its own queue first, then a batch from the global queue,
+// then anything that can be stolen from the other workers
+fn find_task<T>(local: &Worker<T>, global: &Injector<T>, stealers: &[Stealer<T>]) -> Option<T> {
+    local.pop().or_else(|| {
+        iter::repeat_with(|| {
+            global
+                .steal_batch_and_pop(local)
+                .or_else(|| stealers.iter().map(|s| s.steal()).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(|s| s.success())
+    })
+}
+
+// runs the search, emit receives each result and returns false to stop early
+// (e.g. when the output pipe is closed)
+pub fn par_grep<W, F>(walker: W, regex: &Regex, options: &GrepOptions, par: ParallelOptions, mut emit: F)
+where
+    W: Iterator<Item = walkdir::Result<DirEntry>> + Send,
+    F: FnMut(Result<Match, GrepError>) -> bool,
+{
+    let threads = par.threads.max(1);
+    let window = par.channel_size.max(1) + threads;
+    let injector = Injector::new();
+    let walking_done = AtomicBool::new(false);
+    let stop = AtomicBool::new(false);
+    let consumed = AtomicUsize::new(0); // files whose results have all been emitted
+    let (tx, rx) = mpsc::sync_channel::<(usize, Vec<Result<Match, GrepError>>)>(par.channel_size.max(1));
+    let workers: Vec<Worker<(usize, Task)>> = (0..threads).map(|_| Worker::new_fifo()).collect();
+    let stealers: Vec<Stealer<(usize, Task)>> = workers.iter().map(|w| w.stealer()).collect();
+
+    thread::scope(|s| {
+        let worker_threads: Vec<Thread> = workers
+            .into_iter()
+            .map(|local| {
+                let tx = tx.clone();
+                let (injector, stealers, walking_done, stop) = (&injector, &stealers, &walking_done, &stop);
+                let handle = s.spawn(move || {
+                    loop {
+                        if stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        // read done before looking for tasks: if it was already true, every task
+                        // has been pushed and an empty search means there is nothing left
+                        let done = walking_done.load(Ordering::Acquire);
+                        match find_task(&local, injector, stealers) {
+                            Some((seq, task)) => {
+                                let results = match task {
+                                    Task::File(file) => grep_file(&file, regex, options),
+                                    Task::Error(e) => vec![Err(GrepError::Walk(e))],
+                                };
+                                if tx.send((seq, results)).is_err() {
+                                    break; // the receiver stopped
+                                }
+                            }
+                            None if done => break,
+                            // an unpark that came between find_task and here is not lost:
+                            // park returns immediately and the loop looks again
+                            None => thread::park(),
+                        }
+                    }
+                });
+                handle.thread().clone()
+            })
+            .collect();
+        drop(tx); // the channel closes when the last worker ends
+
+        // walker: the tasks are numbered without holes, directories don't count
+        let (injector, walking_done, stop, consumed) = (&injector, &walking_done, &stop, &consumed);
+        let walker_thread = s.spawn(move || {
+            let mut seq = 0;
+            'walk: for entry in walker {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let task = match entry {
+                    Ok(e) if e.file_type().is_file() => Task::File(e.path().display().to_string()),
+                    Ok(_) => continue,
+                    Err(e) => Task::Error(e),
+                };
+                // the receiver unparks us each time it is done with a file
+                while seq >= consumed.load(Ordering::Acquire) + window {
+                    if stop.load(Ordering::Relaxed) {
+                        break 'walk;
+                    }
+                    thread::park();
+                }
+                injector.push((seq, task));
+                seq += 1;
+                worker_threads.iter().for_each(Thread::unpark);
+            }
+            walking_done.store(true, Ordering::Release);
+            worker_threads.iter().for_each(Thread::unpark);
+        });
+        let walker_thread = walker_thread.thread().clone();
+
+        let mut next = 0;
+        let mut waiting = BTreeMap::new();
+        'receive: for (seq, results) in rx.iter() {
+            if !par.ordered {
+                for r in results {
+                    if !emit(r) {
+                        break 'receive;
+                    }
+                }
+                consumed.fetch_add(1, Ordering::Release);
+                walker_thread.unpark();
+                continue;
+            }
+            waiting.insert(seq, results);
+            while let Some(results) = waiting.remove(&next) {
+                next += 1;
+                for r in results {
+                    if !emit(r) {
+                        break 'receive;
+                    }
+                }
+                consumed.store(next, Ordering::Release);
+                walker_thread.unpark();
+            }
+        }
+        // if we stopped early the workers must not wait on a full channel and the walker
+        // must not wait for us: it ends and wakes up the parked workers
+        stop.store(true, Ordering::Relaxed);
+        walker_thread.unpark();
+        drop(rx);
+    });
+}
+
+// like the primes benchmark of lab06: the same search with 1..=max_threads threads,
+// ordered and unordered, compared with the sequential GrepIter
+pub fn benchmark<W, M, O>(make_walker: M, regex: &Regex, options: &GrepOptions, max_threads: usize, out: &mut O) -> io::Result<()>
+where
+    W: Iterator<Item = walkdir::Result<DirEntry>> + Send,
+    M: Fn() -> W,
+    O: Write,
+{
+    writeln!(out, "Logical cores: {}", ParallelOptions::default().threads)?;
+
+    let start = Instant::now();
+    let expected = GrepIter::new(make_walker(), regex.clone(), options.clone()).count();
+    writeln!(out, "GrepIter (sequential) - time: {:?} | results: {}\n", start.elapsed(), expected)?;
+
+    for threads in 1..=max_threads {
+        writeln!(out, "===> THREADS: {}", threads)?;
+        for ordered in [true, false] {
+            let par = ParallelOptions { threads, ordered, ..Default::default() };
+            let mut count = 0;
+            let start = Instant::now();
+            par_grep(make_walker(), regex, options, par, |_| {
+                count += 1;
+                true
+            });
+            let name = if ordered { "ordered  " } else { "unordered" };
+            writeln!(out, "{} - time: {:?} | results: {}", name, start.elapsed(), count)?;
+            if count != expected {
+                writeln!(out, "WARNING: the results differ from GrepIter!")?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDir;
+
+    fn test_dir() -> TestDir {
+        let files: Vec<(String, String)> = (0..40)
+            .map(|i| {
+                let content = (0..i * 10).map(|j| format!("line {} of file {}\n", j, i)).collect();
+                (format!("d{}/f{:02}.txt", i % 4, i), content)
+            })
+            .collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), c.as_str())).collect();
+        TestDir::new(&format!("parallel_{}", thread::current().name().unwrap_or("t").replace("::", "_")), &files)
+    }
+
+    fn collect(dir: &TestDir, par: ParallelOptions) -> Vec<(String, usize, usize)> {
+        let regex = Regex::new(r"line \d*7 ").unwrap();
+        let mut found = vec![];
+        par_grep(dir.walk(), &regex, &GrepOptions::default(), par, |r| {
+            let m = r.unwrap();
+            found.push((m.file, m.line, m.column));
+            true
+        });
+        found
+    }
+
+    #[test]
+    fn test_ordered_same_as_grep_iter() {
+        let dir = test_dir();
+        let regex = Regex::new(r"line \d*7 ").unwrap();
+        let expected: Vec<_> = GrepIter::new(dir.walk(), regex, GrepOptions::default())
+            .map(|r| r.unwrap())
+            .map(|m| (m.file, m.line, m.column))
+            .collect();
+        assert!(!expected.is_empty());
+        for threads in [1, 2, 4, 8] {
+            let par = ParallelOptions { threads, ordered: true, channel_size: 2 };
+            assert_eq!(collect(&dir, par), expected);
+        }
+    }
+
+    #[test]
+    fn test_unordered_same_set() {
+        let dir = test_dir();
+        let mut ordered = collect(&dir, ParallelOptions { threads: 1, ordered: true, channel_size: 4 });
+        let mut unordered = collect(&dir, ParallelOptions { threads: 4, ordered: false, channel_size: 1 });
+        ordered.sort();
+        unordered.sort();
+        assert_eq!(ordered, unordered);
+    }
+
+    #[test]
+    fn test_stop_early() {
+        let dir = test_dir();
+        let regex = Regex::new("line").unwrap();
+        let mut count = 0;
+        let par = ParallelOptions { threads: 4, ordered: true, channel_size: 1 };
+        par_grep(dir.walk(), &regex, &GrepOptions::default(), par, |_| {
+            count += 1;
+            count < 5
+        });
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_slow_consumer_stops_the_walker() {
+        let files: Vec<(String, &str)> = (0..40).map(|i| (format!("f{:02}.txt", i), "line\n")).collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), *c)).collect();
+        let dir = TestDir::new("parallel_slow_consumer", &files);
+        let walked = AtomicUsize::new(0);
+        let walker = dir.walk().inspect(|e| {
+            if e.as_ref().is_ok_and(|e| e.file_type().is_file()) {
+                walked.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let regex = Regex::new("line").unwrap();
+        let par = ParallelOptions { threads: 2, ordered: true, channel_size: 1 };
+        let mut first = true;
+        par_grep(walker, &regex, &GrepOptions::default(), par, |_| {
+            if first {
+                // plenty of time to walk all the 40 files, if nothing held the walker back
+                thread::sleep(std::time::Duration::from_millis(100));
+                // the window is 1 + 2 files, plus the one the walker took and is holding
+                assert!(walked.load(Ordering::Relaxed) <= 4);
+                first = false;
+            }
+            true
+        });
+        assert_eq!(walked.load(Ordering::Relaxed), 40);
+    }
+
+    #[test]
+    fn test_walk_errors_are_reported() {
+        let regex = Regex::new("x").unwrap();
+        let mut errors = 0;
+        let walker = walkdir::WalkDir::new("/does/not/exist").into_iter();
+        par_grep(walker, &regex, &GrepOptions::default(), ParallelOptions::default(), |r| {
+            errors += r.is_err() as usize;
+            true
+        });
+        assert_eq!(errors, 1);
+    }
+}
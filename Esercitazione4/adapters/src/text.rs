@@ -0,0 +1,463 @@
+// adapters for processing text streams, e.g. the lines of a log file
+// all of them are lazy: they pull from the inner iterator only what they need for
+// the next item, so they work on endless streams too (like BufRead::lines on a pipe)
+//
+// usage: reader.lines().map_while(Result::ok).words().ngrams(2) ...
+
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::iter::Peekable;
+use std::rc::Rc;
+
+// the words (separated by whitespace) of each line, in order
+pub struct Words<I>
+where
+    I: Iterator
+{
+    iter: I,
+    words: VecDeque<String>, // the words of the current line not yet returned
+}
+
+impl<I> Iterator for Words<I>
+where
+    I: Iterator,
+    I::Item: AsRef<str>
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(word) = self.words.pop_front() {
+                return Some(word);
+            }
+            let line = self.iter.next()?; // one line at a time, empty lines are skipped
+            self.words.extend(line.as_ref().split_whitespace().map(String::from));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // a line may hold any number of words, the bound is known only at the last line
+        match self.iter.size_hint().1 {
+            Some(0) => (self.words.len(), Some(self.words.len())),
+            _ => (self.words.len(), None),
+        }
+    }
+}
+
+// the windows of n consecutive items: [a, b, c] -> [a, b], [b, c]
+pub struct Ngrams<I>
+where
+    I: Iterator
+{
+    iter: I,
+    n: usize,
+    window: VecDeque<I::Item>, // the last n - 1 items between two calls
+}
+
+impl<I> Iterator for Ngrams<I>
+where
+    I: Iterator,
+    I::Item: Clone
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.window.len() < self.n {
+            self.window.push_back(self.iter.next()?);
+        }
+        let gram = self.window.iter().cloned().collect();
+        self.window.pop_front();
+        Some(gram)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.iter.size_hint();
+        let grams = |items: usize| items.saturating_sub(self.n - 1);
+        // the items in the window count too: the lower bound saturates, an upper bound
+        // that doesn't fit in usize is unknown
+        let low = grams(low.saturating_add(self.window.len()));
+        (low, high.and_then(|h| h.checked_add(self.window.len())).map(grams))
+    }
+}
+
+// drops the items equal to the previous one, like uniq(1)
+pub struct DedupConsecutive<I>
+where
+    I: Iterator
+{
+    iter: I,
+    last: Option<I::Item>,
+}
+
+impl<I> Iterator for DedupConsecutive<I>
+where
+    I: Iterator,
+    I::Item: PartialEq + Clone
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let last = self.last.take();
+        let item = self.iter.find(|item| last.as_ref() != Some(item))?;
+        self.last = Some(item.clone());
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.iter.size_hint();
+        (low.min(1), high) // all the items may be equal to the last one
+    }
+}
+
+// drops the items whose key has already been seen
+// the memory is bounded: only the last `capacity` distinct keys are remembered, an older
+// key is forgotten and its items pass again (the same trade-off of a log deduplicator)
+pub struct UniqueBy<I, K, F>
+where
+    I: Iterator
+{
+    iter: I,
+    key: F,
+    capacity: usize,
+    seen: HashSet<K>,
+    order: VecDeque<K>, // the keys of seen, oldest first
+}
+
+impl<I, K, F> Iterator for UniqueBy<I, K, F>
+where
+    I: Iterator,
+    K: Hash + Eq + Clone,
+    F: FnMut(&I::Item) -> K
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for item in self.iter.by_ref() {
+            let key = (self.key)(&item);
+            if self.seen.contains(&key) {
+                continue;
+            }
+            if self.capacity > 0 {
+                if self.order.len() == self.capacity {
+                    let oldest = self.order.pop_front().expect("capacity > 0");
+                    self.seen.remove(&oldest);
+                }
+                self.order.push_back(key.clone());
+                self.seen.insert(key);
+            }
+            return Some(item);
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+// groups the lines in paragraphs separated by one or more blank lines
+// (only whitespace), the blank lines are not returned
+pub struct ChunkByBlankLine<I>
+where
+    I: Iterator
+{
+    iter: I,
+}
+
+impl<I> Iterator for ChunkByBlankLine<I>
+where
+    I: Iterator,
+    I::Item: AsRef<str>
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::new();
+        for line in self.iter.by_ref() {
+            if !line.as_ref().trim().is_empty() {
+                chunk.push(line);
+            } else if !chunk.is_empty() {
+                return Some(chunk);
+            }
+        }
+        if chunk.is_empty() { None } else { Some(chunk) }
+    }
+}
+
+// the state shared by the copies made by tee()
+struct TeeShared<I>
+where
+    I: Iterator
+{
+    iter: I,
+    buffers: Vec<Option<VecDeque<I::Item>>>, // None when the copy has been dropped
+}
+
+// one of the n copies of an iterator: each copy sees all the items
+// the items read by a copy are buffered for the others, so the memory grows with the
+// distance between the fastest and the slowest copy; the copies live on one thread (Rc)
+pub struct Tee<I>
+where
+    I: Iterator
+{
+    shared: Rc<RefCell<TeeShared<I>>>,
+    index: usize,
+}
+
+impl<I> Iterator for Tee<I>
+where
+    I: Iterator,
+    I::Item: Clone
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut shared = self.shared.borrow_mut();
+        if let Some(item) = shared.buffers[self.index].as_mut().and_then(|b| b.pop_front()) {
+            return Some(item);
+        }
+        let item = shared.iter.next()?;
+        for (i, buffer) in shared.buffers.iter_mut().enumerate() {
+            if let (true, Some(buffer)) = (i != self.index, buffer) {
+                buffer.push_back(item.clone());
+            }
+        }
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let shared = self.shared.borrow();
+        let buffered = shared.buffers[self.index].as_ref().map_or(0, |b| b.len());
+        let (low, high) = shared.iter.size_hint();
+        (low.saturating_add(buffered), high.and_then(|h| h.checked_add(buffered)))
+    }
+}
+
+impl<I> Drop for Tee<I>
+where
+    I: Iterator
+{
+    fn drop(&mut self) {
+        // nobody will read this buffer anymore: stop filling it
+        if let Ok(mut shared) = self.shared.try_borrow_mut() {
+            shared.buffers[self.index] = None;
+        }
+    }
+}
+
+// merges two iterators sorted in ascending order into one sorted iterator
+// with equal items the ones of self come first
+pub struct MergeSorted<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>
+{
+    left: Peekable<I>,
+    right: Peekable<J>,
+}
+
+impl<I, J> Iterator for MergeSorted<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>,
+    I::Item: Ord
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.left.peek(), self.right.peek()) {
+            (Some(l), Some(r)) if r < l => self.right.next(),
+            (Some(_), _) => self.left.next(),
+            (None, _) => self.right.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (l_low, l_high) = self.left.size_hint();
+        let (r_low, r_high) = self.right.size_hint();
+        let high = l_high.zip(r_high).and_then(|(l, r)| l.checked_add(r));
+        (l_low.saturating_add(r_low), high)
+    }
+}
+
+// all the text adapters for any iterator, each method asks only the bounds it needs:
+// words() and chunk_by_blank_line() want items that are text (String, &str, ...)
+pub trait TextAdapters: Iterator + Sized {
+
+    fn words(self) -> Words<Self>
+    where
+        Self::Item: AsRef<str>
+    {
+        Words { iter: self, words: VecDeque::new() }
+    }
+
+    // panics if n is 0, like slice::windows
+    fn ngrams(self, n: usize) -> Ngrams<Self>
+    where
+        Self::Item: Clone
+    {
+        assert!(n > 0, "ngrams: n must be greater than 0");
+        Ngrams { iter: self, n, window: VecDeque::with_capacity(n) }
+    }
+
+    fn dedup_consecutive(self) -> DedupConsecutive<Self>
+    where
+        Self::Item: PartialEq + Clone
+    {
+        DedupConsecutive { iter: self, last: None }
+    }
+
+    // remembers at most `capacity` keys, with capacity 0 nothing is dropped
+    fn unique_by<K, F>(self, capacity: usize, key: F) -> UniqueBy<Self, K, F>
+    where
+        K: Hash + Eq + Clone,
+        F: FnMut(&Self::Item) -> K
+    {
+        UniqueBy { iter: self, key, capacity, seen: HashSet::new(), order: VecDeque::new() }
+    }
+
+    fn chunk_by_blank_line(self) -> ChunkByBlankLine<Self>
+    where
+        Self::Item: AsRef<str>
+    {
+        ChunkByBlankLine { iter: self }
+    }
+
+    fn tee(self, n: usize) -> Vec<Tee<Self>>
+    where
+        Self::Item: Clone
+    {
+        let shared = Rc::new(RefCell::new(TeeShared {
+            iter: self,
+            buffers: (0..n).map(|_| Some(VecDeque::new())).collect(),
+        }));
+        (0..n).map(|index| Tee { shared: Rc::clone(&shared), index }).collect()
+    }
+
+    fn merge_sorted<J>(self, other: J) -> MergeSorted<Self, J::IntoIter>
+    where
+        J: IntoIterator<Item = Self::Item>,
+        Self::Item: Ord
+    {
+        MergeSorted { left: self.peekable(), right: other.into_iter().peekable() }
+    }
+}
+
+impl<I> TextAdapters for I
+where
+    I: Iterator
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "GET /index\nGET /index\n\n\nPOST /login\nGET /index\n  \nGET /about";
+
+    #[test]
+    fn test_words() {
+        let words: Vec<String> = LOG.lines().words().collect();
+        assert_eq!(words.len(), 10);
+        assert_eq!(words[..3], ["GET", "/index", "GET"]);
+        let v = vec![String::from("  a b  "), String::new(), String::from("c")];
+        assert_eq!(v.into_iter().words().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_ngrams() {
+        let grams: Vec<Vec<&str>> = "a b c d".split(' ').ngrams(2).collect();
+        assert_eq!(grams, vec![vec!["a", "b"], vec!["b", "c"], vec!["c", "d"]]);
+        assert_eq!("the cat sat".lines().words().ngrams(3).count(), 1);
+        assert_eq!("a b".split(' ').ngrams(3).next(), None);
+        let mut it = (0..10).ngrams(4);
+        assert_eq!(it.size_hint(), (7, Some(7)));
+        it.next();
+        assert_eq!(it.size_hint(), (6, Some(6)));
+    }
+
+    #[test]
+    fn test_ngrams_of_infinite_source() {
+        let grams: Vec<Vec<u32>> = (0u32..).ngrams(2).take(3).collect();
+        assert_eq!(grams, vec![vec![0, 1], vec![1, 2], vec![2, 3]]);
+        let mut it = (0u32..).ngrams(3);
+        it.next();
+        assert_eq!(it.size_hint().1, None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_ngrams_zero() {
+        let _ = (0..3).ngrams(0);
+    }
+
+    #[test]
+    fn test_dedup_consecutive() {
+        let lines: Vec<&str> = LOG.lines().dedup_consecutive().collect();
+        assert_eq!(lines, vec!["GET /index", "", "POST /login", "GET /index", "  ", "GET /about"]);
+        assert_eq!(vec![1, 1, 1].into_iter().dedup_consecutive().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_unique_by_bounded() {
+        let v = vec!["a1", "b1", "a2", "c1", "a3", "b2"];
+        // the first letter is the key
+        let all: Vec<&str> = v.clone().into_iter().unique_by(10, |s| s.as_bytes()[0]).collect();
+        assert_eq!(all, vec!["a1", "b1", "c1"]);
+        // only 2 keys: when c arrives a is forgotten, when a passes again b is forgotten
+        let bounded: Vec<&str> = v.into_iter().unique_by(2, |s| s.as_bytes()[0]).collect();
+        assert_eq!(bounded, vec!["a1", "b1", "c1", "a3", "b2"]);
+        assert_eq!((0..5).unique_by(0, |_| 0).count(), 5);
+    }
+
+    #[test]
+    fn test_chunk_by_blank_line() {
+        let chunks: Vec<Vec<&str>> = LOG.lines().chunk_by_blank_line().collect();
+        assert_eq!(chunks, vec![
+            vec!["GET /index", "GET /index"],
+            vec!["POST /login", "GET /index"],
+            vec!["GET /about"],
+        ]);
+        assert_eq!("\n\n".lines().chunk_by_blank_line().next(), None);
+    }
+
+    #[test]
+    fn test_tee() {
+        let mut copies = LOG.lines().filter(|l| !l.trim().is_empty()).tee(3);
+        let third = copies.pop().unwrap();
+        let second = copies.pop().unwrap();
+        let first = copies.pop().unwrap();
+        let words: Vec<String> = first.words().collect();
+        assert_eq!(words.len(), 10);
+        assert_eq!(second.size_hint().0, 5); // all buffered by the first copy
+        assert_eq!(second.dedup_consecutive().count(), 4);
+        drop(third); // its buffer is released
+    }
+
+    #[test]
+    fn test_tee_interleaved_is_lazy() {
+        let pulled = RefCell::new(0);
+        let source = (1..=3).inspect(|_| *pulled.borrow_mut() += 1);
+        let mut copies = source.tee(2);
+        assert_eq!(copies[0].next(), Some(1));
+        assert_eq!(copies[1].next(), Some(1));
+        assert_eq!(copies[1].next(), Some(2));
+        assert_eq!(*pulled.borrow(), 2);
+        assert_eq!(copies[0].by_ref().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(copies[1].next(), Some(3));
+        assert_eq!(copies[1].next(), None);
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        let a = vec![1, 4, 4, 9];
+        let b = vec![2, 4, 10];
+        let merged: Vec<i32> = a.into_iter().merge_sorted(b).collect();
+        assert_eq!(merged, vec![1, 2, 4, 4, 4, 9, 10]);
+        let logs = vec!["10:00 a", "10:05 c"].into_iter().merge_sorted(vec!["10:01 b"]);
+        assert_eq!(logs.size_hint(), (3, Some(3)));
+        assert_eq!(logs.collect::<Vec<_>>(), vec!["10:00 a", "10:01 b", "10:05 c"]);
+        assert_eq!((0..0).merge_sorted(1..3).collect::<Vec<_>>(), vec![1, 2]);
+    }
+}
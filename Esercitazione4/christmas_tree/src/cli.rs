@@ -0,0 +1,273 @@
+// il controllo delle luci da riga di comando:
+//
+//   christmas_tree [OPTIONS] FILE [COMMAND...]
+//
+// carica l'albero da FILE (il formato è in file.rs), applica i comandi in ordine e disegna
+// l'albero. Codici di uscita: 0 ok, 1 un comando è fallito, 2 argomenti o FILE sbagliati.
+// i messaggi per l'utente sono in inglese, come quelli di AlberoError
+
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::thread;
+use std::time::Instant;
+
+use crate::{Albero, AlberoError, shared};
+
+const USAGE: &str = "\
+usage: christmas_tree [OPTIONS] FILE [COMMAND...]
+       christmas_tree --stress [OPS]
+
+FILE is a tree in the indented format, or in JSON if its name ends in .json
+commands, applied in order:
+  toggle NODE            toggle the switch of NODE
+  toggle-subtree NODE    give the switches of the whole branch the new value of NODE
+  on, off                all the switches on (off)
+  add FATHER NODE        add NODE as the last child of FATHER
+  remove NODE            remove NODE and its branch
+options:
+  -o, --output FILE      save the tree to FILE (JSON if it ends in .json)
+  -q, --quiet            don't draw the tree
+      --stress [OPS]     time SharedAlbero with 1..=cores threads, OPS operations each
+  -h, --help             print this help";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Toggle(String),
+    ToggleSubtree(String),
+    AllOn,
+    AllOff,
+    Add(String, String),
+    Remove(String),
+}
+
+// il comando come si scrive sulla riga di comando, per i messaggi di errore
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Toggle(n) => write!(f, "toggle {}", n),
+            Command::ToggleSubtree(n) => write!(f, "toggle-subtree {}", n),
+            Command::AllOn => write!(f, "on"),
+            Command::AllOff => write!(f, "off"),
+            Command::Add(father, n) => write!(f, "add {} {}", father, n),
+            Command::Remove(n) => write!(f, "remove {}", n),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub file: String,
+    pub commands: Vec<Command>,
+    pub output: Option<String>,
+    pub quiet: bool,
+    pub stress: Option<usize>,
+    pub help: bool,
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut args = args.into_iter().peekable();
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs an argument", arg));
+        match arg.as_str() {
+            "-o" | "--output" => config.output = Some(value()?),
+            "-q" | "--quiet" => config.quiet = true,
+            "-h" | "--help" => config.help = true,
+            "--stress" => {
+                let ops = args.peek().and_then(|a| a.parse().ok());
+                if ops.is_some() {
+                    args.next();
+                }
+                config.stress = Some(ops.unwrap_or(20_000));
+            }
+            a if a.starts_with('-') && a.len() > 1 => return Err(format!("unknown option {}", a)),
+            _ if file.is_none() => file = Some(arg),
+            "toggle" => config.commands.push(Command::Toggle(value()?)),
+            "toggle-subtree" => config.commands.push(Command::ToggleSubtree(value()?)),
+            "on" => config.commands.push(Command::AllOn),
+            "off" => config.commands.push(Command::AllOff),
+            "add" => {
+                let father = value()?;
+                config.commands.push(Command::Add(father, value()?));
+            }
+            "remove" => config.commands.push(Command::Remove(value()?)),
+            c => return Err(format!("unknown command {}", c)),
+        }
+    }
+    match file {
+        Some(f) => config.file = f,
+        None if config.help || config.stress.is_some() => {}
+        None => return Err("missing FILE".to_string()),
+    }
+    Ok(config)
+}
+
+fn is_json(file: &str) -> bool {
+    file.ends_with(".json")
+}
+
+pub fn load(file: &str) -> Result<Albero, String> {
+    let testo = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+    let albero = if is_json(file) { Albero::from_json(&testo) } else { testo.parse() };
+    albero.map_err(|e| format!("{}: {}", file, e))
+}
+
+pub fn save(albero: &Albero, file: &str) -> Result<(), String> {
+    let testo = if is_json(file) { albero.to_json() + "\n" } else { albero.to_string() };
+    fs::write(file, testo).map_err(|e| format!("{}: {}", file, e))
+}
+
+fn apply(albero: &mut Albero, command: &Command) -> Result<(), AlberoError> {
+    match command {
+        Command::Toggle(n) => albero.toggle(n).map(|_| ()),
+        Command::ToggleSubtree(n) => albero.toggle_subtree(n).map(|_| ()),
+        Command::AllOn => {
+            albero.all_on();
+            Ok(())
+        }
+        Command::AllOff => {
+            albero.all_off();
+            Ok(())
+        }
+        Command::Add(father, n) => albero.add(father, n),
+        Command::Remove(n) => albero.remove(n).map(|_| ()),
+    }
+}
+
+// lo stress di SharedAlbero come i benchmark di lab06, con 1..=cores thread
+fn stress<W: Write>(ops: usize, out: &mut W) -> std::io::Result<()> {
+    let max_threads = thread::available_parallelism().map_or(4, |n| n.get());
+    writeln!(out, "Stress SharedAlbero: {ops} operations per thread\n")?;
+    for n_threads in 1..=max_threads {
+        writeln!(out, "===> THREADS: {n_threads}")?;
+
+        let start = Instant::now();
+        let (albero, _) = shared::stress(n_threads, ops);
+        let duration = start.elapsed();
+
+        writeln!(out, "Time: {:?} | Nodes: {}\n", duration, albero.len())?;
+    }
+    Ok(())
+}
+
+pub fn run<I, W, E>(args: I, out: &mut W, err: &mut E) -> i32
+where
+    I: IntoIterator<Item = String>,
+    W: Write,
+    E: Write,
+{
+    let config = match parse_args(args) {
+        Ok(c) => c,
+        Err(e) => {
+            let _ = writeln!(err, "christmas_tree: {}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    if config.help {
+        let _ = writeln!(out, "{}", USAGE);
+        return 0;
+    }
+    if let Some(ops) = config.stress {
+        return match stress(ops, out) {
+            Ok(()) => 0,
+            Err(_) => 2,
+        };
+    }
+    let mut albero = match load(&config.file) {
+        Ok(a) => a,
+        Err(e) => {
+            let _ = writeln!(err, "christmas_tree: {}", e);
+            return 2;
+        }
+    };
+    // un comando che fallisce non ferma gli altri, ma il codice di uscita è 1
+    let mut code = 0;
+    for command in &config.commands {
+        if let Err(e) = apply(&mut albero, command) {
+            let _ = writeln!(err, "christmas_tree: {}: {}", command, e);
+            code = 1;
+        }
+    }
+    if let Some(output) = &config.output
+        && let Err(e) = save(&albero, output)
+    {
+        let _ = writeln!(err, "christmas_tree: {}", e);
+        return 2;
+    }
+    if !config.quiet {
+        let _ = write!(out, "{}", albero.render());
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("christmas_tree_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        fn file(&self, name: &str, contents: &str) -> String {
+            let path = self.0.join(name);
+            fs::write(&path, contents).unwrap();
+            path.display().to_string()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn christmas_tree(args: &[&str]) -> (i32, String, String) {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = run(args.iter().map(|a| a.to_string()), &mut out, &mut err);
+        (code, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn test_toggle_commands_and_render() {
+        let dir = TestDir::new("render");
+        let file = dir.file("luci.txt", "root\n  a on\n  b\n");
+        let (code, out, err) = christmas_tree(&[&file, "toggle", "root", "add", "b", "b1", "toggle", "b1"]);
+        assert_eq!((code, err.as_str()), (1, "christmas_tree: toggle b1: ancestor b is off\n"));
+        assert_eq!(out, "● root\n├── ● a\n└── ○ b\n    └── ○ b1\n");
+    }
+
+    #[test]
+    fn test_save_converts_format() {
+        let dir = TestDir::new("save");
+        let file = dir.file("luci.txt", "root on\n  a\n");
+        let json = dir.0.join("luci.json").display().to_string();
+        let (code, out, _) = christmas_tree(&["-q", "-o", &json, &file, "on"]);
+        assert_eq!((code, out.as_str()), (0, ""));
+        let letto = load(&json).unwrap();
+        assert_eq!(letto.to_string(), "root on\n  a on\n");
+        let (code, out, _) = christmas_tree(&[&json, "remove", "a"]);
+        assert_eq!((code, out.as_str()), (0, "● root\n"));
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        let dir = TestDir::new("errors");
+        assert_eq!(christmas_tree(&[]).0, 2);
+        assert_eq!(christmas_tree(&["-x"]).0, 2);
+        let file = dir.file("luci.txt", "root\n");
+        assert_eq!(christmas_tree(&[&file, "blink"]).0, 2);
+        assert_eq!(christmas_tree(&[&file, "toggle"]).0, 2);
+        let (code, _, err) = christmas_tree(&[&dir.file("rotto.txt", "a\nb\n")]);
+        assert_eq!(code, 2);
+        assert!(err.ends_with("rotto.txt: line 2: the tree already has a root (a)\n"), "{}", err);
+        assert_eq!(christmas_tree(&["--help"]).0, 0);
+    }
+}
@@ -0,0 +1,272 @@
+// le installazioni di luci sono descritte in file di testo, un nodo per riga:
+//
+//     # salotto
+//     root on
+//       finestra on
+//         tenda
+//       mensola
+//
+// i figli sono indentati più del padre, "on" accende l'interruttore (senza è spento), le
+// righe vuote e quelle che iniziano con # non contano. Lo stesso albero in JSON è una lista
+// piatta, ogni nodo dopo il padre: [{"name": "root", "on": true}, {"name": "finestra",
+// "parent": "root", "on": true}, ...], così la profondità dell'albero non incontra il limite
+// di annidamento di serde_json (128 livelli). Si legge anche il formato annidato
+// {"name": "root", "on": true, "children": [...]}, ma solo entro quel limite.
+// render() disegna l'albero come tree(1), ● per le luci accese e ○ per quelle spente
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{Value, json};
+
+use crate::{Albero, AlberoError};
+
+fn errore(line: usize, message: impl fmt::Display) -> AlberoError {
+    AlberoError::Parse(format!("line {}: {}", line, message))
+}
+
+impl FromStr for Albero {
+    type Err = AlberoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut albero = Albero::new();
+        let mut accesi = Vec::new();
+        let mut aperti: Vec<(usize, String)> = Vec::new(); // (indentazione, nome) dalla radice in giù
+        for (i, riga) in s.lines().enumerate() {
+            let testo = riga.trim();
+            if testo.is_empty() || testo.starts_with('#') {
+                continue;
+            }
+            let indent = riga.len() - riga.trim_start().len();
+            let mut parole = testo.split_whitespace();
+            let nome = parole.next().expect("riga non vuota");
+            match (parole.next(), parole.next()) {
+                (None, _) | (Some("off"), None) => {}
+                (Some("on"), None) => accesi.push(nome.to_string()),
+                (Some(p), None) => return Err(errore(i + 1, format!("expected on or off, found {}", p))),
+                (Some(_), Some(_)) => return Err(errore(i + 1, "too many words, node names cannot contain spaces")),
+            }
+            // il padre è l'ultimo nodo aperto meno indentato
+            while aperti.last().is_some_and(|(n, _)| *n >= indent) {
+                aperti.pop();
+            }
+            match aperti.last() {
+                Some((_, padre)) => albero.add(padre, nome),
+                None => albero.add_root(nome),
+            }
+            .map_err(|e| errore(i + 1, e))?;
+            aperti.push((indent, nome.to_string()));
+        }
+        albero.accendi(accesi);
+        Ok(albero)
+    }
+}
+
+// il formato indentato, due spazi per livello
+impl fmt::Display for Albero {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut da_visitare: Vec<(&str, usize)> = self.root().map(|r| (r, 0)).into_iter().collect();
+        while let Some((n, livello)) = da_visitare.pop() {
+            write!(f, "{:width$}{}", "", n, width = livello * 2)?;
+            writeln!(f, "{}", if self.peek(n) { " on" } else { "" })?;
+            let figli = self.children(n).unwrap_or_default();
+            da_visitare.extend(figli.iter().rev().map(|c| (c.as_str(), livello + 1)));
+        }
+        Ok(())
+    }
+}
+
+fn nome_json(v: &Value) -> Result<&str, AlberoError> {
+    v["name"].as_str().ok_or_else(|| AlberoError::Parse(format!("every node needs a \"name\" string: {}", v)))
+}
+
+fn acceso_json(nome: &str, v: &Value) -> Result<bool, AlberoError> {
+    match &v["on"] {
+        Value::Bool(on) => Ok(*on),
+        Value::Null => Ok(false),
+        on => Err(AlberoError::Parse(format!("\"on\" of {} must be true or false, not {}", nome, on))),
+    }
+}
+
+impl Albero {
+    // gli interruttori letti da un file si accendono senza guardare gli antenati
+    fn accendi(&mut self, accesi: Vec<String>) {
+        for n in &accesi {
+            self.nodi.get_mut(n).expect("nodo appena aggiunto").acceso = true;
+        }
+        if let Some(radice) = self.radice.clone() {
+            self.propaga(&radice, false);
+        }
+    }
+
+    pub fn from_json(s: &str) -> Result<Albero, AlberoError> {
+        let radice: Value = serde_json::from_str(s).map_err(|e| errore(e.line(), e))?;
+        let mut albero = Albero::new();
+        let mut accesi = Vec::new();
+        // la lista piatta scritta da to_json
+        if let Value::Array(nodi) = &radice {
+            for v in nodi {
+                let nome = nome_json(v)?;
+                match &v["parent"] {
+                    Value::String(p) => albero.add(p, nome)?,
+                    Value::Null => albero.add_root(nome)?,
+                    p => return Err(AlberoError::Parse(format!("\"parent\" of {} must be a string, not {}", nome, p))),
+                }
+                if acceso_json(nome, v)? {
+                    accesi.push(nome.to_string());
+                }
+            }
+            albero.accendi(accesi);
+            return Ok(albero);
+        }
+        let mut da_visitare: Vec<(Option<String>, &Value)> = vec![(None, &radice)];
+        while let Some((padre, v)) = da_visitare.pop() {
+            let nome = nome_json(v)?;
+            match &padre {
+                Some(p) => albero.add(p, nome)?,
+                None => albero.add_root(nome)?,
+            }
+            if acceso_json(nome, v)? {
+                accesi.push(nome.to_string());
+            }
+            match &v["children"] {
+                Value::Array(figli) => da_visitare.extend(figli.iter().rev().map(|c| (Some(nome.to_string()), c))),
+                Value::Null => {}
+                c => return Err(AlberoError::Parse(format!("\"children\" of {} must be an array, not {}", nome, c))),
+            }
+        }
+        albero.accendi(accesi);
+        Ok(albero)
+    }
+
+    // in profondità, quindi ogni padre viene prima dei figli e i figli restano in ordine
+    pub fn to_json(&self) -> String {
+        let nodi: Vec<Value> = self.root().map(|r| self.subtree(r)).unwrap_or_default().iter()
+            .map(|n| match self.parent(n) {
+                Some(p) => json!({ "name": n, "parent": p, "on": self.peek(n) }),
+                None => json!({ "name": n, "on": self.peek(n) }),
+            })
+            .collect();
+        serde_json::to_string_pretty(&nodi).expect("un Value si scrive sempre")
+    }
+
+    // l'albero come lo disegna tree(1):
+    // ● root
+    // ├── ● a
+    // │   └── ○ a1
+    // └── ○ b
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let Some(radice) = self.root() else { return out };
+        let luce = |n: &str| if self.is_lit(n) { '●' } else { '○' };
+        out.push_str(&format!("{} {}\n", luce(radice), radice));
+        // (nodo, righe verticali dei livelli sopra, ultimo figlio)
+        let figli = self.children(radice).unwrap_or_default();
+        let mut da_visitare: Vec<(&str, String, bool)> = figli.iter().enumerate().rev()
+            .map(|(i, c)| (c.as_str(), String::new(), i + 1 == figli.len()))
+            .collect();
+        while let Some((n, prefisso, ultimo)) = da_visitare.pop() {
+            let ramo = if ultimo { "└── " } else { "├── " };
+            out.push_str(&format!("{}{}{} {}\n", prefisso, ramo, luce(n), n));
+            let prefisso = prefisso + if ultimo { "    " } else { "│   " };
+            let figli = self.children(n).unwrap_or_default();
+            da_visitare.extend(figli.iter().enumerate().rev().map(|(i, c)| (c.as_str(), prefisso.clone(), i + 1 == figli.len())));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALOTTO: &str = "\
+# salotto
+root on
+  finestra on
+    tenda on
+    vaso
+  mensola
+
+  porta on
+";
+
+    #[test]
+    fn test_text_round_trip() {
+        let albero: Albero = SALOTTO.parse().unwrap();
+        assert_eq!(albero.children("root").unwrap(), ["finestra", "mensola", "porta"]);
+        assert_eq!(albero.children("finestra").unwrap(), ["tenda", "vaso"]);
+        assert!(albero.is_lit("tenda") && !albero.is_lit("vaso"));
+        assert_eq!(albero.lit_count(), 4);
+        let testo = albero.to_string();
+        assert_eq!(testo, "root on\n  finestra on\n    tenda on\n    vaso\n  mensola\n  porta on\n");
+        assert_eq!(testo.parse::<Albero>().unwrap().to_string(), testo);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("a\n  b\n  b".parse::<Albero>().unwrap_err(), AlberoError::Parse("line 3: node b already exists".to_string()));
+        assert_eq!("a\nb".parse::<Albero>().unwrap_err(), AlberoError::Parse("line 2: the tree already has a root (a)".to_string()));
+        assert!(matches!("a acceso".parse::<Albero>(), Err(AlberoError::Parse(m)) if m.starts_with("line 1: expected on")));
+        // un interruttore acceso sotto uno spento: la luce resta spenta
+        let albero: Albero = "a\n\tb on".parse().unwrap();
+        assert!(albero.peek("b") && !albero.is_lit("b"));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let albero: Albero = SALOTTO.parse().unwrap();
+        let json = albero.to_json();
+        let letto = Albero::from_json(&json).unwrap();
+        assert_eq!(letto.to_string(), albero.to_string());
+        let albero = Albero::from_json(r#"{"name": "r", "children": [{"name": "x", "on": true}]}"#).unwrap();
+        assert_eq!(albero.to_string(), "r\n  x on\n");
+        assert!(matches!(Albero::from_json(r#"{"children": []}"#), Err(AlberoError::Parse(_))));
+        assert!(matches!(Albero::from_json("{"), Err(AlberoError::Parse(m)) if m.starts_with("line 1")));
+        assert!(matches!(Albero::from_json(r#"[{"name": "x", "parent": "nessuno"}]"#), Err(AlberoError::UnknownFather(_))));
+        assert_eq!(Albero::new().to_json(), "[]");
+        assert!(Albero::from_json("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_deep_tree_round_trip() {
+        // ben oltre i 128 livelli che serde_json accetta per i valori annidati
+        let mut albero = Albero::new();
+        albero.add_root("n0").unwrap();
+        for i in 1..1000 {
+            albero.add(&format!("n{}", i - 1), &format!("n{}", i)).unwrap();
+        }
+        albero.all_on();
+        let letto = Albero::from_json(&albero.to_json()).unwrap();
+        assert_eq!(letto.to_string(), albero.to_string());
+        assert_eq!(letto.lit_count(), 1000);
+        assert_eq!(albero.to_string().parse::<Albero>().unwrap().to_string(), albero.to_string());
+    }
+
+    #[test]
+    fn test_names_that_cannot_be_written() {
+        let mut albero = Albero::new();
+        assert_eq!(albero.add_root("la radice"), Err(AlberoError::InvalidName("la radice".to_string())));
+        albero.add_root("root").unwrap();
+        for nome in ["", "#commento", "a\tb", "luce\n"] {
+            assert_eq!(albero.add("root", nome), Err(AlberoError::InvalidName(nome.to_string())));
+        }
+        assert!(albero.add("root", "a#b").is_ok()); // # conta solo all'inizio della riga
+        assert!(matches!(Albero::from_json(r#"{"name": "due parole"}"#), Err(AlberoError::InvalidName(_))));
+        assert_eq!(albero.to_string().parse::<Albero>().unwrap().to_string(), albero.to_string());
+    }
+
+    #[test]
+    fn test_render() {
+        let albero: Albero = SALOTTO.parse().unwrap();
+        assert_eq!(albero.render(), "\
+● root
+├── ● finestra
+│   ├── ● tenda
+│   └── ○ vaso
+├── ○ mensola
+└── ● porta
+");
+        assert_eq!(Albero::new().render(), "");
+    }
+}
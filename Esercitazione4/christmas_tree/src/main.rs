@@ -1,87 +1,453 @@
-use std::collections::{HashMap};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
 
+mod cli;
+mod file;
+pub mod shared;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlberoError {
+    DuplicateNode(String), // esiste già un nodo con questo nome
+    UnknownNode(String),
+    UnknownFather(String),
+    Cycle { node: String, father: String }, // father è node o un suo discendente
+    RootExists(String),
+    AncestorOff(String), // il primo antenato spento trovato risalendo verso la radice
+    Parse(String), // un file che non descrive un albero, vedi file.rs
+    InvalidName(String), // vuoto, con spazi o che inizia con #: il formato di testo non lo rileggerebbe
+}
+
+impl fmt::Display for AlberoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlberoError::DuplicateNode(n) => write!(f, "node {} already exists", n),
+            AlberoError::UnknownNode(n) => write!(f, "unknown node {}", n),
+            AlberoError::UnknownFather(n) => write!(f, "unknown father {}", n),
+            AlberoError::Cycle { node, father } => write!(f, "{} cannot be a child of {}: it would create a cycle", node, father),
+            AlberoError::RootExists(n) => write!(f, "the tree already has a root ({})", n),
+            AlberoError::AncestorOff(n) => write!(f, "ancestor {} is off", n),
+            AlberoError::Parse(msg) => write!(f, "{}", msg),
+            AlberoError::InvalidName(n) => write!(f, "invalid node name {:?}: it cannot be empty, contain spaces or start with #", n),
+        }
+    }
+}
+
+impl std::error::Error for AlberoError {}
+
+// i nomi devono sopravvivere al giro in file.rs: una parola per nome, # inizia un commento
+pub(crate) fn valida_nome(node: &str) -> Result<(), AlberoError> {
+    if node.is_empty() || node.starts_with('#') || node.chars().any(char::is_whitespace) {
+        return Err(AlberoError::InvalidName(node.to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Nodo {
+    padre: Option<String>, // None solo per la radice
+    figli: Vec<String>, // in ordine di inserimento
+    acceso: bool, // stato dell'interruttore
+    illuminato: bool, // acceso con tutti gli antenati accesi, tenuto aggiornato a ogni modifica
+}
+
+#[derive(Debug, Default)]
 pub struct Albero {
-    relazioni: HashMap<String, Vec<String>>, // Relazioni padre-figlio
-    stati: HashMap<String, bool>,            // Stato dell'interruttore di ogni nodo
+    nodi: HashMap<String, Nodo>,
+    radice: Option<String>,
+    illuminati: usize, // quanti nodi hanno illuminato == true
 }
 
 impl Albero {
     pub fn new() -> Albero {
-        Albero {
-            relazioni: HashMap::new(),
-            stati: HashMap::new(),
-        }
-    }
-    // nota: aggiustare mutabilità dove necessario gestire errori in caso
-    // di collisioni, valori mancanti
-    // aggiungi un nodo figlio del nodo father
-    pub fn add(&mut self, father: &str, node: &str) {
-        self.relazioni.insert(father.to_string(), vec![node.to_string()]);
-        self.stati.insert(node.to_string(), false); // inizializza lo stato dell'interruttore a false (spento)
-    }
-    // togli un nodo e tutti gli eventuali rami collegati
-    pub fn remove(&mut self, node: &str) {
-        self.relazioni.remove(node);
-        self.stati.remove(node);
-    }
-    // commuta l’interruttore del nodo (che può essere on off) e restituisci il nuovo valore
-    pub fn toggle(&mut self, node: &str) -> bool {
-        // controlla se il nodo esiste
-        if let Some(stato) = self.stati.get(node) {
-            // commuta lo stato
-            // controlla lo stato di tutti i nodi fino alla radice, deve essere on
-            if let Some(padre) = self.relazioni.keys().find(|&k| self.relazioni[k].contains(&node.to_string())) {
-                if let Some(stato_padre) = self.stati.get(padre) {
-                    if !stato_padre {
-                        return false; // se il padre è spento, non posso accendere il figlio
-                    }
-                }
+        Albero::default()
+    }
+
+    // la radice è l'unico nodo senza padre, con l'interruttore spento come gli altri
+    pub fn add_root(&mut self, node: &str) -> Result<(), AlberoError> {
+        valida_nome(node)?;
+        if let Some(radice) = &self.radice {
+            return Err(AlberoError::RootExists(radice.clone()));
+        }
+        self.nodi.insert(node.to_string(), Nodo { padre: None, figli: Vec::new(), acceso: false, illuminato: false });
+        self.radice = Some(node.to_string());
+        Ok(())
+    }
+
+    // aggiungi un nodo figlio del nodo father (in coda agli altri figli)
+    pub fn add(&mut self, father: &str, node: &str) -> Result<(), AlberoError> {
+        valida_nome(node)?;
+        if father == node {
+            return Err(AlberoError::Cycle { node: node.to_string(), father: father.to_string() });
+        }
+        if self.nodi.contains_key(node) {
+            return Err(AlberoError::DuplicateNode(node.to_string()));
+        }
+        let padre = self.nodi.get_mut(father).ok_or_else(|| AlberoError::UnknownFather(father.to_string()))?;
+        padre.figli.push(node.to_string());
+        self.nodi.insert(node.to_string(), Nodo { padre: Some(father.to_string()), figli: Vec::new(), acceso: false, illuminato: false });
+        Ok(())
+    }
+
+    // togli un nodo e tutti i rami collegati, restituisce i nodi tolti (prima il nodo, poi
+    // i discendenti in profondità)
+    pub fn remove(&mut self, node: &str) -> Result<Vec<String>, AlberoError> {
+        let nodo = self.nodi.get(node).ok_or_else(|| AlberoError::UnknownNode(node.to_string()))?;
+        match nodo.padre.clone() {
+            Some(padre) => self.nodi.get_mut(&padre).expect("il padre esiste").figli.retain(|f| f != node),
+            None => self.radice = None,
+        }
+        let rimossi = self.subtree(node);
+        for n in &rimossi {
+            if self.nodi.remove(n).is_some_and(|n| n.illuminato) {
+                self.illuminati -= 1;
             }
-            let nuovo_stato = !stato;
-            // aggiorna lo stato nel dizionario
-            self.stati.insert(node.to_string(), nuovo_stato);
-            return nuovo_stato;
         }
-        false // se il nodo non esiste, restituisci false
+        Ok(rimossi)
+    }
+
+    // sposta node (con i suoi rami) sotto new_father
+    pub fn move_node(&mut self, node: &str, new_father: &str) -> Result<(), AlberoError> {
+        let padre = self.nodi.get(node).ok_or_else(|| AlberoError::UnknownNode(node.to_string()))?.padre.clone();
+        if !self.nodi.contains_key(new_father) {
+            return Err(AlberoError::UnknownFather(new_father.to_string()));
+        }
+        // new_father non può stare sotto node: risalendo da new_father non si deve incontrare node
+        if new_father == node || self.ancestors(new_father).any(|a| a == node) {
+            return Err(AlberoError::Cycle { node: node.to_string(), father: new_father.to_string() });
+        }
+        // la radice sotto un altro nodo sarebbe sempre un ciclo, già escluso sopra
+        let padre = padre.expect("solo la radice non ha padre");
+        self.nodi.get_mut(&padre).expect("il padre esiste").figli.retain(|f| f != node);
+        self.nodi.get_mut(new_father).expect("controllato sopra").figli.push(node.to_string());
+        self.nodi.get_mut(node).expect("controllato sopra").padre = Some(new_father.to_string());
+        self.propaga(node, true);
+        Ok(())
+    }
+
+    // commuta l'interruttore del nodo e restituisci il nuovo valore: si può commutare solo
+    // se tutti i nodi fino alla radice sono accesi
+    pub fn toggle(&mut self, node: &str) -> Result<bool, AlberoError> {
+        if !self.nodi.contains_key(node) {
+            return Err(AlberoError::UnknownNode(node.to_string()));
+        }
+        if let Some(spento) = self.ancestors(node).find(|a| !self.nodi[*a].acceso) {
+            return Err(AlberoError::AncestorOff(spento.to_string()));
+        }
+        let nodo = self.nodi.get_mut(node).expect("controllato sopra");
+        nodo.acceso = !nodo.acceso;
+        let acceso = nodo.acceso;
+        self.propaga(node, true);
+        Ok(acceso)
+    }
+
+    // come toggle, ma tutti gli interruttori del sottoalbero prendono il nuovo valore di node
+    pub fn toggle_subtree(&mut self, node: &str) -> Result<bool, AlberoError> {
+        if !self.nodi.contains_key(node) {
+            return Err(AlberoError::UnknownNode(node.to_string()));
+        }
+        if let Some(spento) = self.ancestors(node).find(|a| !self.nodi[*a].acceso) {
+            return Err(AlberoError::AncestorOff(spento.to_string()));
+        }
+        let acceso = !self.nodi[node].acceso;
+        self.set_subtree(node, acceso);
+        Ok(acceso)
+    }
+
+    // accende (spegne) tutti gli interruttori: con all_on sono illuminati tutti i nodi
+    pub fn all_on(&mut self) {
+        if let Some(radice) = self.radice.clone() {
+            self.set_subtree(&radice, true);
+        }
+    }
+
+    pub fn all_off(&mut self) {
+        if let Some(radice) = self.radice.clone() {
+            self.set_subtree(&radice, false);
+        }
+    }
+
+    fn set_subtree(&mut self, node: &str, acceso: bool) {
+        for n in self.subtree(node) {
+            self.nodi.get_mut(&n).expect("nodo del sottoalbero").acceso = acceso;
+        }
+        self.propaga(node, false);
     }
-    // restituisci se la luce è accesa e spenta
+
+    // ricalcola illuminato nel sottoalbero di node dopo che sono cambiati l'interruttore di
+    // node (pota = true) o quelli di tutto il sottoalbero (pota = false): con pota si scende
+    // solo nei nodi che hanno cambiato stato, gli altri sottoalberi restano come sono, così
+    // un toggle costa quanto i nodi che si accendono o si spengono
+    fn propaga(&mut self, node: &str, pota: bool) {
+        let mut da_visitare = vec![node.to_string()];
+        while let Some(n) = da_visitare.pop() {
+            let padre_illuminato = self.parent(&n).is_none_or(|p| self.nodi[p].illuminato);
+            let nodo = self.nodi.get_mut(&n).expect("nodo del sottoalbero");
+            let illuminato = nodo.acceso && padre_illuminato;
+            if illuminato != nodo.illuminato {
+                nodo.illuminato = illuminato;
+                if illuminato { self.illuminati += 1 } else { self.illuminati -= 1 }
+            } else if pota {
+                continue;
+            }
+            da_visitare.extend(nodo.figli.iter().cloned());
+        }
+    }
+
+    // restituisci se l'interruttore del nodo è acceso o spento, false se il nodo non esiste
     pub fn peek(&self, node: &str) -> bool {
-        // controlla se il nodo esiste
-        if let Some(stato) = self.stati.get(node) {
-            return *stato;
+        self.nodi.get(node).is_some_and(|n| n.acceso)
+    }
+
+    // la luce è davvero accesa: l'interruttore del nodo e quelli di tutti gli antenati
+    // sono accesi, O(1)
+    pub fn is_lit(&self, node: &str) -> bool {
+        self.nodi.get(node).is_some_and(|n| n.illuminato)
+    }
+
+    pub fn lit_count(&self) -> usize {
+        self.illuminati
+    }
+
+    // i nodi illuminati in profondità: sono la parte alta dell'albero, quindi si visitano
+    // solo loro e i loro figli
+    pub fn lit(&self) -> impl Iterator<Item = &str> + '_ {
+        let mut da_visitare: Vec<&str> = self.radice.iter().map(|r| r.as_str()).filter(|r| self.is_lit(r)).collect();
+        std::iter::from_fn(move || {
+            let n = da_visitare.pop()?;
+            da_visitare.extend(self.nodi[n].figli.iter().rev().map(|f| f.as_str()).filter(|f| self.is_lit(f)));
+            Some(n)
+        })
+    }
+
+    pub fn contains(&self, node: &str) -> bool {
+        self.nodi.contains_key(node)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodi.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodi.is_empty()
+    }
+
+    pub fn root(&self) -> Option<&str> {
+        self.radice.as_deref()
+    }
+
+    pub fn parent(&self, node: &str) -> Option<&str> {
+        self.nodi.get(node)?.padre.as_deref()
+    }
+
+    pub fn children(&self, node: &str) -> Option<&[String]> {
+        self.nodi.get(node).map(|n| n.figli.as_slice())
+    }
+
+    // il padre, il padre del padre, ... fino alla radice (esclusi node)
+    pub fn ancestors<'a>(&'a self, node: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let mut corrente = self.parent(node);
+        std::iter::from_fn(move || {
+            let n = corrente?;
+            corrente = self.parent(n);
+            Some(n)
+        })
+    }
+
+    // node e tutti i suoi discendenti, in profondità
+    pub fn subtree(&self, node: &str) -> Vec<String> {
+        let mut visitati = Vec::new();
+        let mut da_visitare = vec![node];
+        while let Some(n) = da_visitare.pop() {
+            let Some(nodo) = self.nodi.get(n) else { continue };
+            visitati.push(n.to_string());
+            // al contrario, così il primo figlio è visitato per primo
+            da_visitare.extend(nodo.figli.iter().rev().map(|f| f.as_str()));
         }
-        false // se il nodo non esiste, restituisci false
+        visitati
     }
 }
 
 
+// vedi cli.rs, ad esempio: christmas_tree luci.txt toggle root toggle finestra
 fn main() {
-    let mut albero = Albero::new();
+    let code = cli::run(std::env::args().skip(1), &mut io::stdout().lock(), &mut io::stderr().lock());
+    std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Aggiungiamo alcuni nodi
-    albero.add("root", "figlio1");
-    albero.add("figlio1", "figlio2");
+    fn albero() -> Albero {
+        // root -> a -> (a1, a2 -> a21), root -> b
+        let mut albero = Albero::new();
+        albero.add_root("root").unwrap();
+        for (padre, figlio) in [("root", "a"), ("a", "a1"), ("a", "a2"), ("a2", "a21"), ("root", "b")] {
+            albero.add(padre, figlio).unwrap();
+        }
+        albero
+    }
 
-    println!("Stato iniziale figlio1: {}", albero.peek("figlio1")); // false
-    println!("Stato iniziale figlio2: {}", albero.peek("figlio2")); // false
+    #[test]
+    fn test_add_keeps_all_children() {
+        let mut albero = albero();
+        assert_eq!(albero.children("a").unwrap(), ["a1", "a2"]);
+        assert_eq!(albero.parent("a21"), Some("a2"));
+        assert_eq!(albero.ancestors("a21").collect::<Vec<_>>(), vec!["a2", "a", "root"]);
+        assert_eq!(albero.add("root", "a1"), Err(AlberoError::DuplicateNode("a1".to_string())));
+        assert_eq!(albero.add("x", "y"), Err(AlberoError::UnknownFather("x".to_string())));
+        assert!(matches!(albero.add("z", "z"), Err(AlberoError::Cycle { .. })));
+        assert_eq!(albero.add_root("r2"), Err(AlberoError::RootExists("root".to_string())));
+        assert_eq!(albero.len(), 6);
+    }
 
-    // Commuta lo stato di figlio1
-    let nuovo_stato1 = albero.toggle("figlio1");
-    println!("Nuovo stato figlio1 dopo toggle: {}", nuovo_stato1); // true
+    #[test]
+    fn test_remove_subtree() {
+        let mut albero = albero();
+        assert_eq!(albero.remove("a").unwrap(), vec!["a", "a1", "a2", "a21"]);
+        assert_eq!(albero.len(), 2);
+        assert!(!albero.contains("a21"));
+        assert_eq!(albero.children("root").unwrap(), ["b"]);
+        assert_eq!(albero.remove("a"), Err(AlberoError::UnknownNode("a".to_string())));
+        // il nome si può riusare
+        albero.add("b", "a21").unwrap();
+        assert_eq!(albero.remove("root").unwrap(), vec!["root", "b", "a21"]);
+        assert!(albero.is_empty() && albero.root().is_none());
+    }
 
-    // Commuta di nuovo per tornare allo stato originale
-    let nuovo_stato2 = albero.toggle("figlio1");
-    println!("Nuovo stato figlio1 dopo secondo toggle: {}", nuovo_stato2); // false
+    #[test]
+    fn test_move_node_refuses_cycles() {
+        let mut albero = albero();
+        albero.move_node("a2", "b").unwrap();
+        assert_eq!(albero.children("b").unwrap(), ["a2"]);
+        assert_eq!(albero.subtree("b"), vec!["b", "a2", "a21"]);
+        assert!(matches!(albero.move_node("b", "a21"), Err(AlberoError::Cycle { .. })));
+        assert!(matches!(albero.move_node("root", "a"), Err(AlberoError::Cycle { .. })));
+        assert_eq!(albero.move_node("a", "x"), Err(AlberoError::UnknownFather("x".to_string())));
+    }
 
-    // Peek su un nodo non esistente
-    println!("Stato nodo inesistente: {}", albero.peek("inesistente")); // false
+    #[test]
+    fn test_toggle_checks_all_ancestors() {
+        let mut albero = albero();
+        assert_eq!(albero.toggle("a21"), Err(AlberoError::AncestorOff("a2".to_string())));
+        albero.toggle("root").unwrap();
+        albero.toggle("a").unwrap();
+        // a2 è spento: conta anche se root e a sono accesi
+        assert_eq!(albero.toggle("a21"), Err(AlberoError::AncestorOff("a2".to_string())));
+        assert_eq!(albero.toggle("a2"), Ok(true));
+        assert_eq!(albero.toggle("a21"), Ok(true));
+        // un antenato lontano spento blocca ancora
+        albero.toggle("a").unwrap();
+        assert_eq!(albero.toggle("a21"), Err(AlberoError::AncestorOff("a".to_string())));
+        assert!(albero.peek("a21"));
+        assert_eq!(albero.toggle("nessuno"), Err(AlberoError::UnknownNode("nessuno".to_string())));
+    }
 
-    // Rimuoviamo un nodo
-    albero.remove("figlio1");
+    // lo stato effettivo calcolato risalendo gli antenati, per controllare la cache
+    fn illuminato_lento(albero: &Albero, node: &str) -> bool {
+        albero.peek(node) && albero.ancestors(node).all(|a| albero.peek(a))
+    }
 
-    // Dopo la rimozione
-    println!("Stato figlio1 dopo rimozione: {}", albero.peek("figlio1")); // false
-    println!("Stato figlio2 dopo rimozione padre: {}", albero.peek("figlio2")); // true (non è stato rimosso direttamente!)
+    #[test]
+    fn test_lit_propagates_to_descendants() {
+        let mut albero = albero();
+        albero.toggle("root").unwrap();
+        albero.toggle("a").unwrap();
+        albero.toggle("a2").unwrap();
+        albero.toggle("a21").unwrap();
+        assert_eq!(albero.lit().collect::<Vec<_>>(), vec!["root", "a", "a2", "a21"]);
+        // spegnere a spegne le luci sotto, ma gli interruttori restano accesi
+        albero.toggle("a").unwrap();
+        assert!(albero.peek("a21") && !albero.is_lit("a21"));
+        assert_eq!(albero.lit_count(), 1);
+        albero.toggle("a").unwrap();
+        assert_eq!(albero.lit_count(), 4);
+        // spostando un ramo sotto un nodo spento le sue luci si spengono
+        albero.move_node("a2", "b").unwrap();
+        assert_eq!(albero.lit().collect::<Vec<_>>(), vec!["root", "a"]);
+        assert_eq!(albero.remove("a").unwrap().len(), 2);
+        assert_eq!(albero.lit_count(), 1);
+    }
 
-    // Nota: figlio2 rimane nello stato perché non è stato direttamente rimosso.
+    #[test]
+    fn test_bulk_operations() {
+        let mut albero = albero();
+        assert_eq!(albero.toggle_subtree("a"), Err(AlberoError::AncestorOff("root".to_string())));
+        albero.all_on();
+        assert_eq!(albero.lit_count(), albero.len());
+        assert_eq!(albero.toggle_subtree("a"), Ok(false));
+        assert_eq!(albero.lit().collect::<Vec<_>>(), vec!["root", "b"]);
+        assert!(!albero.peek("a21"));
+        assert_eq!(albero.toggle_subtree("a"), Ok(true));
+        assert_eq!(albero.lit_count(), 6);
+        albero.all_off();
+        assert_eq!(albero.lit_count(), 0);
+        assert_eq!(albero.lit().count(), 0);
+        assert!(albero.nodi.values().all(|n| !n.acceso));
+    }
+
+    #[test]
+    fn test_cache_matches_ancestors() {
+        // un albero casuale di 2000 nodi e operazioni casuali (xorshift, senza dipendenze)
+        let mut seme: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut caso = move |n: usize| {
+            seme ^= seme << 13;
+            seme ^= seme >> 7;
+            seme ^= seme << 17;
+            (seme % n as u64) as usize
+        };
+        let mut albero = Albero::new();
+        albero.add_root("n0").unwrap();
+        for i in 1..2000 {
+            albero.add(&format!("n{}", caso(i)), &format!("n{}", i)).unwrap();
+        }
+        for _ in 0..3000 {
+            let n = format!("n{}", caso(2000));
+            if !albero.contains(&n) {
+                continue;
+            }
+            let _ = match caso(10) {
+                0 => albero.toggle_subtree(&n).map(|_| ()),
+                1 => albero.move_node(&n, &format!("n{}", caso(2000))),
+                2 if caso(20) == 0 => albero.remove(&n).map(|_| ()),
+                _ => {
+                    // le luci si accendono dall'alto, come farebbe un utente
+                    for a in albero.ancestors(&n).map(String::from).collect::<Vec<_>>().iter().rev() {
+                        if !albero.peek(a) {
+                            albero.toggle(a).unwrap();
+                        }
+                    }
+                    albero.toggle(&n).map(|_| ())
+                }
+            };
+        }
+        let lenti: Vec<&String> = albero.nodi.keys().filter(|n| illuminato_lento(&albero, n)).collect();
+        assert_eq!(lenti.len(), albero.lit_count());
+        assert!(lenti.iter().all(|n| albero.is_lit(n)));
+        assert_eq!(albero.lit().count(), albero.lit_count());
+    }
+
+    #[test]
+    fn test_large_tree() {
+        // 300000 nodi: 1000 rami da 300 nodi in fila
+        let mut albero = Albero::new();
+        albero.add_root("root").unwrap();
+        for r in 0..1000 {
+            albero.add("root", &format!("r{}", r)).unwrap();
+            for i in 1..300 {
+                let padre = if i == 1 { format!("r{}", r) } else { format!("r{}-{}", r, i - 1) };
+                albero.add(&padre, &format!("r{}-{}", r, i)).unwrap();
+            }
+        }
+        assert_eq!(albero.len(), 1 + 1000 * 300);
+        albero.all_on();
+        assert_eq!(albero.lit_count(), albero.len());
+        // spegnere la radice spegne tutto, riaccenderla riaccende tutto (gli interruttori sono accesi)
+        albero.toggle("root").unwrap();
+        assert_eq!(albero.lit_count(), 0);
+        albero.toggle("root").unwrap();
+        assert_eq!(albero.lit_count(), albero.len());
+        assert!(albero.is_lit("r999-299"));
+    }
 }
@@ -0,0 +1,296 @@
+// Albero condiviso tra thread, per il servizio che comanda le luci
+// - ogni nodo ha il suo RwLock: toggle, peek e add su rami diversi vanno in parallelo
+// - i nomi sono in una mappa divisa in SHARDS parti, ognuna col suo lock, così le add non
+//   si mettono in fila su un unico lock
+// - i lock dei nodi si prendono sempre dalla radice verso il basso: due thread non possono
+//   aspettarsi a vicenda (chi aspetta aspetta sempre un nodo più profondo di quelli che ha)
+// - is_lit e toggle tengono in lettura i lock di tutti gli antenati mentre guardano il
+//   nodo: nessuno può spegnere un antenato nel frattempo, quindi un nodo non risulta mai
+//   acceso con un antenato spento
+// il padre di un nodo non cambia mai (non ci sono remove e move_node come in Albero), per
+// questo si può risalire verso la radice senza lock
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread;
+
+use crate::{AlberoError, valida_nome};
+
+const SHARDS: usize = 16;
+
+struct Stato {
+    acceso: bool,
+    figli: Vec<String>,
+}
+
+struct SharedNodo {
+    nome: String,
+    padre: Option<Arc<SharedNodo>>,
+    stato: RwLock<Stato>,
+}
+
+impl SharedNodo {
+    // gli antenati dalla radice al padre
+    fn antenati(self: &Arc<Self>) -> Vec<Arc<SharedNodo>> {
+        let mut antenati = Vec::new();
+        let mut corrente = self.padre.clone();
+        while let Some(n) = corrente {
+            corrente = n.padre.clone();
+            antenati.push(n);
+        }
+        antenati.reverse();
+        antenati
+    }
+}
+
+// i lock in lettura degli antenati, presi dalla radice in giù; Err col primo spento
+fn blocca_accesi(antenati: &[Arc<SharedNodo>]) -> Result<Vec<RwLockReadGuard<'_, Stato>>, AlberoError> {
+    let mut guardie = Vec::with_capacity(antenati.len());
+    for a in antenati {
+        let stato = a.stato.read().unwrap();
+        if !stato.acceso {
+            return Err(AlberoError::AncestorOff(a.nome.clone()));
+        }
+        guardie.push(stato);
+    }
+    Ok(guardie)
+}
+
+pub struct SharedAlbero {
+    nomi: Vec<RwLock<HashMap<String, Arc<SharedNodo>>>>,
+    radice: Mutex<Option<String>>,
+}
+
+impl Default for SharedAlbero {
+    fn default() -> Self {
+        SharedAlbero { nomi: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(), radice: Mutex::new(None) }
+    }
+}
+
+impl SharedAlbero {
+    pub fn new() -> Self {
+        SharedAlbero::default()
+    }
+
+    fn shard(&self, node: &str) -> &RwLock<HashMap<String, Arc<SharedNodo>>> {
+        let mut hasher = DefaultHasher::new();
+        node.hash(&mut hasher);
+        &self.nomi[hasher.finish() as usize % SHARDS]
+    }
+
+    fn get(&self, node: &str) -> Option<Arc<SharedNodo>> {
+        self.shard(node).read().unwrap().get(node).cloned()
+    }
+
+    pub fn add_root(&self, node: &str) -> Result<(), AlberoError> {
+        valida_nome(node)?;
+        let mut radice = self.radice.lock().unwrap();
+        if let Some(r) = radice.as_ref() {
+            return Err(AlberoError::RootExists(r.clone()));
+        }
+        let nodo = SharedNodo { nome: node.to_string(), padre: None, stato: RwLock::new(Stato { acceso: false, figli: Vec::new() }) };
+        self.shard(node).write().unwrap().insert(node.to_string(), Arc::new(nodo));
+        *radice = Some(node.to_string());
+        Ok(())
+    }
+
+    // blocca in scrittura solo il padre e la parte della mappa con il nuovo nome
+    pub fn add(&self, father: &str, node: &str) -> Result<(), AlberoError> {
+        valida_nome(node)?;
+        if father == node {
+            return Err(AlberoError::Cycle { node: node.to_string(), father: father.to_string() });
+        }
+        let padre = self.get(father).ok_or_else(|| AlberoError::UnknownFather(father.to_string()))?;
+        let mut stato_padre = padre.stato.write().unwrap();
+        let mut shard = self.shard(node).write().unwrap();
+        if shard.contains_key(node) {
+            return Err(AlberoError::DuplicateNode(node.to_string()));
+        }
+        let nodo = SharedNodo { nome: node.to_string(), padre: Some(padre.clone()), stato: RwLock::new(Stato { acceso: false, figli: Vec::new() }) };
+        shard.insert(node.to_string(), Arc::new(nodo));
+        stato_padre.figli.push(node.to_string());
+        Ok(())
+    }
+
+    // come Albero::toggle: si commuta solo se tutti gli antenati sono accesi
+    pub fn toggle(&self, node: &str) -> Result<bool, AlberoError> {
+        let nodo = self.get(node).ok_or_else(|| AlberoError::UnknownNode(node.to_string()))?;
+        let antenati = nodo.antenati();
+        let _guardie = blocca_accesi(&antenati)?;
+        let mut stato = nodo.stato.write().unwrap();
+        stato.acceso = !stato.acceso;
+        Ok(stato.acceso)
+    }
+
+    // l'interruttore del nodo, false se il nodo non esiste
+    pub fn peek(&self, node: &str) -> bool {
+        self.get(node).is_some_and(|n| n.stato.read().unwrap().acceso)
+    }
+
+    // la luce del nodo è accesa: lui e tutti gli antenati sono accesi nello stesso istante
+    pub fn is_lit(&self, node: &str) -> bool {
+        let Some(nodo) = self.get(node) else { return false };
+        let antenati = nodo.antenati();
+        let Ok(_guardie) = blocca_accesi(&antenati) else { return false };
+        nodo.stato.read().unwrap().acceso
+    }
+
+    pub fn contains(&self, node: &str) -> bool {
+        self.get(node).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nomi.iter().map(|s| s.read().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn parent(&self, node: &str) -> Option<String> {
+        self.get(node)?.padre.as_ref().map(|p| p.nome.clone())
+    }
+
+    pub fn children(&self, node: &str) -> Option<Vec<String>> {
+        Some(self.get(node)?.stato.read().unwrap().figli.clone())
+    }
+}
+
+// xorshift, per non aggiungere dipendenze
+fn caso(seme: &mut u64, n: usize) -> usize {
+    *seme ^= *seme << 13;
+    *seme ^= *seme >> 7;
+    *seme ^= *seme << 17;
+    (*seme % n as u64) as usize
+}
+
+// su un albero nuovo con la radice accesa n_threads thread fanno ops operazioni a caso
+// ciascuno: il thread i aggiunge e commuta i nodi del suo ramo "t{i}" (il thread 0 commuta
+// anche la radice) e guarda le luci degli altri rami; restituisce l'albero e gli
+// interruttori che i thread si aspettano alla fine, per controllare il risultato
+pub fn stress(n_threads: usize, ops: usize) -> (SharedAlbero, HashMap<String, bool>) {
+    let albero = SharedAlbero::new();
+    albero.add_root("root").unwrap();
+    albero.toggle("root").unwrap();
+    let mut attesi = HashMap::from([("root".to_string(), true)]);
+    thread::scope(|s| {
+        let albero = &albero;
+        let threads: Vec<_> = (0..n_threads).map(|i| s.spawn(move || {
+            let mut seme = 0x9e37_79b9_7f4a_7c15 ^ (i as u64 + 1);
+            let ramo = format!("t{}", i);
+            albero.add("root", &ramo).unwrap();
+            let mut nodi = vec![ramo.clone()];
+            let mut attesi = HashMap::from([(ramo.clone(), false)]);
+            for k in 0..ops {
+                match caso(&mut seme, 10) {
+                    0 => {
+                        let nodo = format!("{}-{}", ramo, k);
+                        albero.add(&nodi[caso(&mut seme, nodi.len())], &nodo).unwrap();
+                        attesi.insert(nodo.clone(), false);
+                        nodi.push(nodo);
+                    }
+                    1..=4 => {
+                        let nodo = &nodi[caso(&mut seme, nodi.len())];
+                        if let Ok(acceso) = albero.toggle(nodo) {
+                            attesi.insert(nodo.clone(), acceso);
+                        }
+                    }
+                    5 if i == 0 => {
+                        let acceso = albero.toggle("root").unwrap();
+                        attesi.insert("root".to_string(), acceso);
+                    }
+                    _ => {
+                        // un nodo (forse) di un altro ramo
+                        let altro = format!("t{}-{}", caso(&mut seme, n_threads), caso(&mut seme, ops));
+                        albero.is_lit(&altro);
+                        albero.peek(&nodi[caso(&mut seme, nodi.len())]);
+                    }
+                }
+            }
+            attesi
+        })).collect();
+        for t in threads {
+            attesi.extend(t.join().unwrap());
+        }
+    });
+    (albero, attesi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_basics() {
+        let albero = SharedAlbero::new();
+        albero.add_root("root").unwrap();
+        albero.add("root", "a").unwrap();
+        albero.add("a", "a1").unwrap();
+        albero.add("root", "b").unwrap();
+        assert_eq!(albero.add("root", "a1"), Err(AlberoError::DuplicateNode("a1".to_string())));
+        assert_eq!(albero.add("x", "y"), Err(AlberoError::UnknownFather("x".to_string())));
+        assert_eq!(albero.add_root("r"), Err(AlberoError::RootExists("root".to_string())));
+        assert_eq!(albero.children("root"), Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(albero.toggle("a1"), Err(AlberoError::AncestorOff("root".to_string())));
+        albero.toggle("root").unwrap();
+        albero.toggle("a").unwrap();
+        assert_eq!(albero.toggle("a1"), Ok(true));
+        assert!(albero.is_lit("a1"));
+        albero.toggle("a").unwrap();
+        assert!(albero.peek("a1") && !albero.is_lit("a1"));
+        assert_eq!(albero.len(), 4);
+    }
+
+    #[test]
+    fn test_stress() {
+        let (albero, attesi) = stress(8, 5_000);
+        assert_eq!(albero.len(), attesi.len());
+        for (nodo, acceso) in &attesi {
+            assert_eq!(albero.peek(nodo), *acceso, "{}", nodo);
+            // a thread fermi la luce è quella calcolata risalendo gli antenati
+            let mut lit = *acceso;
+            let mut corrente = albero.parent(nodo);
+            while let Some(p) = corrente {
+                lit &= attesi[&p];
+                corrente = albero.parent(&p);
+            }
+            assert_eq!(albero.is_lit(nodo), lit, "{}", nodo);
+        }
+    }
+
+    #[test]
+    fn test_toggle_ancestor_while_reading() {
+        // un thread accende e spegne a di continuo, gli altri guardano e commutano a1:
+        // toggle di a1 riesce solo con a acceso, altrimenti AncestorOff
+        let albero = SharedAlbero::new();
+        albero.add_root("root").unwrap();
+        albero.toggle("root").unwrap();
+        albero.add("root", "a").unwrap();
+        albero.add("a", "a1").unwrap();
+        albero.toggle("a").unwrap();
+        let riusciti = std::sync::atomic::AtomicUsize::new(0);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..2_000 {
+                    albero.toggle("a").unwrap();
+                }
+            });
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..2_000 {
+                        albero.is_lit("a1");
+                        match albero.toggle("a1") {
+                            Ok(_) => { riusciti.fetch_add(1, std::sync::atomic::Ordering::Relaxed); }
+                            Err(AlberoError::AncestorOff(a)) => assert_eq!(a, "a"),
+                            Err(e) => panic!("{}", e),
+                        }
+                    }
+                });
+            }
+        });
+        assert!(albero.peek("a")); // 2000 toggle: acceso come all'inizio
+        assert_eq!(albero.peek("a1"), riusciti.into_inner() % 2 == 1);
+        assert_eq!(albero.is_lit("a1"), albero.peek("a1"));
+    }
+}
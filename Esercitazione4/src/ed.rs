@@ -0,0 +1,593 @@
+// a small ed(1): opens a file in a LineEditor and runs commands on it, typed one per
+// line on stdin or read from a script, e.g. for migrating many config files:
+//
+//     Esercitazione4 -e '/^port/s/80/8080/' -e 'w' -e 'q' server.conf
+//
+// addresses (1 is the first line):
+//   N  .  $           a line number, the current line, the last line
+//   /re/  ?re?        the next (previous) line matching re, wrapping around
+//   A,B  ,  %         a range; "," and "%" alone are the whole file
+// commands:
+//   p  d              print, delete the lines
+//   a  i              append after / insert before the line the text that follows, up to
+//                     a line with a single "."; "a text" adds just one line (handy with -e)
+//   s/re/repl/[g][p]  replace the first (g: all) matches in each line, repl can use $1, $name
+//   u                 undo the last command
+//   w [file]  q  Q    write, quit (twice if there are unsaved changes), quit anyway
+// with -f or -e the first error stops the script with exit code 1, interactively it is
+// reported with "?" and the next command is read
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use regex::Regex;
+
+use crate::LineEditor;
+use crate::file::TextFormat;
+use crate::position::PositionError;
+
+const USAGE: &str = "\
+usage: Esercitazione4 [-s] [-f script] [-e command]... file
+       Esercitazione4 --bench [lines]
+
+  -e command  run command (can be repeated)
+  -f script   run the commands in the script file
+  -s          do not print the byte counts of w
+without -e and -f the commands are read from stdin";
+
+#[derive(Debug)]
+pub enum EdError {
+    Usage(String),
+    Parse(String),
+    Address(String), // an address out of the buffer or a regex that matches no line
+    NoMatch,
+    Unsaved, // q with changes not written
+    Position(PositionError),
+    Regex(regex::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for EdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdError::Usage(msg) => write!(f, "{}\n{}", msg, USAGE),
+            EdError::Parse(msg) => write!(f, "{}", msg),
+            EdError::Address(msg) => write!(f, "invalid address: {}", msg),
+            EdError::NoMatch => write!(f, "no match"),
+            EdError::Unsaved => write!(f, "warning: buffer modified, q again to quit"),
+            EdError::Position(e) => write!(f, "{}", e),
+            EdError::Regex(e) => write!(f, "{}", e),
+            EdError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EdError {}
+
+impl From<PositionError> for EdError {
+    fn from(e: PositionError) -> Self {
+        EdError::Position(e)
+    }
+}
+
+impl From<regex::Error> for EdError {
+    fn from(e: regex::Error) -> Self {
+        EdError::Regex(e)
+    }
+}
+
+impl From<io::Error> for EdError {
+    fn from(e: io::Error) -> Self {
+        EdError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    Line(usize), // as typed, 1-based (0 is before the first line, for a and i)
+    Current,
+    Last,
+    Forward(String), // /re/
+    Backward(String), // ?re?
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub range: Option<(Address, Address)>, // a single address is (a, a)
+    pub name: char,
+    pub arg: String, // what follows the command name
+}
+
+// splits "re/rest" at the first delimiter not escaped by a backslash, "\/" becomes "/"
+fn split_delimited(s: &str, delim: char) -> Option<(String, &str)> {
+    let mut part = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == delim {
+            return Some((part, &s[i + c.len_utf8()..]));
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, d)) if d == delim => part.push(d),
+                Some((_, d)) => {
+                    part.push('\\');
+                    part.push(d);
+                }
+                None => part.push('\\'),
+            }
+        } else {
+            part.push(c);
+        }
+    }
+    None
+}
+
+fn parse_address(s: &str) -> Result<(Option<Address>, &str), EdError> {
+    let Some(c) = s.chars().next() else {
+        return Ok((None, s));
+    };
+    match c {
+        '.' => Ok((Some(Address::Current), &s[1..])),
+        '$' => Ok((Some(Address::Last), &s[1..])),
+        '/' | '?' => {
+            let (re, rest) = split_delimited(&s[1..], c).unwrap_or((s[1..].to_string(), ""));
+            let address = if c == '/' { Address::Forward(re) } else { Address::Backward(re) };
+            Ok((Some(address), rest))
+        }
+        '0'..='9' => {
+            let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            let n = s[..end].parse().map_err(|_| EdError::Parse(format!("bad line number {}", &s[..end])))?;
+            Ok((Some(Address::Line(n)), &s[end..]))
+        }
+        _ => Ok((None, s)),
+    }
+}
+
+pub fn parse_command(line: &str) -> Result<Command, EdError> {
+    let line = line.trim_start();
+    let (range, rest) = if let Some(rest) = line.strip_prefix('%') {
+        (Some((Address::Line(1), Address::Last)), rest)
+    } else {
+        let (first, rest) = parse_address(line)?;
+        if let Some(rest) = rest.strip_prefix(',') {
+            let (second, rest) = parse_address(rest)?;
+            let range = match (first, second) {
+                (None, None) => (Address::Line(1), Address::Last),
+                (Some(a), None) => (a.clone(), a),
+                (None, Some(b)) => (Address::Line(1), b),
+                (Some(a), Some(b)) => (a, b),
+            };
+            (Some(range), rest)
+        } else {
+            (first.map(|a| (a.clone(), a)), rest)
+        }
+    };
+    let rest = rest.trim_start();
+    let mut chars = rest.chars();
+    let name = chars.next().unwrap_or('p'); // an address alone prints the line, as in ed
+    let arg = chars.as_str();
+    if !"pdaisuwqQ".contains(name) {
+        return Err(EdError::Parse(format!("unknown command {}", name)));
+    }
+    Ok(Command { range, name, arg: if name == 's' { arg.to_string() } else { arg.trim().to_string() } })
+}
+
+// what the loop does after a command
+#[derive(Debug, PartialEq)]
+enum Flow {
+    Continue,
+    Quit,
+}
+
+pub struct Session<O: Write> {
+    editor: LineEditor,
+    file: String, // the default file of w
+    current: usize, // 1-based, 0 when the buffer is empty
+    dirty: bool,
+    quit_warned: bool,
+    quiet: bool,
+    out: O,
+}
+
+impl<O: Write> Session<O> {
+
+    // a file that does not exist is a new empty buffer, written on the first w
+    pub fn open(file: &str, quiet: bool, out: O) -> Result<Self, EdError> {
+        let editor = match LineEditor::from_file(file) {
+            Ok(editor) => editor,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut editor = LineEditor::new(String::new());
+                editor.set_format(TextFormat::default());
+                editor
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let current = editor.len();
+        Ok(Session { editor, file: file.to_string(), current, dirty: false, quit_warned: false, quiet, out })
+    }
+
+    pub fn editor(&self) -> &LineEditor {
+        &self.editor
+    }
+
+    fn search(&self, re: &str, forward: bool) -> Result<usize, EdError> {
+        let regex = Regex::new(re)?;
+        let len = self.editor.len();
+        // from the line after (before) the current one, wrapping around, the current last
+        (1..=len)
+            .map(|k| if forward { (self.current + k - 1) % len + 1 } else { (self.current + 2 * len - k - 1) % len + 1 })
+            .find(|&n| regex.is_match(self.editor.line(n - 1).unwrap_or_default()))
+            .ok_or_else(|| EdError::Address(format!("no line matches {}", re)))
+    }
+
+    fn resolve(&self, address: &Address) -> Result<usize, EdError> {
+        let n = match address {
+            Address::Line(n) => *n,
+            Address::Current => self.current,
+            Address::Last => self.editor.len(),
+            Address::Forward(re) => self.search(re, true)?,
+            Address::Backward(re) => self.search(re, false)?,
+        };
+        if n > self.editor.len() {
+            return Err(EdError::Address(format!("line {} of {}", n, self.editor.len())));
+        }
+        Ok(n)
+    }
+
+    // the lines of the command, 1-based and inclusive; zero = true accepts line 0
+    fn lines(&self, command: &Command, zero: bool) -> Result<(usize, usize), EdError> {
+        let (first, last) = match &command.range {
+            Some((a, b)) => (self.resolve(a)?, self.resolve(b)?),
+            None => (self.current, self.current),
+        };
+        if first > last {
+            return Err(EdError::Address(format!("{},{} is backwards", first, last)));
+        }
+        if first == 0 && !zero {
+            return Err(EdError::Address("0".to_string()));
+        }
+        Ok((first, last))
+    }
+
+    // text: the lines for a and i, already read by the caller
+    fn execute(&mut self, command: &Command, text: Vec<String>) -> Result<Flow, EdError> {
+        if command.name != 'q' {
+            self.quit_warned = false;
+        }
+        match command.name {
+            'p' => {
+                let (first, last) = self.lines(command, false)?;
+                for line in self.editor.iter_from(first - 1).take(last - first + 1) {
+                    writeln!(self.out, "{}", line)?;
+                }
+                self.current = last;
+            }
+            'd' => {
+                let (first, last) = self.lines(command, false)?;
+                self.editor.remove_lines(first - 1..last)?;
+                self.current = first.min(self.editor.len()); // the line after, or the new last one
+                self.dirty = true;
+            }
+            'a' | 'i' => {
+                let (_, last) = self.lines(command, true)?;
+                let at = match command.name {
+                    'a' => last,
+                    _ => last.saturating_sub(1), // "0i" is the same as "1i"
+                };
+                let count = text.len();
+                self.editor.insert_lines(at, text)?;
+                if count > 0 {
+                    self.current = at + count;
+                    self.dirty = true;
+                }
+            }
+            's' => self.substitute(command)?,
+            'u' => {
+                if !self.editor.undo() {
+                    return Err(EdError::Parse("nothing to undo".to_string()));
+                }
+                // the lines can come back in a buffer that was empty: the current line is 1
+                let len = self.editor.len();
+                self.current = if len == 0 { 0 } else { self.current.clamp(1, len) };
+                self.dirty = true;
+            }
+            'w' => {
+                if !command.arg.is_empty() {
+                    self.file = command.arg.clone();
+                }
+                if self.editor.path().is_some() {
+                    self.editor.save_as(&self.file)?;
+                } else {
+                    self.editor.force_save_as(&self.file)?; // a new file: nothing to protect
+                }
+                if !self.quiet {
+                    writeln!(self.out, "{}", fs::metadata(&self.file)?.len())?;
+                }
+                self.dirty = false;
+            }
+            'q' if self.dirty && !self.quit_warned => {
+                self.quit_warned = true;
+                return Err(EdError::Unsaved);
+            }
+            'q' | 'Q' => return Ok(Flow::Quit),
+            c => return Err(EdError::Parse(format!("unknown command {}", c))),
+        }
+        Ok(Flow::Continue)
+    }
+
+    // s/re/repl/flags, any char after s can be the delimiter
+    fn substitute(&mut self, command: &Command) -> Result<(), EdError> {
+        let mut chars = command.arg.chars();
+        let delim = chars.next().filter(|c| !c.is_alphanumeric() && !c.is_whitespace())
+            .ok_or_else(|| EdError::Parse("s needs a delimiter, e.g. s/re/repl/".to_string()))?;
+        let (re, rest) = split_delimited(chars.as_str(), delim)
+            .ok_or_else(|| EdError::Parse("missing replacement".to_string()))?;
+        let (repl, flags) = split_delimited(rest, delim).unwrap_or((rest.to_string(), ""));
+        let (global, print) = (flags.contains('g'), flags.contains('p'));
+        if let Some(f) = flags.chars().find(|f| !"gp".contains(*f) && !f.is_whitespace()) {
+            return Err(EdError::Parse(format!("unknown flag {}", f)));
+        }
+        let regex = Regex::new(&re)?;
+        let (first, last) = self.lines(command, false)?;
+
+        let mut changed = None;
+        self.editor.try_transaction(|editor| -> Result<(), EdError> {
+            for n in first..=last {
+                let line = editor.line(n - 1)?;
+                if !regex.is_match(line) {
+                    continue;
+                }
+                let (len, new) = if global {
+                    (line.len(), regex.replace_all(line, repl.as_str()).into_owned())
+                } else {
+                    (line.len(), regex.replace(line, repl.as_str()).into_owned())
+                };
+                editor.replace(n - 1, 0, len, &new)?;
+                changed = Some(n);
+            }
+            Ok(())
+        })?;
+        let n = changed.ok_or(EdError::NoMatch)?;
+        self.current = n;
+        self.dirty = true;
+        if print {
+            writeln!(self.out, "{}", self.editor.line(n - 1)?)?;
+        }
+        Ok(())
+    }
+
+    // runs the commands of input; batch: stop at the first error (scripts), otherwise
+    // print "?" and the error on err and go on (interactive)
+    fn run_commands<I, E>(&mut self, input: &mut I, batch: bool, err: &mut E) -> Result<Flow, EdError>
+    where
+        I: Iterator<Item = io::Result<String>>,
+        E: Write,
+    {
+        while let Some(line) = input.next() {
+            let line = line?;
+            if line.trim().is_empty() && batch {
+                continue;
+            }
+            let result = parse_command(&line).and_then(|command| {
+                let mut text = Vec::new();
+                if matches!(command.name, 'a' | 'i') {
+                    if command.arg.is_empty() {
+                        // the lines up to "."
+                        for l in input.by_ref() {
+                            let l = l?;
+                            if l == "." {
+                                break;
+                            }
+                            text.push(l);
+                        }
+                    } else {
+                        text.push(command.arg.clone());
+                    }
+                }
+                self.execute(&command, text)
+            });
+            match result {
+                Ok(Flow::Quit) => return Ok(Flow::Quit),
+                Ok(Flow::Continue) => {}
+                Err(e) if batch => return Err(e),
+                Err(e) => {
+                    writeln!(err, "? {}", e)?;
+                }
+            }
+        }
+        Ok(Flow::Continue)
+    }
+}
+
+struct Options {
+    file: String,
+    quiet: bool,
+    script: Vec<String>, // the -e commands and the lines of the -f files, in order
+    batch: bool,
+}
+
+fn parse_args<A: Iterator<Item = String>>(mut args: A) -> Result<Options, EdError> {
+    let mut options = Options { file: String::new(), quiet: false, script: Vec::new(), batch: false };
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" => options.quiet = true,
+            "-e" => {
+                let command = args.next().ok_or_else(|| EdError::Usage("-e needs a command".to_string()))?;
+                options.script.push(command);
+                options.batch = true;
+            }
+            "-f" => {
+                let path = args.next().ok_or_else(|| EdError::Usage("-f needs a file".to_string()))?;
+                options.script.extend(fs::read_to_string(&path)?.lines().map(String::from));
+                options.batch = true;
+            }
+            "-h" | "--help" => return Err(EdError::Usage(String::new())),
+            a if a.starts_with('-') && a.len() > 1 => return Err(EdError::Usage(format!("unknown option {}", a))),
+            _ if file.is_some() => return Err(EdError::Usage("only one file can be edited".to_string())),
+            _ => file = Some(arg),
+        }
+    }
+    options.file = file.ok_or_else(|| EdError::Usage("missing file".to_string()))?;
+    options.quiet |= options.batch;
+    Ok(options)
+}
+
+// runs the editor, returns the exit code: 0 ok, 1 an error in a script, 2 bad arguments
+pub fn run<A, R, O, E>(args: A, input: R, out: &mut O, err: &mut E) -> i32
+where
+    A: Iterator<Item = String>,
+    R: BufRead,
+    O: Write,
+    E: Write,
+{
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            let _ = writeln!(err, "{}", e);
+            return 2;
+        }
+    };
+    let result = Session::open(&options.file, options.quiet, &mut *out).and_then(|mut session| {
+        if options.batch {
+            let mut script = options.script.into_iter().map(Ok);
+            session.run_commands(&mut script, true, err)
+        } else {
+            session.run_commands(&mut input.lines(), false, err)
+        }
+    });
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            let _ = writeln!(err, "{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    struct TestFile(PathBuf);
+
+    impl TestFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("ed_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("file.conf");
+            fs::write(&path, contents).unwrap();
+            TestFile(path)
+        }
+
+        fn name(&self) -> String {
+            self.0.display().to_string()
+        }
+
+        fn contents(&self) -> String {
+            fs::read_to_string(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    // runs the editor, returns exit code, stdout and stderr
+    fn ed(args: &[&str], input: &str) -> (i32, String, String) {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = run(args.iter().map(|a| a.to_string()), input.as_bytes(), &mut out, &mut err);
+        (code, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    const CONF: &str = "host = localhost\nport = 80\n# end\n";
+
+    #[test]
+    fn test_parse_command() {
+        let c = parse_command("5,$d").unwrap();
+        assert_eq!(c.range, Some((Address::Line(5), Address::Last)));
+        assert_eq!(c.name, 'd');
+        let c = parse_command(r"/a\/b/s/x/y/g").unwrap();
+        assert_eq!(c.range, Some((Address::Forward("a/b".to_string()), Address::Forward("a/b".to_string()))));
+        assert_eq!((c.name, c.arg.as_str()), ('s', "/x/y/g"));
+        assert_eq!(parse_command(",p").unwrap().range, Some((Address::Line(1), Address::Last)));
+        assert_eq!(parse_command("3").unwrap().name, 'p');
+        assert_eq!(parse_command("a new line").unwrap().arg, "new line");
+        assert!(parse_command("5x").is_err());
+    }
+
+    #[test]
+    fn test_script_migration() {
+        let file = TestFile::new("script", CONF);
+        let (code, out, err) = ed(&["-e", "/^port/s/(\\d+)/8$1/", "-e", "$i\ntimeout = 5", "-e", "w", "-e", "q", &file.name()], "");
+        assert_eq!((code, err.as_str()), (0, ""), "{}", out);
+        // -e "$i\n..." is a one line command: i with the text after it
+        assert_eq!(file.contents(), "host = localhost\nport = 880\ntimeout = 5\n# end\n");
+    }
+
+    #[test]
+    fn test_script_file_with_text_blocks() {
+        let file = TestFile::new("script_file", CONF);
+        let script = file.0.with_file_name("migrate.ed");
+        fs::write(&script, "1a\nuser = admin\nlog = on\n.\n/# end/d\n,p\nw\nq\n").unwrap();
+        let (code, out, _) = ed(&["-f", script.to_str().unwrap(), &file.name()], "");
+        assert_eq!(code, 0);
+        assert_eq!(out, "host = localhost\nuser = admin\nlog = on\nport = 80\n");
+        assert_eq!(file.contents(), out);
+    }
+
+    #[test]
+    fn test_script_stops_at_first_error() {
+        let file = TestFile::new("script_error", CONF);
+        let (code, _, err) = ed(&["-e", "1d", "-e", "s/nothing/x/", "-e", "w", &file.name()], "");
+        assert_eq!(code, 1);
+        assert_eq!(err, "no match\n");
+        assert_eq!(file.contents(), CONF); // w never ran
+        let (code, _, err) = ed(&["-e", "9p", &file.name()], "");
+        assert_eq!((code, err.as_str()), (1, "invalid address: line 9 of 3\n"));
+    }
+
+    #[test]
+    fn test_interactive_session() {
+        let file = TestFile::new("interactive", CONF);
+        let input = "2\nx\n3,1p\n/host/s/local/remote/p\nu\n1p\nq\n$a\nlast\n.\nw\nq\nnever run\n";
+        let (code, out, err) = ed(&[&file.name()], input);
+        assert_eq!(code, 0);
+        assert_eq!(out, "port = 80\nhost = remotehost\nhost = localhost\n38\n");
+        assert_eq!(err, "? unknown command x\n? invalid address: 3,1 is backwards\n? warning: buffer modified, q again to quit\n");
+        assert_eq!(file.contents(), "host = localhost\nport = 80\n# end\nlast\n");
+    }
+
+    #[test]
+    fn test_new_file_and_search_wraps() {
+        let file = TestFile::new("new_file", "");
+        let path = file.0.with_file_name("new.txt");
+        let name = path.display().to_string();
+        let (code, _, _) = ed(&["-e", "a one", "-e", "a two", "-e", "a three", "-e", "1", "-e", "?t?d", "-e", "w", &name], "");
+        assert_eq!(code, 0);
+        // from line 1 backwards: "three" (line 3) is found before "two"
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_undo_of_delete_all_and_search_backwards() {
+        let file = TestFile::new("undo_all", "");
+        let input = "a\nx\ny\n.\n,d\nu\n?nomatch?\n.p\n?x?p\nw\nq\n";
+        let (code, out, err) = ed(&[&file.name()], input);
+        assert_eq!(code, 0);
+        assert_eq!(out, "x\nx\n3\n");
+        assert_eq!(err, "? invalid address: no line matches nomatch\n");
+        assert_eq!(file.contents(), "x\ny"); // an empty file has no final newline
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        assert_eq!(ed(&[], "").0, 2);
+        assert_eq!(ed(&["-x", "f"], "").0, 2);
+        assert_eq!(ed(&["a", "b"], "").0, 2);
+    }
+}
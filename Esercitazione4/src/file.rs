@@ -0,0 +1,395 @@
+// loading and saving LineEditor files
+// - the format (line ending, BOM, final newline) is detected on load and used on save,
+//   so a file opened and saved without edits is written back byte by byte; with mixed
+//   endings each line not edited keeps its own, the edited and new ones get the most
+//   frequent
+// - save writes a temporary file in the same directory and renames it over the
+//   original: a crash leaves the old file or the new one, never half of it
+// - before saving over the loaded file we check that nobody changed it on disk,
+//   first with size and mtime, then with the hash of the contents
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::LineEditor;
+use crate::history::History;
+use crate::rope::LineRope;
+
+const BOM: &str = "\u{feff}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextFormat {
+    pub ending: LineEnding, // with mixed endings the most frequent one
+    pub bom: bool,
+    pub final_newline: bool,
+    pub mixed: bool, // both endings found: ending is used only for edited and new lines
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        TextFormat { ending: LineEnding::Lf, bom: false, final_newline: true, mixed: false }
+    }
+}
+
+impl TextFormat {
+    // the format of a text and the text without the BOM
+    pub fn detect(text: &str) -> (TextFormat, &str) {
+        let bom = text.starts_with(BOM);
+        let text = text.strip_prefix(BOM).unwrap_or(text);
+        let newlines = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        let format = TextFormat {
+            ending: if crlf * 2 > newlines { LineEnding::CrLf } else { LineEnding::Lf },
+            bom,
+            final_newline: text.ends_with('\n'),
+            mixed: crlf > 0 && crlf < newlines,
+        };
+        (format, text)
+    }
+
+    // the lines come with the ending they had when loaded, see LineRope::iter_with_endings
+    pub fn join<'a>(&self, lines: impl Iterator<Item = (&'a str, Option<&'a str>)>) -> String {
+        let mut text = Vec::new();
+        self.write(lines, &mut text).expect("writing to a Vec");
+        String::from_utf8(text).expect("the lines are UTF-8")
+    }
+
+    // writes the lines one at a time, without joining them in memory
+    pub fn write<'a, W: Write + ?Sized>(
+        &self,
+        lines: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+        out: &mut W,
+    ) -> io::Result<()> {
+        if self.bom {
+            out.write_all(BOM.as_bytes())?;
+        }
+        let mut previous: Option<&str> = None; // the ending of the line before
+        for (line, ending) in lines {
+            if let Some(ending) = previous {
+                out.write_all(ending.as_bytes())?;
+            }
+            out.write_all(line.as_bytes())?;
+            previous = Some(match ending {
+                Some(ending) if self.mixed => ending,
+                _ => self.ending.as_str(),
+            });
+        }
+        if self.final_newline && let Some(ending) = previous {
+            out.write_all(ending.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+// what we know of the file when it was loaded (or saved the last time)
+#[derive(Debug, Clone)]
+pub struct DiskState {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl DiskState {
+    fn new(path: &Path, contents: &[u8]) -> io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        Ok(DiskState {
+            path: path.to_path_buf(),
+            modified: meta.modified().ok(),
+            len: meta.len(),
+            hash: hash(contents),
+        })
+    }
+
+    // true if the file is not the same anymore (a deleted file counts as changed)
+    pub fn changed(&self) -> io::Result<bool> {
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+            Err(e) => return Err(e),
+        };
+        if meta.len() != self.len {
+            return Ok(true);
+        }
+        if meta.modified().ok() == self.modified {
+            return Ok(false);
+        }
+        // touched but maybe with the same contents
+        Ok(hash(&fs::read(&self.path)?) != self.hash)
+    }
+}
+
+fn hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(contents); // the same bytes written in pieces give the same hash
+    hasher.finish()
+}
+
+// a writer that hashes what goes through it, so the saved file must not be read back
+struct HashWriter<W> {
+    inner: W,
+    hasher: DefaultHasher,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.write(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// writes the contents in a temporary file next to path and renames it over path,
+// returns the hash of the contents
+fn write_atomic(path: &Path, contents: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<u64> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let tmp = dir.join(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+
+    let result = (|| {
+        let mut out = HashWriter { inner: BufWriter::new(File::create(&tmp)?), hasher: DefaultHasher::new() };
+        contents(&mut out)?;
+        let file = out.inner.into_inner().map_err(|e| e.into_error())?;
+        if let Ok(meta) = fs::metadata(path) {
+            file.set_permissions(meta.permissions())?; // keep the mode of the old file
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(out.hasher.finish())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+impl LineEditor {
+
+    // create a new LineEditor from a file, remembering its format and state on disk
+    // the file is read in memory, so changing it on disk can't affect the editor
+    pub fn from_file(file_name: &str) -> Result<Self, io::Error> {
+        let lines = LineRope::open(&File::open(file_name)?)?;
+        let (format, _) = TextFormat::detect(lines.source());
+        let disk = DiskState::new(Path::new(file_name), lines.source().as_bytes())?;
+        Ok(LineEditor { lines, history: History::default(), format, disk: Some(disk), version: 0 })
+    }
+
+    pub fn format(&self) -> TextFormat {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: TextFormat) {
+        self.format = format;
+    }
+
+    // the file the editor was loaded from or saved to
+    pub fn path(&self) -> Option<&Path> {
+        self.disk.as_ref().map(|d| d.path.as_path())
+    }
+
+    // the whole text as it would be saved
+    pub fn text(&self) -> String {
+        self.format.join(self.lines.iter_with_endings())
+    }
+
+    // false also for an editor not bound to a file
+    pub fn is_changed_on_disk(&self) -> io::Result<bool> {
+        match &self.disk {
+            Some(disk) => disk.changed(),
+            None => Ok(false),
+        }
+    }
+
+    // saves to the file it was loaded from, refusing to overwrite changes made by others
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = self.path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the editor has no file, use save_as"))?
+            .to_path_buf();
+        self.save_as(path)
+    }
+
+    // saves to path, which becomes the file of the editor; saving over the loaded file
+    // fails if it changed on disk since it was loaded
+    pub fn save_as(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(disk) = &self.disk
+            && same_file(&disk.path, path)
+            && disk.changed()?
+        {
+            return Err(io::Error::other(format!("{} changed on disk since it was loaded", path.display())));
+        }
+        self.force_save_as(path)
+    }
+
+    // saves to path without checking for changes on disk
+    pub fn force_save_as(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let hash = write_atomic(path, |out| self.format.write(self.lines.iter_with_endings(), out))?;
+        let meta = fs::metadata(path)?;
+        self.disk = Some(DiskState { path: path.to_path_buf(), modified: meta.modified().ok(), len: meta.len(), hash });
+        Ok(())
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b, // the file was deleted: compare the paths as given
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestFile(PathBuf);
+
+    impl TestFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let dir = std::env::temp_dir().join(format!("line_editor_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("file.txt");
+            fs::write(&path, contents).unwrap();
+            TestFile(path)
+        }
+
+        fn name(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let (format, text) = TextFormat::detect("\u{feff}a\r\nb\r\nc");
+        assert_eq!(format, TextFormat { ending: LineEnding::CrLf, bom: true, final_newline: false, mixed: false });
+        assert_eq!(text, "a\r\nb\r\nc");
+        let (format, _) = TextFormat::detect("a\nb\r\nc\n");
+        assert_eq!(format.ending, LineEnding::Lf);
+        assert!(format.final_newline);
+        assert!(format.mixed);
+    }
+
+    #[test]
+    fn test_round_trip_without_edits() {
+        for contents in [&b"one\ntwo\n"[..], b"one\r\ntwo", b"\xef\xbb\xbfuno\r\ndue\r\n", b"", b"\n\n"] {
+            let file = TestFile::new("round_trip", contents);
+            let mut editor = LineEditor::from_file(file.name()).unwrap();
+            editor.save().unwrap();
+            assert_eq!(fs::read(&file.0).unwrap(), contents);
+        }
+    }
+
+    #[test]
+    fn test_large_file_truncated_while_open() {
+        // 17 MiB: above the size that used to be memory mapped
+        let line = "x".repeat(1023);
+        let contents: String = (0..17 * 1024).map(|_| format!("{}\n", line)).collect();
+        let file = TestFile::new("large", contents.as_bytes());
+        let editor = LineEditor::from_file(file.name()).unwrap();
+        fs::write(&file.0, "").unwrap();
+        assert_eq!(editor.len(), 17 * 1024);
+        assert_eq!(editor.iter_from(17 * 1024 - 1).next(), Some(line.as_str()));
+        assert!(editor.is_changed_on_disk().unwrap());
+    }
+
+    #[test]
+    fn test_mixed_endings_round_trip() {
+        let file = TestFile::new("mixed", b"one\r\ntwo\nthree\r\nfour\n");
+        let mut editor = LineEditor::from_file(file.name()).unwrap();
+        editor.save().unwrap();
+        assert_eq!(fs::read(&file.0).unwrap(), b"one\r\ntwo\nthree\r\nfour\n");
+
+        // the edited and new lines get the most frequent ending (a tie goes to \n)
+        editor.set_line(0, "ONE".to_string()).unwrap();
+        editor.insert_lines(2, vec!["2.5".to_string()]).unwrap();
+        editor.save().unwrap();
+        assert_eq!(fs::read(&file.0).unwrap(), b"ONE\ntwo\n2.5\nthree\r\nfour\n");
+
+        // a format without mixed endings normalizes the whole file
+        editor.set_format(TextFormat { mixed: false, ..editor.format() });
+        editor.save().unwrap();
+        assert_eq!(fs::read(&file.0).unwrap(), b"ONE\ntwo\n2.5\nthree\nfour\n");
+    }
+
+    #[test]
+    fn test_save_keeps_format_after_edits() {
+        let file = TestFile::new("edits", b"\xef\xbb\xbfHello\r\nWorld");
+        let mut editor = LineEditor::from_file(file.name()).unwrap();
+        editor.replace(1, 0, 5, "Rust").unwrap();
+        editor.save().unwrap();
+        assert_eq!(fs::read(&file.0).unwrap(), b"\xef\xbb\xbfHello\r\nRust");
+        assert!(!editor.is_changed_on_disk().unwrap());
+        // no temporary files left
+        assert_eq!(fs::read_dir(file.0.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_refuse_to_clobber_changes_on_disk() {
+        let file = TestFile::new("clobber", b"a\nb\n");
+        let mut editor = LineEditor::from_file(file.name()).unwrap();
+        fs::write(&file.0, b"a\nchanged by someone else\n").unwrap();
+        assert!(editor.is_changed_on_disk().unwrap());
+        editor.set_line(0, "A".to_string()).unwrap();
+        assert!(editor.save().is_err());
+        assert_eq!(fs::read(&file.0).unwrap(), b"a\nchanged by someone else\n");
+
+        // saving somewhere else is allowed and binds the editor to the new file
+        let other = file.0.with_file_name("other.txt");
+        editor.save_as(&other).unwrap();
+        assert_eq!(editor.path(), Some(other.as_path()));
+        assert_eq!(fs::read(&other).unwrap(), b"A\nb\n");
+        editor.force_save_as(&file.0).unwrap();
+        assert_eq!(fs::read(&file.0).unwrap(), b"A\nb\n");
+    }
+
+    #[test]
+    fn test_touched_but_same_contents_is_not_a_change() {
+        let file = TestFile::new("touch", b"x\n");
+        let mut editor = LineEditor::from_file(file.name()).unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        File::options().write(true).open(&file.0).unwrap().set_modified(later).unwrap();
+        assert!(!editor.is_changed_on_disk().unwrap());
+        editor.save().unwrap();
+    }
+
+    #[test]
+    fn test_new_editor_needs_save_as() {
+        let mut editor = LineEditor::new("text".to_string());
+        assert_eq!(editor.save().unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(editor.text(), "text");
+        assert_eq!(LineEditor::new("a\r\nb\r\n".to_string()).text(), "a\r\nb\r\n");
+    }
+}
@@ -0,0 +1,169 @@
+// the edit log of LineEditor: each change is stored as a command that knows how to
+// undo itself, the commands of a transaction are grouped and undone together
+//
+// - done: the groups that can be undone, the oldest are dropped beyond the limit
+// - undone: the groups that can be redone, emptied by any new edit
+
+use std::collections::VecDeque;
+
+use crate::rope::LineRope;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    // in line `line` the text `removed` at byte `start` has been replaced by `inserted`
+    Text { line: usize, start: usize, removed: String, inserted: String },
+    // whole lines inserted before line `at` or removed starting at line `at`
+    InsertLines { at: usize, lines: Vec<String> },
+    RemoveLines { at: usize, lines: Vec<String> },
+}
+
+impl Edit {
+    // the edit that brings the lines back to the state before this one
+    pub fn inverse(&self) -> Edit {
+        match self {
+            Edit::Text { line, start, removed, inserted } => Edit::Text {
+                line: *line,
+                start: *start,
+                removed: inserted.clone(),
+                inserted: removed.clone(),
+            },
+            Edit::InsertLines { at, lines } => Edit::RemoveLines { at: *at, lines: lines.clone() },
+            Edit::RemoveLines { at, lines } => Edit::InsertLines { at: *at, lines: lines.clone() },
+        }
+    }
+
+    // the edit has been recorded on these same lines, so the positions are valid
+    pub fn apply(&self, rope: &mut LineRope) {
+        match self {
+            Edit::Text { line, start, removed, inserted } => {
+                let text = rope.get_mut(*line).expect("line of the edit");
+                text.replace_range(*start..*start + removed.len(), inserted);
+            }
+            Edit::InsertLines { at, lines } => {
+                for (i, line) in lines.iter().enumerate() {
+                    rope.insert(at + i, line.clone());
+                }
+            }
+            Edit::RemoveLines { at, lines } => {
+                for _ in lines {
+                    rope.remove(*at);
+                }
+            }
+        }
+    }
+}
+
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+#[derive(Debug)]
+pub struct History {
+    done: VecDeque<Vec<Edit>>,
+    undone: Vec<Vec<Edit>>,
+    limit: usize, // max number of groups that can be undone, 0 disables the history
+    group: Vec<Edit>, // the edits of the open transaction
+    depth: usize, // nested transactions, the group is closed when it returns to 0
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        History { done: VecDeque::new(), undone: Vec::new(), limit, group: Vec::new(), depth: 0 }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.done.len() > limit {
+            self.done.pop_front();
+        }
+        // redo pops from the back: the groups to forget are the furthest away, at the front
+        let n = self.undone.len().saturating_sub(limit);
+        self.undone.drain(..n);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty() || !self.group.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    // an edit already applied to the lines
+    pub fn record(&mut self, edit: Edit) {
+        self.undone.clear();
+        self.group.push(edit);
+        if self.depth == 0 {
+            self.close_group();
+        }
+    }
+
+    // returns where the edits of this transaction start in the open group, for rollback
+    pub fn begin(&mut self) -> usize {
+        self.depth += 1;
+        self.group.len()
+    }
+
+    pub fn commit(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.close_group();
+        }
+    }
+
+    // undoes the edits recorded since begin returned mark and ends the transaction like
+    // commit: the edits of the outer transactions stay in the group; false if nothing changed
+    pub fn rollback(&mut self, mark: usize, lines: &mut LineRope) -> bool {
+        let edits = self.group.split_off(mark.min(self.group.len()));
+        for edit in edits.iter().rev() {
+            edit.inverse().apply(lines);
+        }
+        self.commit();
+        !edits.is_empty()
+    }
+
+    fn close_group(&mut self) {
+        if self.group.is_empty() {
+            return;
+        }
+        let group = std::mem::take(&mut self.group);
+        if self.limit == 0 {
+            return;
+        }
+        if self.done.len() == self.limit {
+            self.done.pop_front();
+        }
+        self.done.push_back(group);
+    }
+
+    // an open transaction is closed first, so it is undone as a whole
+    pub fn undo(&mut self, lines: &mut LineRope) -> bool {
+        self.depth = 0;
+        self.close_group();
+        let Some(group) = self.done.pop_back() else {
+            return false;
+        };
+        for edit in group.iter().rev() {
+            edit.inverse().apply(lines);
+        }
+        self.undone.push(group);
+        true
+    }
+
+    pub fn redo(&mut self, lines: &mut LineRope) -> bool {
+        self.depth = 0;
+        self.close_group();
+        let Some(group) = self.undone.pop() else {
+            return false;
+        };
+        for edit in &group {
+            edit.apply(lines);
+        }
+        self.done.push_back(group);
+        true
+    }
+}
@@ -4,61 +4,262 @@
 // - *** see test test functions in the code for usage examples
 
 use std::io;
-use std::fs::File;
-use std::io::BufRead;
+
+pub mod ed;
+mod file;
+mod history;
+pub mod position;
+pub mod replace;
+pub mod rope;
+pub mod search;
+
+use file::{DiskState, TextFormat};
+use history::{Edit, History};
+use position::{CharIndex, DisplayColumn, LinePosition, PositionError};
+use rope::LineRope;
 
 // (1) LineEditor: implement functionality
 pub struct LineEditor {
-    lines: Vec<String>,
+    lines: LineRope, // see rope.rs, only the edited lines are Strings
+    history: History, // every change goes through record() so it can be undone
+    format: TextFormat, // how the lines are joined when saved
+    disk: Option<DiskState>, // the file the lines come from, see file.rs for from_file and save
+    version: u64, // incremented by every change of the lines, see SearchSession
 }
 
 impl LineEditor {
     pub fn new(s: String) -> Self {
-        let l = LineEditor {
-            lines: s.lines().map(|l| l.to_string()).collect(), // map each line to a String
-        };
-        l
+        let (format, _) = TextFormat::detect(&s);
+        LineEditor {
+            lines: LineRope::from_text(s), // the lines are ranges of s, no copies
+            history: History::default(),
+            format,
+            disk: None,
+            version: 0,
+        }
+    }
+
+    // allocates a Vec as long as the file: for big files use iter()
+    pub fn all_lines(&self) -> Vec<&str> {
+        self.lines.iter().collect()
+    }
+
+    pub fn iter(&self) -> rope::Iter<'_> {
+        self.lines.iter()
+    }
+
+    // the lines from line on
+    pub fn iter_from(&self, line: usize) -> rope::Iter<'_> {
+        self.lines.iter_from(line)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    // replace the text in the line between start and end, which can be byte offsets
+    // (usize or ByteOffset), CharIndex or DisplayColumn; a position past the end of the
+    // line or inside a multi-byte char is an error, the line is not changed
+    pub fn replace<P, Q>(&mut self, line: usize, start: P, end: Q, subst: &str) -> Result<(), PositionError>
+    where
+        P: LinePosition,
+        Q: LinePosition,
+    {
+        let l = self.line(line)?;
+        let (start, end) = position::byte_range(l, start, end)?;
+        self.record(Edit::Text {
+            line,
+            start,
+            removed: l[start..end].to_string(),
+            inserted: subst.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn line(&self, line: usize) -> Result<&str, PositionError> {
+        self.lines.get(line).ok_or(PositionError::LineOutOfRange { line, lines: self.lines.len() })
+    }
+
+    // returns &str since the rope storage: the lines not yet edited are ranges of the
+    // loaded text, there is no String to borrow (it used to return Vec<&String>)
+    pub fn get_lines(&self) -> Vec<&str> {
+        self.all_lines()
+    }
+
+    // the new line is returned as &str (it used to be &mut String): changing it by hand
+    // would bypass the history, edit it with replace() or set_line() again
+    pub fn set_line(&mut self, line: usize, new_line: String) -> Result<&str, io::Error> {
+        let removed = self.line(line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .to_string();
+        self.record(Edit::Text { line, start: 0, removed, inserted: new_line });
+        Ok(self.lines.get(line).expect("line just set"))
+    }
+
+    // inserts whole lines before line `at` (at == len() appends them)
+    pub fn insert_lines(&mut self, at: usize, lines: Vec<String>) -> Result<(), PositionError> {
+        if at > self.lines.len() {
+            return Err(PositionError::LineOutOfRange { line: at, lines: self.lines.len() });
+        }
+        if !lines.is_empty() {
+            self.record(Edit::InsertLines { at, lines });
+        }
+        Ok(())
+    }
+
+    // removes the lines in range and returns them
+    pub fn remove_lines(&mut self, range: std::ops::Range<usize>) -> Result<Vec<String>, PositionError> {
+        if range.start > range.end {
+            return Err(PositionError::InvalidRange { start: range.start, end: range.end });
         }
+        if range.end > self.lines.len() {
+            return Err(PositionError::LineOutOfRange { line: range.end, lines: self.lines.len() });
+        }
+        let lines: Vec<String> = self.lines.iter_from(range.start).take(range.len()).map(String::from).collect();
+        if !lines.is_empty() {
+            self.record(Edit::RemoveLines { at: range.start, lines: lines.clone() });
+        }
+        Ok(lines)
+    }
 
-    // create a new LineEditor from a file
-    pub fn from_file(file_name: &str) -> Result<Self, io::Error> {
-        let file = File::open(file_name)?;
-        let reader = io::BufReader::new(file);
-        let mut lines = Vec::new();
-        for line in reader.lines() {
-            lines.push(line?);
+    // inserts text at a position of a line, each '\n' in text splits the line
+    pub fn insert_text(&mut self, line: usize, at: impl LinePosition, text: &str) -> Result<(), PositionError> {
+        let l = self.line(line)?;
+        let at = at.to_byte(l)?.0;
+        let mut parts: Vec<&str> = text.split('\n').map(|p| p.strip_suffix('\r').unwrap_or(p)).collect();
+        if parts.len() == 1 {
+            return self.replace(line, at, at, text);
         }
-        Ok(LineEditor { lines }) // se funziona crea un nuovo LineEditor
+        // the end of the line moves after the last part
+        let rest = l[at..].to_string();
+        let first = parts.remove(0).to_string();
+        let mut new_lines: Vec<String> = parts.iter().map(|p| p.to_string()).collect();
+        new_lines.last_mut().expect("at least two parts").push_str(&rest);
+        self.transaction(|editor| {
+            editor.record(Edit::Text { line, start: at, removed: rest, inserted: first });
+            editor.record(Edit::InsertLines { at: line + 1, lines: new_lines });
+        });
+        Ok(())
     }
 
-    pub fn all_lines(&self) -> Vec<&str> {
-        self.lines.iter().map(|l| l.as_str()).collect()
-    }
-
-    pub fn replace(&mut self, line: usize, start: usize, end: usize, subst: &str) {
-        // replace the text in the line
-        if line < self.lines.len() {
-            let l = &mut self.lines[line];
-            let start = start.min(l.len()); // cerca il minimo tra start e la lunghezza della stringa
-            let end = end.min(l.len()); // cerca il minimo tra end e la lunghezza della stringa
-            // così non vado fuori dagli indici
-            let new_line = format!("{}{}{}", &l[..start], subst, &l[end..]);
-            // formatta con la sottostringa iniziale fino a start, poi la nuova sottostringa
-            // poi quella finale da end in poi
-            self.lines[line] = new_line;
+    // deletes the text from (start line, start) to (end line, end), joining the two lines;
+    // returns the deleted text with '\n' between the lines
+    pub fn delete_text<P, Q>(&mut self, start: (usize, P), end: (usize, Q)) -> Result<String, PositionError>
+    where
+        P: LinePosition,
+        Q: LinePosition,
+    {
+        let (first_line, last_line) = (start.0, end.0);
+        if first_line > last_line {
+            return Err(PositionError::InvalidRange { start: first_line, end: last_line });
+        }
+        let first = self.line(first_line)?;
+        let last = self.line(last_line)?;
+        let start = start.1.to_byte(first)?.0;
+        let end = end.1.to_byte(last)?.0;
+        if first_line == last_line {
+            let deleted = first.get(start..end).ok_or(PositionError::InvalidRange { start, end })?.to_string();
+            self.replace(first_line, start, end, "")?;
+            return Ok(deleted);
+        }
+        let removed = first[start..].to_string();
+        let inserted = last[end..].to_string();
+        let mut deleted = removed.clone();
+        for l in self.lines.iter_from(first_line + 1).take(last_line - first_line) {
+            deleted.push('\n');
+            deleted.push_str(l);
         }
+        deleted.truncate(deleted.len() - inserted.len());
+        let lines: Vec<String> = self.lines.iter_from(first_line + 1).take(last_line - first_line).map(String::from).collect();
+        self.transaction(|editor| {
+            editor.record(Edit::Text { line: first_line, start, removed, inserted });
+            editor.record(Edit::RemoveLines { at: first_line + 1, lines });
+        });
+        Ok(deleted)
+    }
+
+    // applies the edit and adds it to the history
+    fn record(&mut self, edit: Edit) {
+        edit.apply(&mut self.lines);
+        self.history.record(edit);
+        self.version += 1;
+    }
+
+    // undo the last edit (or the last transaction), false if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        let done = self.history.undo(&mut self.lines);
+        self.version += done as u64;
+        done
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let done = self.history.redo(&mut self.lines);
+        self.version += done as u64;
+        done
+    }
+
+    // changes whenever the lines change (undo and redo too): positions computed
+    // on an older version may be stale
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    // how many edits (or transactions) are kept for undo, the oldest are forgotten
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    // all the edits made by f are undone with a single undo(); transactions can be nested
+    // the edits are kept whatever f returns, see try_transaction for a fallible f
+    pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let guard = Transaction::begin(self);
+        f(guard.editor)
+    }
+
+    // like transaction, but if f fails its edits are undone before returning the error
+    pub fn try_transaction<T, E>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        let mut guard = Transaction::begin(self);
+        let result = f(guard.editor);
+        guard.failed = result.is_err();
+        result
     }
+}
+
+// ends the transaction when dropped, so even a panic in f does not leave it open and
+// every later edit inside it; a panic keeps the edits made so far, like a success
+struct Transaction<'a> {
+    editor: &'a mut LineEditor,
+    mark: usize, // see History::begin
+    failed: bool,
+}
 
-    pub fn get_lines(&self) -> Vec<&String> {
-        let v = self.lines.iter().collect();
-        v
+impl<'a> Transaction<'a> {
+    fn begin(editor: &'a mut LineEditor) -> Self {
+        let mark = editor.history.begin();
+        Transaction { editor, mark, failed: false }
     }
+}
 
-    pub fn set_line(&mut self, line: usize, new_line: String) -> Result<&mut String, io::Error> {
-        if line < self.lines.len() {
-            self.lines[line] = new_line;
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        let editor = &mut *self.editor;
+        if self.failed {
+            editor.version += editor.history.rollback(self.mark, &mut editor.lines) as u64;
+        } else {
+            editor.history.commit();
         }
-        Ok(&mut self.lines[line])
     }
 }
 
@@ -66,7 +267,7 @@ impl LineEditor {
 // (2) Match contains the information about the match. Fix the lifetimes
 // repl will contain the replacement.
 // It is an Option because it may be not set yet or it may be skipped
-struct Match<'a> {
+pub struct Match<'a> {
     pub line: usize,
     pub start: usize,
     pub end: usize,
@@ -76,7 +277,7 @@ struct Match<'a> {
 
 // use the crate "regex" to find the pattern and its method find_iter for iterating over the matches
 // modify if necessary, this is just an example for using a regex to find a pattern
-fn find_example<'a>(lines: &[&'a str], pattern: &'a str) -> Vec<Match<'a>> { // si aspetta i lifetimes
+pub fn find_example<'a>(lines: &[&'a str], pattern: &'a str) -> Vec<Match<'a>> { // si aspetta i lifetimes
     let mut matches = Vec::new();
     let re = regex::Regex::new(pattern).expect("Invalid regex");
     for (line_idx, line) in lines.iter().enumerate() {
@@ -95,41 +296,96 @@ fn find_example<'a>(lines: &[&'a str], pattern: &'a str) -> Vec<Match<'a>> { //
 
 // (3) Fix the lifetimes of the FindReplace struct
 // (4) implement the Finder struct
-struct FindReplace<'a> {
+pub struct FindReplace<'a> {
     lines: Vec<&'a str>,
     pattern: String,
+    regex: regex::Regex, // kept to expand the capture groups of the templates
     matches: Vec<Match<'a>>, // matches dura quanto FindReplace
 }
 
 impl<'a> FindReplace<'a> { // lifetime anonimo
     pub fn new(lines: Vec<&'a str>, pattern: &'a str) -> Self {
         let matches = find_example(&lines, pattern);
-        let mut finder = FindReplace {
+        FindReplace {
             lines: lines.clone(),
             pattern: pattern.to_string(),
-            matches: matches,
-        };
-        finder
+            regex: regex::Regex::new(pattern).expect("Invalid regex"),
+            matches,
+        }
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
     }
 
     // return all the matches
-    pub fn matches(&self) -> &Vec<Match> {
+    pub fn matches(&self) -> &Vec<Match<'a>> {
         &self.matches
     }
 
     // apply a function to all matches and allow to accept them and set the repl
     // useful for promptig the user for a replacement
-    pub fn apply(&mut self, fun: impl Fn(&mut Match) -> bool) {
+    pub fn apply(&mut self, mut fun: impl FnMut(&mut Match) -> bool) {
         for m in &mut self.matches {
-            if fun(m) {
-                // if the function returns true, we accept the match
-                // and set the repl
-                m.repl = Some("some repl".to_string());
-            } else {
+            if !fun(m) {
                 // if the function returns false, we skip the match
                 m.repl = None;
             }
+            // if it returns true we keep the repl it has set
+        }
+    }
+
+    // accepts all the matches, the repl is the template with the capture groups of the
+    // match: "$1", "${1}", "$name" (see regex::Captures::expand); returns the accepted ones
+    pub fn replace_all_with_template(&mut self, template: &str) -> usize {
+        for m in &mut self.matches {
+            m.repl = replace::expand(&self.regex, self.lines[m.line], m.start, template);
+        }
+        self.matches.iter().filter(|m| m.repl.is_some()).count()
+    }
+
+    // the accepted replacements as a diff, nothing is changed
+    pub fn preview(&self) -> String {
+        let edits: Vec<_> = self.matches.iter()
+            .filter_map(|m| Some(((m.line, m.start), (m.line, m.end), m.repl.as_deref()?)))
+            .collect();
+        replace::preview(&self.lines, &edits)
+    }
+
+    // the position of a match in chars and in columns on screen (start and end are bytes)
+    pub fn char_span(&self, m: &Match) -> Result<(CharIndex, CharIndex), PositionError> {
+        let line = self.line(m.line)?;
+        Ok((position::char_index(line, m.start)?, position::char_index(line, m.end)?))
+    }
+
+    pub fn column_span(&self, m: &Match) -> Result<(DisplayColumn, DisplayColumn), PositionError> {
+        let line = self.line(m.line)?;
+        Ok((position::display_column(line, m.start)?, position::display_column(line, m.end)?))
+    }
+
+    fn line(&self, line: usize) -> Result<&'a str, PositionError> {
+        self.lines.get(line).copied().ok_or(PositionError::LineOutOfRange { line, lines: self.lines.len() })
+    }
+
+    // writes the accepted replacements (the matches with a repl) into the editor as a
+    // single transaction, so one undo() restores the text; returns how many were made
+    // the editor must still hold the lines the matches were searched in: all the matches
+    // are checked before changing anything, with an invalid one the editor is not touched
+    pub fn commit(&self, editor: &mut LineEditor) -> Result<usize, PositionError> {
+        let mut accepted: Vec<&Match> = self.matches.iter().filter(|m| m.repl.is_some()).collect();
+        for m in &accepted {
+            let line = editor.line(m.line)?;
+            let (start, end) = position::byte_range(line, m.start, m.end)?;
+            if line[start..end] != *m.text {
+                return Err(PositionError::TextChanged { line: m.line });
+            }
         }
+        // from the last to the first, so the offsets of the matches not yet replaced stay valid
+        accepted.sort_by_key(|m| std::cmp::Reverse((m.line, m.start)));
+        editor.try_transaction(|editor| {
+            accepted.iter().try_for_each(|m| editor.replace(m.line, m.start, m.end, m.repl.as_deref().unwrap_or_default()))
+        })?;
+        Ok(accepted.len())
     }
 }
 
@@ -164,30 +420,239 @@ fn test_find_replace() {
 
     let mut subs = Vec::new();
     for m in finder.matches() {
-        subs.push( /** add match if repl is set */
+        subs.push( /* add match if repl is set */
         (m.line, m.start, m.end, m.repl.as_ref().expect("repl not set").as_str()));
     }
 
     for (line, start, end, subst) in subs {
-        editor.replace(line, start, end, subst);
+        editor.replace(line, start, end, subst).unwrap();
     }
 
 }
 
 
+#[test]
+fn test_undo_redo() {
+    let mut editor = LineEditor::new("Hello World.\nsecond".to_string());
+    editor.replace(0, 6, 11, "Rust").unwrap();
+    editor.set_line(1, "2nd line".to_string()).unwrap();
+    assert_eq!(editor.all_lines(), vec!["Hello Rust.", "2nd line"]);
+    assert!(editor.set_line(5, String::new()).is_err());
+
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["Hello Rust.", "second"]);
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["Hello World.", "second"]);
+    assert!(!editor.undo());
+
+    assert!(editor.redo());
+    assert_eq!(editor.all_lines(), vec!["Hello Rust.", "second"]);
+    // a new edit drops what could be redone
+    editor.replace(1, 0, 0, ">").unwrap();
+    assert!(!editor.can_redo());
+    assert!(!editor.redo());
+    assert_eq!(editor.all_lines(), vec!["Hello Rust.", ">second"]);
+}
+
+#[test]
+fn test_find_replace_commit_is_one_transaction() {
+    let mut editor = LineEditor::new("ll all\nnull\nnone".to_string());
+    let lines_owned: Vec<String> = editor.all_lines().iter().map(|&l| l.to_string()).collect();
+    let lines: Vec<&str> = lines_owned.iter().map(|s| s.as_str()).collect();
+    let mut finder = FindReplace::new(lines, "ll");
+    // the matches on the first line have different lengths after the replacement
+    finder.apply(|m| {
+        m.repl = Some(format!("<{}:{}>", m.line, m.start));
+        m.line == 0
+    });
+    assert_eq!(finder.commit(&mut editor), Ok(2));
+    assert_eq!(editor.all_lines(), vec!["<0:0> a<0:4>", "null", "none"]);
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["ll all", "null", "none"]);
+    assert!(editor.redo());
+    assert_eq!(editor.all_lines()[0], "<0:0> a<0:4>");
+}
+
+#[test]
+fn test_nested_transactions_and_history_limit() {
+    let mut editor = LineEditor::new("abc".to_string());
+    editor.transaction(|e| {
+        e.replace(0, 0, 1, "A").unwrap();
+        e.transaction(|e| e.replace(0, 1, 2, "B")).unwrap();
+        e.replace(0, 2, 3, "C").unwrap();
+    });
+    assert_eq!(editor.all_lines(), vec!["ABC"]);
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["abc"]);
+
+    editor.set_history_limit(2);
+    for i in 0..5 {
+        editor.replace(0, 0, 0, &i.to_string()).unwrap();
+    }
+    assert!(editor.undo());
+    assert!(editor.undo());
+    assert!(!editor.undo()); // the older edits have been forgotten
+    assert_eq!(editor.all_lines(), vec!["210abc"]);
+}
+
+#[test]
+fn test_failed_transaction_is_rolled_back() {
+    let mut editor = LineEditor::new("abc\ndef".to_string());
+    editor.replace(1, 0, 1, "D").unwrap();
+    let result = editor.try_transaction(|e| {
+        e.replace(0, 0, 1, "A")?;
+        e.insert_text(0, 1, "\n")?;
+        e.try_transaction(|e| e.replace(0, 0, 0, "inner"))?;
+        e.replace(9, 0, 0, "no such line")
+    });
+    assert!(result.is_err());
+    assert_eq!(editor.all_lines(), vec!["abc", "Def"]);
+    // the rolled back edits are not in the history, the edit before is still there
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["abc", "def"]);
+    assert!(!editor.undo());
+
+    // a failed inner transaction keeps the edits of the outer one
+    editor.try_transaction(|e| {
+        e.replace(0, 0, 1, "A")?;
+        let inner = e.try_transaction(|e| {
+            e.replace(0, 1, 2, "B")?;
+            e.replace(9, 0, 0, "no such line")
+        });
+        assert!(inner.is_err());
+        e.replace(0, 2, 3, "C")
+    })
+    .unwrap();
+    assert_eq!(editor.all_lines(), vec!["AbC", "def"]);
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["abc", "def"]);
+}
+
+#[test]
+fn test_panic_in_transaction_closes_it() {
+    let mut editor = LineEditor::new("abc".to_string());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        editor.transaction(|e| {
+            e.replace(0, 0, 1, "A").unwrap();
+            panic!("in the middle of a transaction");
+        })
+    }));
+    assert!(result.is_err());
+    // the next edit is a group of its own, not part of the transaction left behind
+    editor.replace(0, 1, 2, "B").unwrap();
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["Abc"]);
+}
+
+#[test]
+fn test_history_limit_keeps_the_next_redo() {
+    let mut editor = LineEditor::new("x".to_string());
+    for c in ["a", "b", "c"] {
+        editor.replace(0, 0, 0, c).unwrap();
+    }
+    for _ in 0..3 {
+        assert!(editor.undo());
+    }
+    // the redo of "a" and "b" is kept, the one of "c" is forgotten
+    editor.set_history_limit(2);
+    assert!(editor.redo());
+    assert_eq!(editor.all_lines(), vec!["ax"]);
+    assert!(editor.redo());
+    assert_eq!(editor.all_lines(), vec!["bax"]);
+    assert!(!editor.redo());
+}
+
+
+#[test]
+fn test_replace_utf8_positions() {
+    let mut editor = LineEditor::new("La città è già qui.".to_string());
+    // byte 8 is inside "à": an error instead of a panic, the line is not changed
+    assert_eq!(editor.replace(0, 3, 8, "x"), Err(PositionError::NotCharBoundary { offset: 8 }));
+    assert_eq!(editor.replace(1, 0, 0, "x"), Err(PositionError::LineOutOfRange { line: 1, lines: 1 }));
+    assert!(editor.replace(0, 0, 100, "x").is_err());
+    assert!(!editor.can_undo());
+
+    editor.replace(0, CharIndex(3), CharIndex(8), "casa").unwrap();
+    assert_eq!(editor.all_lines(), vec!["La casa è già qui."]);
+
+    let lines_owned: Vec<String> = editor.all_lines().iter().map(|&l| l.to_string()).collect();
+    let lines: Vec<&str> = lines_owned.iter().map(|s| s.as_str()).collect();
+    let mut finder = FindReplace::new(lines, "già");
+    let m = &finder.matches()[0];
+    assert_eq!((m.start, m.end), (11, 15)); // bytes
+    assert_eq!(finder.char_span(m), Ok((CharIndex(10), CharIndex(13))));
+    assert_eq!(finder.column_span(m), Ok((DisplayColumn(10), DisplayColumn(13))));
+
+    finder.apply(|m| {
+        m.repl = Some("ora".to_string());
+        true
+    });
+    editor.replace(0, 0, 0, "(").unwrap(); // the matches are stale now
+    assert!(finder.commit(&mut editor).is_err());
+    assert_eq!(editor.all_lines(), vec!["(La casa è già qui."]);
+    editor.undo();
+    assert_eq!(finder.commit(&mut editor), Ok(1));
+    assert_eq!(editor.all_lines(), vec!["La casa è ora qui."]);
+}
+
+
+#[test]
+fn test_edits_spanning_lines() {
+    let mut editor = LineEditor::new("uno\ndue\ntre\nquattro".to_string());
+    editor.insert_lines(1, vec!["1.5".to_string(), "1.7".to_string()]).unwrap();
+    assert_eq!(editor.all_lines(), vec!["uno", "1.5", "1.7", "due", "tre", "quattro"]);
+    assert_eq!(editor.remove_lines(3..5).unwrap(), vec!["due", "tre"]);
+    assert_eq!(editor.len(), 4);
+    assert!(editor.insert_lines(5, vec![]).is_err());
+    assert!(editor.remove_lines(2..9).is_err());
+
+    // "uno|1.5|1.7|quattro": delete from "n" of "uno" to "t" of "quattro"
+    assert_eq!(editor.delete_text((0, 1), (3, CharIndex(3))).unwrap(), "no\n1.5\n1.7\nqua");
+    assert_eq!(editor.all_lines(), vec!["uttro"]);
+    editor.insert_text(0, 1, "A\nB\r\nC").unwrap();
+    assert_eq!(editor.all_lines(), vec!["uA", "B", "Cttro"]);
+    assert_eq!(editor.iter_from(1).collect::<Vec<_>>(), vec!["B", "Cttro"]);
+
+    // each of them is undone in one step
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["uttro"]);
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["uno", "1.5", "1.7", "quattro"]);
+    assert!(editor.undo());
+    assert!(editor.undo());
+    assert_eq!(editor.all_lines(), vec!["uno", "due", "tre", "quattro"]);
+    assert!(editor.redo());
+    assert_eq!(editor.get_lines(), vec!["uno", "1.5", "1.7", "due", "tre", "quattro"]);
+}
+
+
+#[test]
+fn test_replace_with_template() {
+    let mut editor = LineEditor::new("2024-01-15 start\nnothing\n2023-12-31 end 2022-02-02".to_string());
+    let lines_owned: Vec<String> = editor.all_lines().iter().map(|&l| l.to_string()).collect();
+    let lines: Vec<&str> = lines_owned.iter().map(|s| s.as_str()).collect();
+    let mut finder = FindReplace::new(lines, r"(?<y>\d{4})-(\d\d)-(\d\d)");
+    assert_eq!(finder.replace_all_with_template("$3/$2/$y"), 3);
+    assert_eq!(finder.preview(), "@@ -1,1 +1,1 @@\n-2024-01-15 start\n+15/01/2024 start\n\
+                                  @@ -3,1 +3,1 @@\n-2023-12-31 end 2022-02-02\n+31/12/2023 end 02/02/2022\n");
+    assert_eq!(finder.commit(&mut editor), Ok(3));
+    assert_eq!(editor.all_lines(), vec!["15/01/2024 start", "nothing", "31/12/2023 end 02/02/2022"]);
+}
+
+
 // (6) sometimes it's very expensive to find all the matches at once before applying
 // the changes
 // we can implement a lazy finder that finds just the next match and returns it
 // each call to next() will return the next match
 // this is a naive implementation of an Iterarator
 
-#[derive(Debug, Clone, Copy)]
-struct FinderPos {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FinderPos {
     pub line: usize,
     pub offset: usize,
 }
 
-struct LazyFinder<'a> {
+pub struct LazyFinder<'a> {
     lines: Vec<&'a str>,
     pattern: String,
     pos: Option<FinderPos>,
@@ -205,6 +670,12 @@ impl<'a> LazyFinder<'a> {
             }
         }
 
+    // the next call to next() searches from (line, byte offset), e.g. from the cursor
+    pub fn seek(&mut self, line: usize, offset: usize) {
+        let offset = self.lines.get(line).map_or(0, |l| position::snap_floor(l, offset).0);
+        self.pos = Some(FinderPos { line, offset });
+    }
+
     // remember:
     // return None if there are no more matches
     // return Some(Match) if there is a match
@@ -213,12 +684,7 @@ impl<'a> LazyFinder<'a> {
         'a: 'b, // Assicura che il lifetime 'a sia più lungo di 'b
         {
         // Se non ci sono più righe da analizzare, restituisci None
-        if self.pos.is_none() {
-            return None;
-        }
-
-        // Ottieni la posizione corrente
-        let pos = self.pos.unwrap();
+        let pos = self.pos?;
         let line = self.lines.get(pos.line).copied()?; // Usa `.copied()` per ottenere un riferimento valido
 
         // Trova il pattern nella riga corrente
@@ -256,7 +722,7 @@ impl<'a> LazyFinder<'a> {
 #[test]
 fn test_lazy_finder() {
     let s = "Hello World.\nA second line full of text.";
-    let mut editor = LineEditor::new(s.to_string());
+    let editor = LineEditor::new(s.to_string());
 
     let lines = editor.all_lines();
     let mut finder = LazyFinder::new(lines, "ll");
@@ -270,49 +736,148 @@ fn test_lazy_finder() {
 
 // (8) now you have everything you need to implement the real Iterator
 
-struct FindIter<'a> {
-    lines: Vec<&'a str>,
-    pattern: String,
+// the lines a FindIter searches: a Vec as in the exercise, or the editor itself, so
+// a search in a big file does not copy all the lines first
+enum FindLines<'a> {
+    Vec(Vec<&'a str>),
+    Editor(&'a LineEditor),
+}
+
+impl<'a> FindLines<'a> {
+    fn len(&self) -> usize {
+        match self {
+            FindLines::Vec(lines) => lines.len(),
+            FindLines::Editor(editor) => editor.len(),
+        }
+    }
+
+    fn get(&self, i: usize) -> &'a str {
+        match self {
+            FindLines::Vec(lines) => lines[i],
+            FindLines::Editor(editor) => editor.line(i).unwrap_or_default(),
+        }
+    }
+}
 
+// the matches starting between front (included) and back (excluded), in both directions:
+// next() and next_back() meet in the middle without returning a match twice, and both
+// see the same non-overlapping matches that find_iter would find starting at front
+pub struct FindIter<'a> {
+    lines: FindLines<'a>,
+    regex: regex::Regex,
+    front: FinderPos,
+    after_match: bool, // front is the end of a match: an empty match there is skipped
+    back: FinderPos,
 }
 
 impl<'a> FindIter<'a> {
     pub fn new(lines: Vec<&'a str>, pattern: &'a str) -> Self {
-        let mut finder = FindIter {
-            lines: lines.clone(),
-            pattern: pattern.to_string(),
+        FindIter::from_pos(lines, pattern, (0, 0))
+    }
+
+    // the matches starting at (line, byte offset) or after it; an offset inside a char
+    // moves back to the start of the char
+    pub fn from_pos(lines: Vec<&'a str>, pattern: &'a str, pos: (usize, usize)) -> Self {
+        let regex = regex::Regex::new(pattern).expect("Invalid regex");
+        let end = (lines.len(), 0);
+        FindIter::between(FindLines::Vec(lines), regex, pos, end)
+    }
+
+    // the matches of regex in the editor starting from start up to end (excluded), e.g.
+    // the ones before the cursor are in_editor(editor, regex, (0, 0), cursor).rev()
+    pub fn in_editor(editor: &'a LineEditor, regex: regex::Regex, start: (usize, usize), end: (usize, usize)) -> Self {
+        FindIter::between(FindLines::Editor(editor), regex, start, end)
+    }
+
+    fn between(lines: FindLines<'a>, regex: regex::Regex, start: (usize, usize), end: (usize, usize)) -> Self {
+        let snap = |(line, offset): (usize, usize)| {
+            if line < lines.len() {
+                FinderPos { line, offset: position::snap_floor(lines.get(line), offset).0 }
+            } else {
+                FinderPos { line: lines.len(), offset: 0 }
+            }
         };
-        finder
+        let (front, back) = (snap(start), snap(end));
+        FindIter { lines, regex, front, after_match: false, back }
+    }
+
+    fn before_back(&self, line: usize, start: usize) -> bool {
+        (line, start) < (self.back.line, self.back.offset)
+    }
+
+    // the first match of text at or after from, as find_iter would return it
+    fn find_at(&self, text: &str, from: usize, after_match: bool) -> Option<(usize, usize)> {
+        if from > text.len() {
+            return None;
+        }
+        let m = self.regex.find_at(text, from)?;
+        if after_match && m.is_empty() && m.start() == from {
+            let next = from + text[from..].chars().next()?.len_utf8();
+            return self.find_at(text, next, false);
+        }
+        Some((m.start(), m.end()))
+    }
+
+    // where the search goes on after the match start..end: (offset, after_match)
+    fn step(text: &str, start: usize, end: usize) -> (usize, bool) {
+        if start < end {
+            (end, true)
+        } else {
+            (end + text[end..].chars().next().map_or(1, |c| c.len_utf8()), false)
+        }
+    }
+
+    fn make_match(&self, line: usize, text: &'a str, (start, end): (usize, usize)) -> Match<'a> {
+        Match { line, start, end, text: &text[start..end], repl: None }
     }
 }
 
 impl<'a> Iterator for FindIter<'a> {
     type Item = Match<'a>; // <== we inform the Iterator that we return a Match
 
-    fn next<'b>(&'b mut self) -> Option<Self::Item> {
-        // Se non ci sono più righe da analizzare, restituisci None
-        if self.lines.is_empty() {
-            return None;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front.line < self.lines.len() && self.before_back(self.front.line, self.front.offset) {
+            let line = self.front.line;
+            let text = self.lines.get(line);
+            match self.find_at(text, self.front.offset, self.after_match) {
+                Some(m) if self.before_back(line, m.0) => {
+                    (self.front.offset, self.after_match) = Self::step(text, m.0, m.1);
+                    return Some(self.make_match(line, text, m));
+                }
+                Some(_) => break, // past back: the rest was returned by next_back()
+                None => {
+                    self.front = FinderPos { line: line + 1, offset: 0 };
+                    self.after_match = false;
+                }
+            }
         }
+        self.front = self.back; // nothing left
+        None
+    }
+}
 
-        // Ottieni la riga corrente
-        let line = self.lines.remove(0); // Rimuovi la riga corrente dalla lista
-
-        // Trova il pattern nella riga corrente
-        let re = regex::Regex::new(&self.pattern).expect("Invalid regex");
-        if let Some(mat) = re.find(line) {
-            // Se trovi un match, crea un nuovo Match e restituiscilo
-            Some(Match {
-                line: 0,
-                start: mat.start(),
-                end: mat.end(),
-                text: &line[mat.start()..mat.end()],
-                repl: None,
-            })
-        } else {
-            // Se non trovi un match, chiama next() ricorsivamente per cercare nella prossima riga
-            self.next()
+impl DoubleEndedIterator for FindIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let last = self.back.line.min(self.lines.len().checked_sub(1)?);
+        for line in (self.front.line..=last).rev() {
+            let text = self.lines.get(line);
+            // the last match before back in the sequence that next() would walk
+            let (mut from, mut after_match) = if line == self.front.line { (self.front.offset, self.after_match) } else { (0, false) };
+            let mut found = None;
+            while let Some(m) = self.find_at(text, from, after_match) {
+                if !self.before_back(line, m.0) {
+                    break;
+                }
+                found = Some(m);
+                (from, after_match) = Self::step(text, m.0, m.1);
+            }
+            if let Some(m) = found {
+                self.back = FinderPos { line, offset: m.0 };
+                return Some(self.make_match(line, text, m));
+            }
         }
+        self.back = self.front;
+        None
     }
 }
 
@@ -320,10 +885,10 @@ impl<'a> Iterator for FindIter<'a> {
 #[test]
 fn test_find_iter() {
     let s = "Hello World.\nA second line full of text.";
-    let mut editor = LineEditor::new(s.to_string());
+    let editor = LineEditor::new(s.to_string());
 
     let lines = editor.all_lines();
-    let mut finder = FindIter::new(lines, "ll");
+    let finder = FindIter::new(lines, "ll");
 
     // find all the matches and accept them
     for m in finder {
@@ -332,5 +897,50 @@ fn test_find_iter() {
     }
 }
 
+#[test]
+fn test_find_iter_both_ends() {
+    let lines = vec!["ab ab", "", "xab", "città ab"];
+    let all: Vec<(usize, usize)> = FindIter::new(lines.clone(), "ab").map(|m| (m.line, m.start)).collect();
+    assert_eq!(all, vec![(0, 0), (0, 3), (2, 1), (3, 7)]);
+    let mut back: Vec<(usize, usize)> = FindIter::new(lines.clone(), "ab").rev().map(|m| (m.line, m.start)).collect();
+    back.reverse();
+    assert_eq!(back, all);
+
+    // from both ends: they meet in the middle
+    let mut finder = FindIter::new(lines.clone(), "ab");
+    assert_eq!(finder.next().map(|m| m.start), Some(0));
+    assert_eq!(finder.next_back().map(|m| (m.line, m.start)), Some((3, 7)));
+    assert_eq!(finder.next_back().map(|m| (m.line, m.start)), Some((2, 1)));
+    assert_eq!(finder.next().map(|m| (m.line, m.start)), Some((0, 3)));
+    assert!(finder.next().is_none() && finder.next_back().is_none());
+
+    // seeded inside a line, an offset inside "à" is moved back to its start
+    let from: Vec<(usize, usize)> = FindIter::from_pos(lines.clone(), "ab", (0, 1)).map(|m| (m.line, m.start)).collect();
+    assert_eq!(from, vec![(0, 3), (2, 1), (3, 7)]);
+    let from_inside_char = FindIter::from_pos(lines.clone(), "à|ab", (3, 5)).next().unwrap();
+    assert_eq!((from_inside_char.line, from_inside_char.text), (3, "à"));
+
+    // empty matches are the same in both directions
+    let empty: Vec<(usize, usize)> = FindIter::new(vec!["aab"], "a*").map(|m| (m.start, m.end)).collect();
+    let mut empty_back: Vec<(usize, usize)> = FindIter::new(vec!["aab"], "a*").rev().map(|m| (m.start, m.end)).collect();
+    empty_back.reverse();
+    assert_eq!(empty, empty_back);
+    let expected: Vec<(usize, usize)> = regex::Regex::new("a*").unwrap().find_iter("aab").map(|m| (m.start(), m.end())).collect();
+    assert_eq!(empty, expected);
+}
+
 
-fn main() {}
+fn main() {
+    // --bench [lines]: compares the old Vec<String> storage with the rope
+    // otherwise the ed-like editor, see ed.rs
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("--bench") {
+        let lines = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(1_000_000);
+        if let Err(e) = rope::benchmark(lines, 1_000, &mut io::stdout().lock()) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+    let code = ed::run(args.into_iter(), io::stdin().lock(), &mut io::stdout().lock(), &mut io::stderr().lock());
+    std::process::exit(code);
+}
@@ -0,0 +1,178 @@
+// positions inside a line
+// Rust strings are UTF-8: "città" is 5 chars but 6 bytes, and slicing it at byte 5
+// (inside the "à") panics. The regex offsets are bytes, the user counts chars or
+// looks at the columns on the screen, so each kind of position has its own type:
+// - ByteOffset: what str slicing and regex use
+// - CharIndex: the number of chars (unicode scalar values) before the position
+// - DisplayColumn: the width on screen of the graphemes before the position, a grapheme
+//   ("e" + combining accent, an emoji with modifiers) is what the user sees as one character
+// and all of them are converted to a ByteOffset checking that it is valid for the line
+
+use std::fmt;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteOffset(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CharIndex(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DisplayColumn(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    LineOutOfRange { line: usize, lines: usize },
+    OutOfBounds { position: usize, len: usize }, // len in the unit of the position
+    NotCharBoundary { offset: usize }, // a byte offset inside a multi-byte char
+    InvalidRange { start: usize, end: usize }, // start after end (byte offsets)
+    TextChanged { line: usize }, // the line is not the one a match was found in
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::LineOutOfRange { line, lines } => write!(f, "line {} out of range ({} lines)", line, lines),
+            PositionError::OutOfBounds { position, len } => write!(f, "position {} past the end of the line ({})", position, len),
+            PositionError::NotCharBoundary { offset } => write!(f, "byte {} is inside a multi-byte character", offset),
+            PositionError::InvalidRange { start, end } => write!(f, "invalid range {}..{}", start, end),
+            PositionError::TextChanged { line } => write!(f, "line {} changed after the search", line),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+// anything that can be turned into a valid byte offset of a line
+pub trait LinePosition: Copy {
+    fn to_byte(self, line: &str) -> Result<ByteOffset, PositionError>;
+}
+
+impl LinePosition for ByteOffset {
+    fn to_byte(self, line: &str) -> Result<ByteOffset, PositionError> {
+        if self.0 > line.len() {
+            return Err(PositionError::OutOfBounds { position: self.0, len: line.len() });
+        }
+        if !line.is_char_boundary(self.0) {
+            return Err(PositionError::NotCharBoundary { offset: self.0 });
+        }
+        Ok(self)
+    }
+}
+
+// plain numbers are byte offsets, as they have always been for LineEditor::replace
+impl LinePosition for usize {
+    fn to_byte(self, line: &str) -> Result<ByteOffset, PositionError> {
+        ByteOffset(self).to_byte(line)
+    }
+}
+
+impl LinePosition for CharIndex {
+    fn to_byte(self, line: &str) -> Result<ByteOffset, PositionError> {
+        match line.char_indices().nth(self.0) {
+            Some((b, _)) => Ok(ByteOffset(b)),
+            None => {
+                let len = line.chars().count();
+                if self.0 == len { Ok(ByteOffset(line.len())) } else { Err(PositionError::OutOfBounds { position: self.0, len }) }
+            }
+        }
+    }
+}
+
+// a column in the middle of a wide grapheme (e.g. a CJK ideograph takes 2 columns)
+// snaps to the start of the grapheme
+impl LinePosition for DisplayColumn {
+    fn to_byte(self, line: &str) -> Result<ByteOffset, PositionError> {
+        let mut column = 0;
+        for (b, g) in line.grapheme_indices(true) {
+            let width = g.width();
+            if self.0 < column + width.max(1) {
+                return Ok(ByteOffset(b));
+            }
+            column += width;
+        }
+        if self.0 == column {
+            Ok(ByteOffset(line.len()))
+        } else {
+            Err(PositionError::OutOfBounds { position: self.0, len: column })
+        }
+    }
+}
+
+// the char index of a byte offset
+pub fn char_index(line: &str, offset: impl LinePosition) -> Result<CharIndex, PositionError> {
+    let b = offset.to_byte(line)?.0;
+    Ok(CharIndex(line[..b].chars().count()))
+}
+
+// the display column of a byte offset
+pub fn display_column(line: &str, offset: impl LinePosition) -> Result<DisplayColumn, PositionError> {
+    let b = offset.to_byte(line)?.0;
+    Ok(DisplayColumn(line[..b].graphemes(true).map(|g| g.width()).sum()))
+}
+
+// the closest char boundary at or before offset, offsets past the end go to the end
+pub fn snap_floor(line: &str, offset: usize) -> ByteOffset {
+    let mut b = offset.min(line.len());
+    while !line.is_char_boundary(b) {
+        b -= 1;
+    }
+    ByteOffset(b)
+}
+
+// the closest char boundary at or after offset
+pub fn snap_ceil(line: &str, offset: usize) -> ByteOffset {
+    let mut b = offset.min(line.len());
+    while !line.is_char_boundary(b) {
+        b += 1;
+    }
+    ByteOffset(b)
+}
+
+// start and end of a range as valid byte offsets, start <= end
+pub fn byte_range(line: &str, start: impl LinePosition, end: impl LinePosition) -> Result<(usize, usize), PositionError> {
+    let (start, end) = (start.to_byte(line)?.0, end.to_byte(line)?.0);
+    if start > end {
+        return Err(PositionError::InvalidRange { start, end });
+    }
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let line = "perché è già così";
+        assert_eq!(CharIndex(6).to_byte(line), Ok(ByteOffset(7))); // dopo "perché"
+        assert_eq!(char_index(line, 7), Ok(CharIndex(6)));
+        assert_eq!(char_index(line, 6), Err(PositionError::NotCharBoundary { offset: 6 }));
+        assert_eq!(CharIndex(17).to_byte(line), Ok(ByteOffset(line.len())));
+        assert_eq!(CharIndex(18).to_byte(line), Err(PositionError::OutOfBounds { position: 18, len: 17 }));
+        assert_eq!(display_column(line, ByteOffset(line.len())), Ok(DisplayColumn(17)));
+    }
+
+    #[test]
+    fn test_display_columns_of_graphemes() {
+        let line = "e\u{301}日x"; // "e" + combining accent (1 column), a wide ideograph (2 columns)
+        assert_eq!(DisplayColumn(1).to_byte(line), Ok(ByteOffset(3)));
+        assert_eq!(DisplayColumn(2).to_byte(line), Ok(ByteOffset(3))); // inside 日: snaps to its start
+        assert_eq!(DisplayColumn(3).to_byte(line), Ok(ByteOffset(6)));
+        assert_eq!(display_column(line, 6), Ok(DisplayColumn(3)));
+        assert_eq!(DisplayColumn(4).to_byte(line), Ok(ByteOffset(7)));
+        assert!(DisplayColumn(5).to_byte(line).is_err());
+    }
+
+    #[test]
+    fn test_snap_and_ranges() {
+        let line = "città";
+        assert_eq!(snap_floor(line, 5), ByteOffset(4));
+        assert_eq!(snap_ceil(line, 5), ByteOffset(6));
+        assert_eq!(snap_floor(line, 100), ByteOffset(6));
+        assert_eq!(byte_range(line, CharIndex(1), 6), Ok((1, 6)));
+        assert_eq!(byte_range(line, 4, 2), Err(PositionError::InvalidRange { start: 4, end: 2 }));
+    }
+}
@@ -0,0 +1,270 @@
+// search and replace over the whole buffer
+// - MultiLineFindReplace runs the regex on the lines joined with '\n', so a pattern
+//   like "foo\n\s*bar" or "(?s)BEGIN.*?END" can match across lines; the byte offsets
+//   of the joined text are mapped back to (line, byte in the line)
+// - the replacement can be a template with the capture groups: "$name-$1" (see
+//   regex::Captures::expand, "$$" is a literal "$")
+// - preview() shows the changes as a diff before they are written into the LineEditor
+
+use std::fmt::Write;
+
+use regex::Regex;
+
+use crate::LineEditor;
+use crate::position::PositionError;
+
+// (line, byte offset in the line)
+pub type LinePos = (usize, usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiLineMatch {
+    pub start: LinePos,
+    pub end: LinePos, // exclusive, on the same line as start or on a following one
+    pub text: String, // the lines of the match are separated by '\n'
+    pub repl: Option<String>,
+    offset: usize, // start in the joined text, to run the captures again
+}
+
+pub struct MultiLineFindReplace {
+    text: String, // the lines joined with '\n'
+    line_starts: Vec<usize>, // offset of each line in text
+    regex: Regex,
+    matches: Vec<MultiLineMatch>,
+}
+
+impl MultiLineFindReplace {
+    pub fn new(editor: &LineEditor, pattern: &str) -> Result<Self, regex::Error> {
+        let regex = Regex::new(pattern)?;
+        // an empty buffer has no lines, not one empty line: nothing to match, even for
+        // a pattern like "x*" that matches the empty string
+        let mut text = String::new();
+        let mut line_starts = Vec::with_capacity(editor.len());
+        for (i, line) in editor.iter().enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            line_starts.push(text.len());
+            text.push_str(line);
+        }
+        let mut finder = MultiLineFindReplace { text, line_starts, regex, matches: Vec::new() };
+        if finder.line_starts.is_empty() {
+            return Ok(finder);
+        }
+        finder.matches = finder.regex.find_iter(&finder.text)
+            .map(|m| MultiLineMatch {
+                start: finder.line_pos(m.start()),
+                end: finder.line_pos(m.end()),
+                text: m.as_str().to_string(),
+                repl: None,
+                offset: m.start(),
+            })
+            .collect();
+        Ok(finder)
+    }
+
+    // the line holding the byte offset of the joined text
+    fn line_pos(&self, offset: usize) -> LinePos {
+        let line = self.line_starts.partition_point(|&s| s <= offset).saturating_sub(1);
+        (line, offset - self.line_starts.get(line).copied().unwrap_or(0))
+    }
+
+    // the line as it was when the search was run, without the '\n'
+    fn line(&self, line: usize) -> &str {
+        let end = self.line_starts.get(line + 1).map_or(self.text.len(), |&s| s - 1);
+        &self.text[self.line_starts[line]..end]
+    }
+
+    pub fn matches(&self) -> &[MultiLineMatch] {
+        &self.matches
+    }
+
+    // like FindReplace::apply: false skips the match, true keeps the repl set by fun
+    pub fn apply(&mut self, mut fun: impl FnMut(&mut MultiLineMatch) -> bool) {
+        for m in &mut self.matches {
+            if !fun(m) {
+                m.repl = None;
+            }
+        }
+    }
+
+    // accepts all the matches, replacing them with the expanded template
+    pub fn replace_all_with_template(&mut self, template: &str) -> usize {
+        for m in &mut self.matches {
+            m.repl = expand(&self.regex, &self.text, m.offset, template);
+        }
+        self.matches.iter().filter(|m| m.repl.is_some()).count()
+    }
+
+    // the accepted replacements as a diff of the lines they change
+    pub fn preview(&self) -> String {
+        let lines: Vec<&str> = self.text.split('\n').collect();
+        let lines = if self.line_starts.is_empty() { &[][..] } else { &lines[..] };
+        let edits: Vec<(LinePos, LinePos, &str)> = self.matches.iter()
+            .filter_map(|m| Some((m.start, m.end, m.repl.as_deref()?)))
+            .collect();
+        preview(lines, &edits)
+    }
+
+    // writes the accepted replacements into the editor as one transaction, if one of them
+    // fails the ones already made are undone; the editor must still hold the text the
+    // search was run on
+    pub fn commit(&self, editor: &mut LineEditor) -> Result<usize, PositionError> {
+        // the first line that changed, or the first one that only one of the two texts has
+        let lines = self.line_starts.len();
+        let changed = editor.iter().take(lines).enumerate()
+            .position(|(i, l)| self.line(i) != l)
+            .or_else(|| (editor.len() != lines).then(|| editor.len().min(lines)));
+        if let Some(line) = changed {
+            return Err(PositionError::TextChanged { line });
+        }
+        let accepted: Vec<&MultiLineMatch> = self.matches.iter().filter(|m| m.repl.is_some()).collect();
+        editor.try_transaction(|editor| {
+            // from the last one, so the positions of the others are still valid
+            accepted.iter().rev().try_for_each(|m| {
+                editor.delete_text(m.start, m.end)?;
+                editor.insert_text(m.start.0, m.start.1, m.repl.as_deref().unwrap_or_default())
+            })
+        })?;
+        Ok(accepted.len())
+    }
+}
+
+// the template expanded with the captures of the match starting at offset of haystack
+pub fn expand(regex: &Regex, haystack: &str, offset: usize, template: &str) -> Option<String> {
+    // captures_at looks at the text before offset too, so \b and ^ behave as in the search
+    let caps = regex.captures_at(haystack, offset)?;
+    if caps.get(0)?.start() != offset {
+        return None;
+    }
+    let mut repl = String::new();
+    caps.expand(template, &mut repl);
+    Some(repl)
+}
+
+// a diff of the replacements (start, end, new text) on lines, sorted and not overlapping:
+// the replacements touching the same lines are shown together as
+// @@ -first,count +first,count @@ with the old lines (-) and the new ones (+)
+pub fn preview(lines: &[&str], edits: &[(LinePos, LinePos, &str)]) -> String {
+    let mut out = String::new();
+    let mut shift: isize = 0; // lines added (or removed) by the previous hunks
+    let mut i = 0;
+    while i < edits.len() {
+        // the hunk goes on while the next edit starts on a line already touched
+        let first = edits[i].0.0;
+        let mut last = edits[i].1.0;
+        let mut j = i + 1;
+        while j < edits.len() && edits[j].0.0 <= last {
+            last = last.max(edits[j].1.0);
+            j += 1;
+        }
+        let old = &lines[first..=last];
+        let mut new = String::new();
+        let mut pos = (first, 0);
+        for &(start, end, repl) in &edits[i..j] {
+            push_text(&mut new, lines, pos, start);
+            new.push_str(repl);
+            pos = end;
+        }
+        push_text(&mut new, lines, pos, (last, lines[last].len()));
+        let new: Vec<&str> = new.split('\n').collect();
+
+        let new_first = (first as isize + shift + 1) as usize;
+        writeln!(out, "@@ -{},{} +{},{} @@", first + 1, old.len(), new_first, new.len()).unwrap();
+        for l in old {
+            writeln!(out, "-{}", l).unwrap();
+        }
+        for l in &new {
+            writeln!(out, "+{}", l).unwrap();
+        }
+        shift += new.len() as isize - old.len() as isize;
+        i = j;
+    }
+    out
+}
+
+// the text of lines from `from` to `to` (line, byte), with '\n' between the lines
+fn push_text(out: &mut String, lines: &[&str], from: LinePos, to: LinePos) {
+    if from.0 == to.0 {
+        out.push_str(&lines[from.0][from.1..to.1]);
+        return;
+    }
+    out.push_str(&lines[from.0][from.1..]);
+    for line in &lines[from.0 + 1..to.0] {
+        out.push('\n');
+        out.push_str(line);
+    }
+    out.push('\n');
+    out.push_str(&lines[to.0][..to.1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "[server]\nhost = old.example.com\nport = 80\n[client]\nretries = 3";
+
+    #[test]
+    fn test_match_across_lines() {
+        let editor = LineEditor::new(CONFIG.to_string());
+        let finder = MultiLineFindReplace::new(&editor, r"(?m)com\nport = (\d+)").unwrap();
+        let m = &finder.matches()[0];
+        assert_eq!(m.start, (1, 19));
+        assert_eq!(m.end, (2, 9));
+        assert_eq!(m.text, "com\nport = 80");
+        assert!(MultiLineFindReplace::new(&editor, "(").is_err());
+    }
+
+    #[test]
+    fn test_template_preview_and_commit() {
+        let mut editor = LineEditor::new(CONFIG.to_string());
+        let mut finder = MultiLineFindReplace::new(&editor, r"host = (?<name>\w+)\.example\.com\nport = (\d+)").unwrap();
+        assert_eq!(finder.replace_all_with_template("url = http://$name:$2/\nhost = $name"), 1);
+        assert_eq!(finder.preview(), "@@ -2,2 +2,2 @@\n-host = old.example.com\n-port = 80\n+url = http://old:80/\n+host = old\n");
+        assert_eq!(editor.all_lines()[1], "host = old.example.com"); // the preview changes nothing
+        assert_eq!(finder.commit(&mut editor), Ok(1));
+        assert_eq!(editor.all_lines(), vec!["[server]", "url = http://old:80/", "host = old", "[client]", "retries = 3"]);
+        // a stale search is refused
+        assert_eq!(finder.commit(&mut editor), Err(PositionError::TextChanged { line: 1 }));
+        assert!(editor.undo());
+        assert_eq!(editor.all_lines().join("\n"), CONFIG);
+    }
+
+    #[test]
+    fn test_join_sections() {
+        // the replacement removes lines: the next hunk starts before its old line number
+        let mut editor = LineEditor::new("a\n\n\nb\nc\nc\nd".to_string());
+        let mut finder = MultiLineFindReplace::new(&editor, r"\n\n+|c\nc").unwrap();
+        finder.apply(|m| {
+            m.repl = Some(if m.text.starts_with('c') { "C".to_string() } else { "\n".to_string() });
+            true
+        });
+        assert_eq!(finder.preview(), "@@ -1,4 +1,2 @@\n-a\n-\n-\n-b\n+a\n+b\n@@ -5,2 +3,1 @@\n-c\n-c\n+C\n");
+        assert_eq!(finder.commit(&mut editor), Ok(2));
+        assert_eq!(editor.all_lines(), vec!["a", "b", "C", "d"]);
+    }
+
+    #[test]
+    fn test_empty_buffer() {
+        let mut editor = LineEditor::new(String::new());
+        let mut finder = MultiLineFindReplace::new(&editor, "x*").unwrap();
+        assert_eq!(finder.replace_all_with_template("y"), 0);
+        assert_eq!(finder.preview(), "");
+        assert_eq!(finder.commit(&mut editor), Ok(0));
+        assert!(editor.is_empty());
+    }
+
+    #[test]
+    fn test_stale_search_reports_the_changed_line() {
+        let mut editor = LineEditor::new("a\nbc\nd".to_string());
+        let finder = MultiLineFindReplace::new(&editor, "d").unwrap();
+        // a line cut short is a change too, not only a different prefix
+        editor.set_line(1, "b".to_string()).unwrap();
+        assert_eq!(finder.commit(&mut editor), Err(PositionError::TextChanged { line: 1 }));
+        editor.undo();
+        editor.insert_lines(3, vec!["e".to_string()]).unwrap();
+        assert_eq!(finder.commit(&mut editor), Err(PositionError::TextChanged { line: 3 }));
+        editor.undo();
+        editor.remove_lines(2..3).unwrap();
+        assert_eq!(finder.commit(&mut editor), Err(PositionError::TextChanged { line: 2 }));
+    }
+}
@@ -0,0 +1,515 @@
+// the storage of LineEditor: a rope of lines
+// - the lines live in the leaves of a B-tree, each inner node knows how many lines are
+//   under each child, so finding line i, inserting or removing a line is O(log n)
+//   instead of shifting a Vec of millions of Strings
+// - a line is either a range of the original text or an owned String: the original
+//   text is never copied, a line becomes a String only when it is edited
+// - a file is read in memory once and indexed in one pass; LineRope::map can memory map
+//   it instead, but it is unsafe: the caller must guarantee nobody changes the file
+// nodes that become small after many removals are not merged: the tree can get sparse,
+// but its height never grows beyond the one needed by the largest size it had
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::iter;
+use std::ops::Range;
+use std::slice;
+use std::time::Instant;
+
+use memmap2::Mmap;
+
+const MAX: usize = 64; // lines in a leaf and children of an inner node
+
+// the text the lines were loaded from
+enum Source {
+    Text(String),
+    // SAFETY of the mapping: like any mmap the file must not be changed in place while it
+    // is open, the lines not yet edited would change (and could stop being UTF-8) or the
+    // process gets SIGBUS if the file is truncated; see LineRope::map
+    Mapped(Mmap),
+}
+
+impl Source {
+    fn as_str(&self) -> &str {
+        match self {
+            Source::Text(text) => text,
+            // SAFETY: the contents have been validated as UTF-8 in map(), and the caller of
+            // map() guarantees that they don't change
+            Source::Mapped(map) => unsafe { std::str::from_utf8_unchecked(map) },
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Line {
+    Source(Range<usize>), // bytes of the source, without the line ending
+    Owned(String),
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf(Vec<Line>),
+    Inner(Vec<(usize, Node)>), // lines under each child, child
+}
+
+impl Node {
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(lines) => lines.len(),
+            Node::Inner(children) => children.iter().map(|(n, _)| n).sum(),
+        }
+    }
+
+    // the child holding line i and the index of its first line
+    // with insert = true i can also be the position just after the last line
+    fn locate(children: &[(usize, Node)], i: usize, insert: bool) -> (usize, usize) {
+        let mut first = 0;
+        for (c, (n, _)) in children.iter().enumerate() {
+            if i < first + n || (insert && c == children.len() - 1) {
+                return (c, first);
+            }
+            first += n;
+        }
+        unreachable!("line {} out of the node", i)
+    }
+
+    fn get(&self, i: usize) -> &Line {
+        match self {
+            Node::Leaf(lines) => &lines[i],
+            Node::Inner(children) => {
+                let (c, first) = Node::locate(children, i, false);
+                children[c].1.get(i - first)
+            }
+        }
+    }
+
+    fn get_mut(&mut self, i: usize) -> &mut Line {
+        match self {
+            Node::Leaf(lines) => &mut lines[i],
+            Node::Inner(children) => {
+                let (c, first) = Node::locate(children, i, false);
+                children[c].1.get_mut(i - first)
+            }
+        }
+    }
+
+    // returns the right half of the node if it had to be split
+    fn insert(&mut self, i: usize, line: Line) -> Option<Node> {
+        match self {
+            Node::Leaf(lines) => {
+                lines.insert(i, line);
+                (lines.len() > MAX).then(|| Node::Leaf(lines.split_off(lines.len() / 2)))
+            }
+            Node::Inner(children) => {
+                let (c, first) = Node::locate(children, i, true);
+                children[c].0 += 1;
+                if let Some(right) = children[c].1.insert(i - first, line) {
+                    let moved = right.len();
+                    children[c].0 -= moved;
+                    children.insert(c + 1, (moved, right));
+                }
+                (children.len() > MAX).then(|| Node::Inner(children.split_off(children.len() / 2)))
+            }
+        }
+    }
+
+    fn remove(&mut self, i: usize) -> Line {
+        match self {
+            Node::Leaf(lines) => lines.remove(i),
+            Node::Inner(children) => {
+                let (c, first) = Node::locate(children, i, false);
+                children[c].0 -= 1;
+                let line = children[c].1.remove(i - first);
+                if children[c].0 == 0 {
+                    children.remove(c);
+                }
+                line
+            }
+        }
+    }
+}
+
+pub struct LineRope {
+    source: Source,
+    root: Node,
+    len: usize,
+}
+
+impl LineRope {
+
+    // the lines of a text, as str::lines() splits them, without the BOM
+    pub fn from_text(text: String) -> Self {
+        let ranges: Vec<_> = line_ranges(&text).collect();
+        LineRope::build(Source::Text(text), ranges.into_iter())
+    }
+
+    // loads a file in memory, the contents must be UTF-8
+    pub fn open(file: &File) -> io::Result<Self> {
+        let mut text = String::with_capacity(file.metadata()?.len() as usize);
+        io::Read::read_to_string(&mut &*file, &mut text)?; // InvalidData if not UTF-8
+        Ok(LineRope::from_text(text))
+    }
+
+    /// Memory maps the file instead of reading it: the contents are read from the page
+    /// cache only when a line is used (an empty file can't be mapped).
+    ///
+    /// # Safety
+    /// The file must not be truncated or changed in place while the rope is alive: the
+    /// lines not yet edited are read from the mapping, a truncated file makes the process
+    /// get SIGBUS and a rewritten one can turn them into invalid UTF-8.
+    pub unsafe fn map(file: &File) -> io::Result<Self> {
+        // SAFETY: guaranteed by the caller
+        let map = unsafe { Mmap::map(file)? };
+        let text = std::str::from_utf8(&map).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let ranges: Vec<_> = line_ranges(text).collect();
+        Ok(LineRope::build(Source::Mapped(map), ranges.into_iter()))
+    }
+
+    // the whole text, as it was loaded
+    pub fn source(&self) -> &str {
+        self.source.as_str()
+    }
+
+    // bulk load: full leaves, then the levels above them, up to a single root
+    fn build(source: Source, ranges: impl Iterator<Item = Range<usize>>) -> Self {
+        let mut level: Vec<(usize, Node)> = Vec::new();
+        let mut leaf = Vec::with_capacity(MAX);
+        for range in ranges {
+            leaf.push(Line::Source(range));
+            if leaf.len() == MAX {
+                level.push((MAX, Node::Leaf(std::mem::replace(&mut leaf, Vec::with_capacity(MAX)))));
+            }
+        }
+        if !leaf.is_empty() || level.is_empty() {
+            level.push((leaf.len(), Node::Leaf(leaf)));
+        }
+        while level.len() > 1 {
+            let mut upper = Vec::with_capacity(level.len() / MAX + 1);
+            let mut children = level.into_iter().peekable();
+            while children.peek().is_some() {
+                let group: Vec<(usize, Node)> = children.by_ref().take(MAX).collect();
+                upper.push((group.iter().map(|(n, _)| n).sum(), Node::Inner(group)));
+            }
+            level = upper;
+        }
+        let (len, root) = level.pop().expect("at least one leaf");
+        LineRope { source, root, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn text<'a>(&'a self, line: &'a Line) -> &'a str {
+        match line {
+            Line::Source(range) => &self.source.as_str()[range.clone()],
+            Line::Owned(s) => s,
+        }
+    }
+
+    // the ending that followed the line in the source: None for an edited line
+    // and for the last line of a text without a final newline
+    fn ending(&self, line: &Line) -> Option<&'static str> {
+        let Line::Source(range) = line else { return None };
+        let rest = &self.source.as_str()[range.end..];
+        if rest.starts_with("\r\n") {
+            Some("\r\n")
+        } else if rest.starts_with('\n') {
+            Some("\n")
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Option<&str> {
+        (i < self.len).then(|| self.text(self.root.get(i)))
+    }
+
+    // the line as a String that can be changed, copied out of the source the first time
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut String> {
+        if i >= self.len {
+            return None;
+        }
+        let source = &self.source;
+        let line = self.root.get_mut(i);
+        if let Line::Source(range) = line {
+            *line = Line::Owned(source.as_str()[range.clone()].to_string());
+        }
+        match line {
+            Line::Owned(s) => Some(s),
+            Line::Source(_) => unreachable!(),
+        }
+    }
+
+    pub fn set(&mut self, i: usize, text: String) -> String {
+        let line = self.get_mut(i).expect("line out of range");
+        std::mem::replace(line, text)
+    }
+
+    // panics if i > len, like Vec::insert
+    pub fn insert(&mut self, i: usize, text: String) {
+        assert!(i <= self.len, "insert at {} with {} lines", i, self.len);
+        if let Some(right) = self.root.insert(i, Line::Owned(text)) {
+            let left = std::mem::replace(&mut self.root, Node::Leaf(Vec::new()));
+            let left_len = left.len();
+            self.root = Node::Inner(vec![(left_len, left), (right.len(), right)]);
+        }
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, i: usize) -> String {
+        assert!(i < self.len, "remove of {} with {} lines", i, self.len);
+        let line = match self.root.remove(i) {
+            Line::Source(range) => self.source.as_str()[range].to_string(),
+            Line::Owned(s) => s,
+        };
+        self.len -= 1;
+        // a root with a single child is replaced by the child
+        while let Node::Inner(children) = &mut self.root {
+            match children.len() {
+                0 => self.root = Node::Leaf(Vec::new()),
+                1 => self.root = children.pop().expect("one child").1,
+                _ => break,
+            }
+        }
+        line
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(0)
+    }
+
+    // the lines with the ending each one had in the source, see ending()
+    pub fn iter_with_endings(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        let mut iter = self.iter();
+        iter::from_fn(move || iter.next_line().map(|line| (self.text(line), self.ending(line))))
+    }
+
+    // the lines from line i on, finding the first one is O(log n)
+    pub fn iter_from(&self, i: usize) -> Iter<'_> {
+        let i = i.min(self.len);
+        let mut iter = Iter { rope: self, stack: Vec::new(), leaf: [].iter(), remaining: self.len - i };
+        let mut node = &self.root;
+        let mut i = i;
+        loop {
+            match node {
+                Node::Leaf(lines) => {
+                    iter.leaf = lines[i..].iter();
+                    return iter;
+                }
+                Node::Inner(children) => {
+                    let (c, first) = Node::locate(children, i, true);
+                    iter.stack.push(children[c + 1..].iter());
+                    node = &children[c].1;
+                    i -= first;
+                }
+            }
+        }
+    }
+}
+
+// the ranges of the lines of text: split at '\n', without a trailing '\r' and the BOM
+fn line_ranges(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let base = text.as_ptr() as usize;
+    text.strip_prefix('\u{feff}').unwrap_or(text).lines().map(move |l| {
+        let start = l.as_ptr() as usize - base;
+        start..start + l.len()
+    })
+}
+
+pub struct Iter<'a> {
+    rope: &'a LineRope,
+    stack: Vec<slice::Iter<'a, (usize, Node)>>, // the children still to visit, at each level
+    leaf: slice::Iter<'a, Line>,
+    remaining: usize,
+}
+
+impl<'a> Iter<'a> {
+    fn next_line(&mut self) -> Option<&'a Line> {
+        loop {
+            if let Some(line) = self.leaf.next() {
+                self.remaining -= 1;
+                return Some(line);
+            }
+            // next leaf: climb until a level has children left, then go down
+            loop {
+                match self.stack.last_mut()?.next() {
+                    Some((_, Node::Leaf(lines))) => {
+                        self.leaf = lines.iter();
+                        break;
+                    }
+                    Some((_, Node::Inner(children))) => self.stack.push(children.iter()),
+                    None => {
+                        self.stack.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_line().map(|line| self.rope.text(line))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+// like the benchmarks of lab06: the same operations on the old Vec<String> storage
+// and on the rope, printing the times
+pub fn benchmark<O: Write>(lines: usize, edits: usize, out: &mut O) -> io::Result<()> {
+    let text: String = (0..lines).map(|i| format!("line {} of the benchmark log\n", i)).collect();
+    writeln!(out, "{} lines, {} bytes, {} edits", lines, text.len(), edits)?;
+    // positions spread over the whole text, the same for both
+    let positions: Vec<usize> = (0..edits).map(|i| (i * 7919 + lines / 2) % lines.max(1)).collect();
+
+    let start = Instant::now();
+    let mut vec: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+    writeln!(out, "Vec<String> - load: {:?}", start.elapsed())?;
+    let start = Instant::now();
+    for &p in &positions {
+        vec.insert(p, "inserted".to_string());
+    }
+    for &p in positions.iter().rev() {
+        vec.remove(p);
+    }
+    writeln!(out, "Vec<String> - insert + remove: {:?}", start.elapsed())?;
+    let start = Instant::now();
+    let mut bytes = 0;
+    for &p in &positions {
+        let all: Vec<&str> = vec.iter().map(|l| l.as_str()).collect(); // what all_lines() did
+        bytes += all[p].len();
+    }
+    writeln!(out, "Vec<String> - all_lines()[i]: {:?} ({} bytes)", start.elapsed(), bytes)?;
+
+    let start = Instant::now();
+    let mut rope = LineRope::from_text(text);
+    writeln!(out, "LineRope - load: {:?}", start.elapsed())?;
+    let start = Instant::now();
+    for &p in &positions {
+        rope.insert(p, "inserted".to_string());
+    }
+    for &p in positions.iter().rev() {
+        rope.remove(p);
+    }
+    writeln!(out, "LineRope - insert + remove: {:?}", start.elapsed())?;
+    let start = Instant::now();
+    let mut bytes = 0;
+    for &p in &positions {
+        bytes += rope.get(p).map_or(0, |l| l.len());
+    }
+    writeln!(out, "LineRope - get(i): {:?} ({} bytes)", start.elapsed(), bytes)?;
+    let start = Instant::now();
+    let total: usize = rope.iter().map(|l| l.len()).sum();
+    writeln!(out, "LineRope - iter over all lines: {:?} ({} bytes)", start.elapsed(), total)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(rope: &LineRope) -> Vec<&str> {
+        rope.iter().collect()
+    }
+
+    #[test]
+    fn test_from_text_like_str_lines() {
+        for text in ["", "a", "a\n", "a\r\nb\r\n", "\n\nc", "x\ny\r\nz"] {
+            let rope = LineRope::from_text(text.to_string());
+            assert_eq!(lines(&rope), text.lines().collect::<Vec<_>>());
+            assert_eq!(rope.len(), text.lines().count());
+        }
+    }
+
+    #[test]
+    fn test_endings_of_the_source() {
+        let mut rope = LineRope::from_text("\u{feff}a\r\nb\nc\r\nd".to_string());
+        rope.get_mut(2).unwrap().push('!');
+        rope.insert(0, "new".to_string());
+        let endings: Vec<_> = rope.iter_with_endings().collect();
+        assert_eq!(endings, vec![("new", None), ("a", Some("\r\n")), ("b", Some("\n")), ("c!", None), ("d", None)]);
+    }
+
+    #[test]
+    fn test_same_as_vec_under_random_edits() {
+        let text: String = (0..1000).map(|i| format!("{}\n", i)).collect();
+        let mut rope = LineRope::from_text(text.clone());
+        let mut vec: Vec<String> = text.lines().map(String::from).collect();
+        // a simple LCG, so the sequence is the same at each run
+        let mut seed = 12345u64;
+        let mut next = |n: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n.max(1)
+        };
+        for step in 0..5000 {
+            match next(4) {
+                0 | 1 => {
+                    let i = next(vec.len() + 1);
+                    vec.insert(i, format!("new {}", step));
+                    rope.insert(i, format!("new {}", step));
+                }
+                2 if !vec.is_empty() => {
+                    let i = next(vec.len());
+                    assert_eq!(rope.remove(i), vec.remove(i));
+                }
+                _ if !vec.is_empty() => {
+                    let i = next(vec.len());
+                    rope.get_mut(i).unwrap().push('!');
+                    vec[i].push('!');
+                }
+                _ => {}
+            }
+            assert_eq!(rope.len(), vec.len());
+        }
+        assert_eq!(lines(&rope), vec);
+        for i in [0, 1, 63, 64, 65, vec.len() / 2, vec.len() - 1] {
+            assert_eq!(rope.get(i), Some(vec[i].as_str()));
+            assert_eq!(rope.iter_from(i).collect::<Vec<_>>(), vec[i..]);
+        }
+        assert_eq!(rope.get(vec.len()), None);
+        assert_eq!(rope.iter_from(vec.len() + 5).next(), None);
+    }
+
+    #[test]
+    fn test_mapped_file() {
+        let path = std::env::temp_dir().join(format!("line_rope_map_{}", std::process::id()));
+        std::fs::write(&path, "\u{feff}uno\r\ndue\n\ntre").unwrap();
+        // SAFETY: nobody else uses the file while it is mapped
+        let mut rope = unsafe { LineRope::map(&File::open(&path).unwrap()) }.unwrap();
+        assert_eq!(lines(&rope), vec!["uno", "due", "", "tre"]);
+        rope.get_mut(1).unwrap().push_str(" e mezzo");
+        rope.insert(0, "zero".to_string());
+        assert_eq!(lines(&rope), vec!["zero", "uno", "due e mezzo", "", "tre"]);
+        assert!(rope.source().starts_with('\u{feff}'));
+        drop(rope); // the file can't be changed while it is mapped
+
+        std::fs::write(&path, b"\xff\xfe").unwrap();
+        let err = unsafe { LineRope::map(&File::open(&path).unwrap()) }.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_all_and_insert_again() {
+        let text: String = (0..300).map(|i| format!("{}\n", i)).collect();
+        let mut rope = LineRope::from_text(text);
+        while !rope.is_empty() {
+            rope.remove(rope.len() / 2);
+        }
+        assert_eq!(rope.iter().next(), None);
+        rope.insert(0, "only".to_string());
+        assert_eq!(lines(&rope), vec!["only"]);
+    }
+}
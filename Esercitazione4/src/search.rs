@@ -0,0 +1,156 @@
+// "find next" / "find previous" from a cursor, as in an editor
+// - the search wraps around: after the last match comes the first one (wrapped() tells it)
+// - the session remembers the cursor between the searches; if the lines are edited in
+//   between (LineEditor::version changes) the cursor is moved back inside the text and
+//   the search starts again from there, the match at the cursor is skipped only if the
+//   regex still matches there
+
+use regex::Regex;
+
+use crate::position;
+use crate::replace::LinePos;
+use crate::{FindIter, LineEditor, Match};
+
+pub struct SearchSession {
+    regex: Regex,
+    cursor: LinePos, // the start of the current match, or where the next search starts
+    current: bool, // there is a match at the cursor: find_next() goes past it
+    version: Option<u64>, // the editor version the cursor refers to
+    wrapped: bool,
+}
+
+impl SearchSession {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(SearchSession { regex: Regex::new(pattern)?, cursor: (0, 0), current: false, version: None, wrapped: false })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    pub fn cursor(&self) -> LinePos {
+        self.cursor
+    }
+
+    // the next search starts here, a match at pos included
+    pub fn set_cursor(&mut self, pos: LinePos) {
+        self.cursor = pos;
+        self.current = false;
+    }
+
+    // the last search went past the end (or the start) of the text
+    pub fn wrapped(&self) -> bool {
+        self.wrapped
+    }
+
+    // after an edit the cursor may be past the end of its line or of the text
+    fn sync(&mut self, editor: &LineEditor) {
+        if self.version == Some(editor.version()) {
+            return;
+        }
+        self.version = Some(editor.version());
+        let (mut line, mut offset) = self.cursor;
+        if line >= editor.len() {
+            (line, offset) = (editor.len(), 0);
+        } else {
+            offset = position::snap_floor(editor.line(line).unwrap_or_default(), offset).0;
+        }
+        self.cursor = (line, offset);
+        self.current = self.current
+            && editor.line(line).is_ok_and(|l| self.regex.find_at(l, offset).is_some_and(|m| m.start() == offset));
+    }
+
+    fn found<'e>(&mut self, m: Option<Match<'e>>, wrapped: bool) -> Option<Match<'e>> {
+        let m = m?;
+        self.cursor = (m.line, m.start);
+        self.current = true;
+        self.wrapped = wrapped;
+        Some(m)
+    }
+
+    pub fn find_next<'e>(&mut self, editor: &'e LineEditor) -> Option<Match<'e>> {
+        self.sync(editor);
+        let end = (editor.len(), 0);
+        let mut after = FindIter::in_editor(editor, self.regex.clone(), self.cursor, end);
+        if self.current {
+            after.next(); // the match at the cursor
+        }
+        if let Some(m) = after.next() {
+            return self.found(Some(m), false);
+        }
+        // the first one, it may be the current match if it is the only one
+        let first = FindIter::in_editor(editor, self.regex.clone(), (0, 0), end).next();
+        self.found(first, true)
+    }
+
+    pub fn find_prev<'e>(&mut self, editor: &'e LineEditor) -> Option<Match<'e>> {
+        self.sync(editor);
+        let end = (editor.len(), 0);
+        if let Some(m) = FindIter::in_editor(editor, self.regex.clone(), (0, 0), self.cursor).next_back() {
+            return self.found(Some(m), false);
+        }
+        let last = FindIter::in_editor(editor, self.regex.clone(), (0, 0), end).next_back();
+        self.found(last, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(m: Option<Match>) -> Option<LinePos> {
+        m.map(|m| (m.line, m.start))
+    }
+
+    #[test]
+    fn test_next_and_prev_wrap_around() {
+        let editor = LineEditor::new("let x = 1;\nlet y = x;\n\nx".to_string());
+        let mut search = SearchSession::new(r"\bx\b").unwrap();
+        search.set_cursor((0, 5));
+        assert_eq!(pos(search.find_next(&editor)), Some((1, 8)));
+        assert_eq!(pos(search.find_next(&editor)), Some((3, 0)));
+        assert!(!search.wrapped());
+        assert_eq!(pos(search.find_next(&editor)), Some((0, 4)));
+        assert!(search.wrapped());
+        assert_eq!(pos(search.find_prev(&editor)), Some((3, 0)));
+        assert!(search.wrapped());
+        assert_eq!(pos(search.find_prev(&editor)), Some((1, 8)));
+        assert_eq!(pos(search.find_prev(&editor)), Some((0, 4)));
+        assert!(!search.wrapped());
+
+        // a single match is found again after wrapping, none at all in an empty editor
+        let mut search = SearchSession::new("y").unwrap();
+        assert_eq!(pos(search.find_next(&editor)), Some((1, 4)));
+        assert_eq!(pos(search.find_next(&editor)), Some((1, 4)));
+        assert!(search.wrapped());
+        assert!(search.find_prev(&LineEditor::new(String::new())).is_none());
+    }
+
+    #[test]
+    fn test_survives_edits() {
+        let mut editor = LineEditor::new("foo bar\nbar foo\nfoo".to_string());
+        let mut search = SearchSession::new("foo").unwrap();
+        assert_eq!(pos(search.find_next(&editor)), Some((0, 0)));
+        assert_eq!(pos(search.find_next(&editor)), Some((1, 4)));
+
+        // the match is replaced by text that still matches: it is still the current one
+        editor.replace(1, 4, 7, "food").unwrap();
+        assert_eq!(pos(search.find_next(&editor)), Some((2, 0)));
+
+        // the cursor line is gone: the search goes on from the end, wrapping
+        editor.remove_lines(1..3).unwrap();
+        assert_eq!(pos(search.find_next(&editor)), Some((0, 0)));
+        assert!(search.wrapped());
+
+        // the match at the cursor is gone: the search starts from the cursor
+        editor.replace(0, 0, 3, "x").unwrap();
+        editor.insert_lines(1, vec!["foo".to_string()]).unwrap();
+        assert_eq!(pos(search.find_next(&editor)), Some((1, 0)));
+        assert!(!search.wrapped());
+        editor.undo();
+        assert!(search.find_next(&editor).is_none());
+        assert_eq!(search.cursor(), (1, 0));
+        editor.undo();
+        assert_eq!(pos(search.find_next(&editor)), Some((0, 0)));
+    }
+}
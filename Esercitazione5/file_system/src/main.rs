@@ -1,12 +1,17 @@
 use std::cell::RefCell;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
+use std::time::SystemTime;
 
 use std::fs;
+use std::io::Write;
 
 use walkdir::WalkDir;
 
+mod search;
+mod shell;
+
 
 pub enum FSItem {
     File(File),
@@ -34,6 +39,22 @@ impl FSItem {
         }
     }
 
+    pub fn metadata(&self) -> &Metadata {
+        match self {
+            FSItem::File(f) => &f.meta,
+            FSItem::Directory(d) => &d.meta,
+            FSItem::SymLink(l) => &l.meta,
+        }
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        match self {
+            FSItem::File(f) => &mut f.meta,
+            FSItem::Directory(d) => &mut d.meta,
+            FSItem::SymLink(l) => &mut l.meta,
+        }
+    }
+
     pub fn get_children(&self) -> Option<&Vec<FSNode>> {
         match self {
             FSItem::Directory(d) => Some(&d.children), // solo le directory
@@ -68,6 +89,14 @@ impl FSItem {
         }
     }
 
+    pub fn set_parent(&mut self, parent: FSNodeWeak) {
+        match self {
+            FSItem::File(f) => f.parent = parent,
+            FSItem::Directory(d) => d.parent = parent,
+            FSItem::SymLink(s) => s.parent = parent,
+        }
+    }
+
     // return the absolute path of the item (of the parent)
     pub fn abs_path(&self) -> String {
         let mut parts = vec![];
@@ -80,9 +109,9 @@ impl FSItem {
         }
 
         if parts.len() < 2 {
-            return "/".to_string();
+            "/".to_string()
         } else {
-            return parts.join("/");
+            parts.join("/")
         }
     }
 
@@ -93,14 +122,165 @@ type FSItemCell = RefCell<FSItem>; // elemento modificabile, prende possesso
 type FSNode = Rc<FSItemCell>; // possesso a più variabili
 type FSNodeWeak = Weak<FSItemCell>; // riferimento debole (esempio per padre)
 
+// the metadata of an item, as stat(2) gives it: the times, the mode bits (rwx for
+// owner, group and others, plus setuid, setgid and sticky) and the owner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub created: SystemTime,
+    pub modified: SystemTime,
+    pub accessed: SystemTime,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Metadata {
+    // a new item, all the times are now
+    pub fn new(mode: u32, owner: User) -> Self {
+        let now = SystemTime::now();
+        Metadata { created: now, modified: now, accessed: now, mode: mode & 0o7777, uid: owner.uid, gid: owner.gid }
+    }
+
+    // the metadata of a real file; the creation time is not available everywhere, the
+    // modification time takes its place
+    pub fn from_fs(meta: &fs::Metadata) -> Self {
+        let now = SystemTime::now();
+        let modified = meta.modified().unwrap_or(now);
+        #[cfg(unix)]
+        let (mode, uid, gid) = {
+            use std::os::unix::fs::MetadataExt;
+            (meta.mode() & 0o7777, meta.uid(), meta.gid())
+        };
+        #[cfg(not(unix))]
+        let (mode, uid, gid) = (if meta.permissions().readonly() { 0o555 } else { 0o755 }, 0, 0);
+        Metadata {
+            created: meta.created().unwrap_or(modified),
+            modified,
+            accessed: meta.accessed().unwrap_or(modified),
+            mode,
+            uid,
+            gid,
+        }
+    }
+
+    // the permission bits (among READ, WRITE and EXEC) that user has on the item
+    pub fn allows(&self, user: User, access: u32) -> bool {
+        if user.uid == 0 {
+            return true;
+        }
+        let shift = if user.uid == self.uid {
+            6
+        } else if user.gid == self.gid {
+            3
+        } else {
+            0
+        };
+        (self.mode >> shift) & access == access
+    }
+}
+
+pub const READ: u32 = 0o4;
+pub const WRITE: u32 = 0o2;
+pub const EXEC: u32 = 0o1;
+
+// the links followed to resolve a path before giving up, like ELOOP in Linux:
+// a chain longer than this is taken as a loop
+pub const MAX_LINK_HOPS: usize = 40;
+const TOO_MANY_LINKS: &str = "too many levels of symbolic links";
+
+// who does the operations when the permissions are checked (see set_user); uid 0 can do everything
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct User {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl User {
+    pub const ROOT: User = User { uid: 0, gid: 0 };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Directory,
+    SymLink,
+}
+
+// what stat returns: size is the length of the content for files, of the target for links
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stat {
+    pub kind: Kind,
+    pub size: usize,
+    pub metadata: Metadata,
+}
+
+impl Stat {
+    // as ls -l shows it: drwxr-xr-x
+    pub fn mode_string(&self) -> String {
+        let kind = match self.kind {
+            Kind::File => '-',
+            Kind::Directory => 'd',
+            Kind::SymLink => 'l',
+        };
+        let bits = (0..9).rev().map(|i| {
+            if self.metadata.mode & (1 << i) == 0 {
+                '-'
+            } else {
+                ['x', 'w', 'r'][i % 3]
+            }
+        });
+        std::iter::once(kind).chain(bits).collect()
+    }
+}
+
+// the bytes of a file: in memory, or still only in the backing directory (see
+// from_file_system), read the first time they are needed
+pub enum Content {
+    Loaded(Vec<u8>),
+    OnDisk(PathBuf),
+}
+
+// how from_file_system reads the files of the backing directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loading {
+    Eager, // all the contents while building the tree
+    Lazy,  // only the sizes, each content when it is first read or changed
+}
+
 pub struct File {
     name: String,
     size: usize,
+    content: Content,
+    meta: Metadata,
     parent: FSNodeWeak, // senza possesso 
 }
 
+impl File {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        matches!(self.content, Content::Loaded(_))
+    }
+
+    // the content, loaded from the backing file if it is not in memory yet
+    fn data(&mut self) -> Result<&mut Vec<u8>, String> {
+        if let Content::OnDisk(path) = &self.content {
+            let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            self.size = data.len();
+            self.content = Content::Loaded(data);
+        }
+        match &mut self.content {
+            Content::Loaded(data) => Ok(data),
+            Content::OnDisk(_) => unreachable!("loaded above"),
+        }
+    }
+}
+
 pub struct Directory {
     name: String,
+    meta: Metadata,
     parent: FSNodeWeak,
     children: Vec<FSNode>, // più children, condivisi, e modificabili (Vec<Rc<RefCell<FSItem>>>)
 }
@@ -108,6 +288,7 @@ pub struct Directory {
 pub struct SymLink {
     name: String,
     target: String,
+    meta: Metadata,
     parent: FSNodeWeak, 
 }
 
@@ -115,13 +296,21 @@ pub struct FileSystem {
     real_path: String,  // the real path of the file system
     root: FSNode,
     current: FSNode,
-    side_effects: bool  // enable / disable side effects on the file system
+    side_effects: bool,  // enable / disable side effects on the file system
+    user: Option<User>, // who does the operations, None: no permission checks
+}
+
+impl Default for FileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FileSystem {
     pub fn new() -> Self {
         let root = Rc::new(RefCell::new(FSItem::Directory(Directory {
             name: "".to_string(),
+            meta: Metadata::new(0o755, User::ROOT),
             parent: Weak::new(),
             children: vec![],
         })));
@@ -131,18 +320,27 @@ impl FileSystem {
             root: root.clone(),
             current: root,
             side_effects: false,
+            user: None,
         }
     }
 
     pub fn from_file_system(base_path: &str) -> Self {
+        Self::from_file_system_with(base_path, Loading::Lazy)
+    }
+
+    // the tree of base_path with the real sizes of the files, the contents are read now
+    // (Eager) or when needed (Lazy); files that cannot be read are left empty
+    pub fn from_file_system_with(base_path: &str, loading: Loading) -> Self {
         
         let mut fs = FileSystem::new();
         fs.set_real_path(base_path);
+        if let Ok(meta) = fs::metadata(base_path) {
+            *fs.root.borrow_mut().metadata_mut() = Metadata::from_fs(&meta);
+        }
         
-        let wdir = WalkDir::new(base_path);
-        for entry in wdir.into_iter()
-            .filter(|e| e.is_ok())
-            .map(|e| e.unwrap())  {
+        // min_depth(1): base_path itself is the root
+        let wdir = WalkDir::new(base_path).min_depth(1);
+        for entry in wdir.into_iter().flatten() {
             
             // full fs path
             let _entry_path = entry.path().to_str().unwrap();
@@ -158,11 +356,23 @@ impl FileSystem {
                 "/".to_string()  
             };
             let name = entry_path.file_name().unwrap().to_str().unwrap();
+            // links are followed, as is_dir and is_file do
+            let Ok(real_meta) = fs::metadata(&entry_path) else { continue };
+            let meta = Metadata::from_fs(&real_meta);
             
             if entry_path.is_dir() {
-                fs.make_dir(&head, name).unwrap();
+                FileSystem::insert_dir(&fs.find(&head).unwrap(), name, meta);
             } else if entry_path.is_file() {
-                fs.make_file(&head, name).unwrap();
+                let (size, content) = match loading {
+                    Loading::Eager => {
+                        let data = fs::read(&entry_path).unwrap_or_default();
+                        (data.len(), Content::Loaded(data))
+                    }
+                    Loading::Lazy => {
+                        (real_meta.len() as usize, Content::OnDisk(entry_path.clone()))
+                    }
+                };
+                FileSystem::insert_file(&fs.find(&head).unwrap(), name, size, content, meta);
             }
         }
 
@@ -183,11 +393,11 @@ impl FileSystem {
             .join(&abs_path)
             .join(node.borrow().name());
 
-        return real_path.to_str().unwrap().to_string();
+        real_path.to_str().unwrap().to_string()
     }
 
     fn split_path(path: &str) -> Vec<&str> {
-        path.split('/').filter(|&t| t != "").collect()
+        path.split('/').filter(|&t| !t.is_empty()).collect()
     }
 
     pub fn find(&self, path: &str) -> Option<FSNode> {
@@ -196,6 +406,11 @@ impl FileSystem {
 
     // find using either absolute or relative path
     pub fn find_full(&self, path: &str, base: Option<&str>) -> Option<FSNode> {
+        self.find_hops(path, base, &mut 0)
+    }
+
+    // hops counts the links followed so far, also those in the targets of other links
+    fn find_hops(&self, path: &str, base: Option<&str>, hops: &mut usize) -> Option<FSNode> {
         let parts = FileSystem::split_path(path);
 
         let mut current = if path.starts_with('/') {
@@ -203,19 +418,28 @@ impl FileSystem {
         } else {
             if let Some(base) = base {
                 // if we can't find the base, return None
-                self.find(base)?
+                self.find_hops(base, None, hops)?
             } else {
                 self.current.clone()
             }
         };
 
         for part in parts {
+            // a link in the middle of the path is a directory: follow it, then look for part
+            let target = match current.borrow().deref() {
+                FSItem::SymLink(link) => Some(self.follow_hops(&current.borrow().abs_path(), link, hops)?),
+                _ => None,
+            };
+            if let Some(target) = target {
+                current = target;
+            }
             let next_node = match current.borrow().deref() {
                 FSItem::Directory(d) => {
                     if part == "." {
                         current.clone()
                     } else if part == ".." {
-                        d.parent.upgrade().unwrap()
+                        // the parent of the root is the root itself
+                        d.parent.upgrade().unwrap_or_else(|| current.clone())
                     } else {
                         let item = d
                             .children
@@ -229,16 +453,7 @@ impl FileSystem {
                         }
                     }
                 },
-                FSItem::SymLink(link) => {
-                    let path = current.borrow().abs_path();
-                    let target = self.follow_link(&path, &link);
-                    if let Some(target) = target {
-                        target
-                    } else {
-                        return None;
-                    }
-                }
-                FSItem::File(_) => {
+                FSItem::SymLink(_) | FSItem::File(_) => {
                     return None;
                 }
             };
@@ -247,28 +462,41 @@ impl FileSystem {
         Some(current)
     }
 
+    // None for a broken link and for a loop of links
     pub fn follow_link(&self, path: &str, link: &SymLink) -> Option<FSNode> {
+        self.follow_hops(path, link, &mut 0)
+    }
+
+    fn follow_hops(&self, path: &str, link: &SymLink, hops: &mut usize) -> Option<FSNode> {
 
         // path is the absolute path of the link and it necessary if the link is relative
 
-        let node = self.find_full(&link.target, Some(path));
-        if let Some(node) = node {
-            match node.borrow().deref() {
-                FSItem::Directory(_) => return Some(node.clone()),
-                FSItem::File(_) => return Some(node.clone()),
-                FSItem::SymLink(link) => {
-                    let path = node.borrow().abs_path();
-                    return self.follow_link(&path, link)
-                },
-            }
-        } else {
-            return None
+        *hops += 1;
+        if *hops > MAX_LINK_HOPS {
+            return None;
+        }
+        let node = self.find_hops(&link.target, Some(path), hops)?;
+        match node.borrow().deref() {
+            FSItem::Directory(_) => Some(node.clone()),
+            FSItem::File(_) => Some(node.clone()),
+            FSItem::SymLink(link) => {
+                let path = node.borrow().abs_path();
+                self.follow_hops(&path, link, hops)
+            },
         }
     }
 
     pub fn change_dir(&mut self, path: &str) -> Result<(), String> {
-        let node = self.find(path);
+        // a link is followed, the current directory is always a directory
+        let node = match self.find_target(path) {
+            Err(e) if e.ends_with(TOO_MANY_LINKS) => return Err(e),
+            result => result.ok(),
+        };
         if let Some(n) = node {
+            if !matches!(n.borrow().deref(), FSItem::Directory(_)) {
+                return Err(format!("{} is not a directory", path));
+            }
+            self.check(&n, EXEC)?;
             self.current = n;
             Ok(())
         } else {
@@ -277,7 +505,9 @@ impl FileSystem {
     }
 
     pub fn make_dir(&mut self, path: &str, name: &str) -> Result<(), String> {
-        if let Some(node) = self.find(path) {
+        if let Ok(node) = self.find_target(path) {
+            FileSystem::check_new(&node, name)?;
+            self.check(&node, WRITE | EXEC)?;
 
             if self.side_effects {
                 // create the directory on the file system
@@ -288,24 +518,19 @@ impl FileSystem {
                 fs::create_dir(&target).map_err(|e| e.to_string())?;
             }
 
-            let new_dir = FSItem::Directory(Directory {
-                name: name.to_string(),
-                parent: Rc::downgrade(&node),
-                children: vec![],
-            });
-            
-            let new_node = Rc::new(RefCell::new(new_dir));
-            node.borrow_mut().add(new_node.clone());
-            
+            FileSystem::insert_dir(&node, name, Metadata::new(0o755, self.owner()));
+            FileSystem::touch_dir(&node);
             Ok(())
         } else {
-            return Err(format!("Directory {} not found", path));
+            Err(format!("Directory {} not found", path))
         }
     }
 
     pub fn make_file(&mut self, path: &str, name: &str) -> Result<(), String> {
-        if let Some(node) = self.find(path) {
-            
+        if let Ok(node) = self.find_target(path) {
+            FileSystem::check_new(&node, name)?;
+            self.check(&node, WRITE | EXEC)?;
+
             if self.side_effects {
                 // create the file on the file system
                 let real_path = self.make_real_path(node.clone());
@@ -314,25 +539,79 @@ impl FileSystem {
                 fs::File::create(&target).map_err(|e| e.to_string())?;
             }
 
-            let new_file = FSItem::File(File {
-                name: name.to_string(),
-                size: 0,
-                parent: Rc::downgrade(&node),
-            });
-
-            let new_node = Rc::new(RefCell::new(new_file));
-            node.borrow_mut().add(new_node.clone());
+            FileSystem::insert_file(&node, name, 0, Content::Loaded(vec![]), Metadata::new(0o644, self.owner()));
+            FileSystem::touch_dir(&node);
             Ok(())
         }
         else {
-            return Err(format!("Directory {} not found", path));
+            Err(format!("Directory {} not found", path))
+        }
+    }
+
+    fn insert_dir(dir: &FSNode, name: &str, meta: Metadata) -> FSNode {
+        let new_dir = FSItem::Directory(Directory {
+            name: name.to_string(),
+            meta,
+            parent: Rc::downgrade(dir),
+            children: vec![],
+        });
+
+        let new_node = Rc::new(RefCell::new(new_dir));
+        dir.borrow_mut().add(new_node.clone());
+        new_node
+    }
+
+    fn insert_file(dir: &FSNode, name: &str, size: usize, content: Content, meta: Metadata) -> FSNode {
+        let new_file = FSItem::File(File {
+            name: name.to_string(),
+            size,
+            content,
+            meta,
+            parent: Rc::downgrade(dir),
+        });
+
+        let new_node = Rc::new(RefCell::new(new_file));
+        dir.borrow_mut().add(new_node.clone());
+        new_node
+    }
+
+    // name is free in dir
+    fn check_new(dir: &FSNode, name: &str) -> Result<(), String> {
+        FileSystem::check_name(name)?;
+        let exists = match dir.borrow().deref() {
+            FSItem::Directory(d) => d.children.iter().any(|c| c.borrow().name() == name),
+            _ => return Err(format!("{} is not a directory", FileSystem::full_path(dir))),
+        };
+        if exists {
+            return Err(format!("Item {} already exists", FileSystem::child_path(dir, name)));
         }
+        Ok(())
+    }
+
+    // a name that find can reach: not empty, not . or .., without /
+    fn check_name(name: &str) -> Result<(), String> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(format!("Invalid name {:?}", name));
+        }
+        Ok(())
+    }
+
+    fn child_path(dir: &FSNode, name: &str) -> String {
+        let dir = FileSystem::full_path(dir);
+        format!("{}/{}", dir.trim_end_matches('/'), name)
+    }
+
+    // the content of the directory has changed
+    fn touch_dir(dir: &FSNode) {
+        dir.borrow_mut().metadata_mut().modified = SystemTime::now();
     }
 
     // added for testing
     pub fn make_link(&mut self, path: &str, name: &str, target: &str) -> Result<(), String> {
         
-        if let Some(node) = self.find(path) {
+        if let Ok(node) = self.find_target(path) {
+            FileSystem::check_new(&node, name)?;
+            self.check(&node, WRITE | EXEC)?;
 
             // handle symlinks on FS only on linux
             #[cfg(target_os = "linux")]
@@ -347,20 +626,27 @@ impl FileSystem {
             let new_link = FSItem::SymLink(SymLink {
                 name: name.to_string(),
                 target: target.to_string(),
+                meta: Metadata::new(0o777, self.owner()),
                 parent: Rc::downgrade(&node),
             });
 
             let new_node = Rc::new(RefCell::new(new_link));
             node.borrow_mut().add(new_node.clone());
+            FileSystem::touch_dir(&node);
             Ok(())
         } else {
-            return Err(format!("Directory {} not found", path));
+            Err(format!("Directory {} not found", path))
         }
     }
 
     pub fn rename(&self, path: &str, new_name: &str) -> Result<(), String> {
+        FileSystem::check_name(new_name)?;
         let node = self.find(path);
         if let Some(n) = node {
+            let parent = n.borrow().parent().upgrade();
+            if let Some(parent) = &parent {
+                self.check(parent, WRITE | EXEC)?;
+            }
 
             if self.side_effects {
                 let real_path = self.make_real_path(n.clone());
@@ -370,18 +656,85 @@ impl FileSystem {
                 parts.push(new_name);// remove the last part (the file name)
                 let new_path = parts.join("/");
                 fs::rename(&real_path, &new_path).map_err(|e| e.to_string())?;
+                // the files not loaded yet are read from the new path
+                FileSystem::relocate(&n, Path::new(&real_path), Path::new(&new_path));
             }
 
             n.borrow_mut().set_name(new_name);
+            if let Some(parent) = &parent {
+                FileSystem::touch_dir(parent);
+            }
             Ok(())
         } else {
             Err(format!("Item {} not found", path))
         }
     }
 
+    // moves the item at path into the directory dir (or the directory a link points to),
+    // with the name new_name, as mv does
+    pub fn move_item(&mut self, path: &str, dir: &str, new_name: &str) -> Result<(), String> {
+        FileSystem::check_name(new_name)?;
+        let n = self.find(path).ok_or_else(|| format!("Item {} not found", path))?;
+        let dest = self.find_target(dir)?;
+        if !matches!(dest.borrow().deref(), FSItem::Directory(_)) {
+            return Err(format!("{} is not a directory", dir));
+        }
+        let parent = n.borrow().parent().upgrade().ok_or_else(|| "Cannot move the root".to_string())?;
+        // a directory cannot go inside itself
+        let mut current = Some(dest.clone());
+        while let Some(d) = current {
+            if Rc::ptr_eq(&d, &n) {
+                return Err(format!("Cannot move {} inside itself", path));
+            }
+            current = d.borrow().parent().upgrade();
+        }
+        let exists = dest.borrow().get_children().unwrap().iter()
+            .any(|c| !Rc::ptr_eq(c, &n) && c.borrow().name() == new_name);
+        if exists {
+            return Err(format!("Item {} already exists", FileSystem::child_path(&dest, new_name)));
+        }
+        self.check(&parent, WRITE | EXEC)?;
+        self.check(&dest, WRITE | EXEC)?;
+
+        if self.side_effects {
+            let real_path = self.make_real_path(n.clone());
+            let new_path = PathBuf::from(self.make_real_path(dest.clone())).join(new_name);
+            fs::rename(&real_path, &new_path).map_err(|e| e.to_string())?;
+            FileSystem::relocate(&n, Path::new(&real_path), &new_path);
+        }
+
+        parent.borrow_mut().remove(n.borrow().name());
+        n.borrow_mut().set_name(new_name);
+        n.borrow_mut().set_parent(Rc::downgrade(&dest));
+        dest.borrow_mut().add(n.clone());
+        FileSystem::touch_dir(&parent);
+        FileSystem::touch_dir(&dest);
+        Ok(())
+    }
+
+    // changes the backing paths under old into paths under new, in node and below
+    fn relocate(node: &FSNode, old: &Path, new: &Path) {
+        match &mut *node.borrow_mut() {
+            FSItem::File(File { content: Content::OnDisk(path), .. }) => {
+                if let Ok(rest) = path.strip_prefix(old) {
+                    *path = if rest.as_os_str().is_empty() { new.to_path_buf() } else { new.join(rest) };
+                }
+            }
+            FSItem::Directory(d) => {
+                for child in &d.children {
+                    FileSystem::relocate(child, old, new);
+                }
+            }
+            _ => {}
+        }
+    }
+
     pub fn delete(&self, path: &str) -> Result<(), String> {
         let node = self.find(path);
         if let Some(n) = node {
+            if let Some(parent) = n.borrow().parent().upgrade() {
+                self.check(&parent, WRITE | EXEC)?;
+            }
             
             if self.side_effects {
                 match n.borrow().deref() {
@@ -402,7 +755,8 @@ impl FileSystem {
             }
 
             if let Some(parent) = n.borrow().parent().upgrade() {
-                parent.borrow_mut().remove(&n.borrow().name());
+                parent.borrow_mut().remove(n.borrow().name());
+                FileSystem::touch_dir(&parent);
             }
             Ok(())
         } else {
@@ -410,10 +764,264 @@ impl FileSystem {
         }
     }
 
+    // the names in the directory at path (or in the one a link points to)
+    pub fn list_dir(&self, path: &str) -> Result<Vec<String>, String> {
+        let node = self.find_target(path)?;
+        self.check(&node, READ)?;
+        let item = node.borrow();
+        let children = item.get_children().ok_or_else(|| format!("{} is not a directory", path))?;
+        Ok(children.iter().map(|c| c.borrow().name().to_string()).collect())
+    }
+
+    // the target of the link at path
+    pub fn read_link(&self, path: &str) -> Result<String, String> {
+        let node = self.find(path).ok_or_else(|| format!("Item {} not found", path))?;
+        match node.borrow().deref() {
+            FSItem::SymLink(link) => Ok(link.target.clone()),
+            _ => Err(format!("{} is not a link", path)),
+        }
+    }
+
+    // the absolute path of the current directory
+    pub fn current_path(&self) -> String {
+        FileSystem::full_path(&self.current)
+    }
+
+    // creates an empty file, or sets the times of an existing item to now
+    pub fn touch(&mut self, path: &str) -> Result<(), String> {
+        if self.find(path).is_none() {
+            let (dir, name) = FileSystem::split_parent(path);
+            return self.make_file(dir, name);
+        }
+        let node = self.find_target(path)?;
+        self.check(&node, WRITE)?;
+        let now = SystemTime::now();
+        if self.side_effects && matches!(node.borrow().deref(), FSItem::File(_)) {
+            let real_path = self.make_real_path(node.clone());
+            let real = fs::OpenOptions::new().write(true).open(&real_path).map_err(|e| e.to_string())?;
+            real.set_times(fs::FileTimes::new().set_accessed(now).set_modified(now)).map_err(|e| e.to_string())?;
+        }
+        let mut item = node.borrow_mut();
+        let meta = item.metadata_mut();
+        meta.accessed = now;
+        meta.modified = now;
+        Ok(())
+    }
+
     pub fn set_side_effects(&mut self, side_effects: bool) {
         self.side_effects = side_effects;
     }
 
+    // "/a/b/c" -> ("/a/b", "c"), "c" -> (".", "c")
+    fn split_parent(path: &str) -> (&str, &str) {
+        let path = path.trim_end_matches('/');
+        match path.rsplit_once('/') {
+            Some(("", name)) => ("/", name),
+            Some((dir, name)) => (dir, name),
+            None => (".", path),
+        }
+    }
+
+    // the item at path, following the link if path is a symlink
+    fn find_target(&self, path: &str) -> Result<FSNode, String> {
+        // the hops are counted from the start of path, a loop gets its own error
+        let mut hops = 0;
+        let error = |hops: usize, other: String| {
+            if hops > MAX_LINK_HOPS { format!("{}: {}", path, TOO_MANY_LINKS) } else { other }
+        };
+        let node = self.find_hops(path, None, &mut hops)
+            .ok_or_else(|| error(hops, format!("Item {} not found", path)))?;
+        let target = match node.borrow().deref() {
+            FSItem::SymLink(link) => {
+                let link_path = node.borrow().abs_path();
+                Some(self.follow_hops(&link_path, link, &mut hops)
+                    .ok_or_else(|| error(hops, format!("Broken link {}", path)))?)
+            }
+            _ => None,
+        };
+        Ok(target.unwrap_or(node))
+    }
+
+    // the file at path, following the link if path is a symlink
+    fn find_file(&self, path: &str) -> Result<FSNode, String> {
+        let node = self.find_target(path)?;
+        if !matches!(node.borrow().deref(), FSItem::File(_)) {
+            return Err(format!("{} is not a file", path));
+        }
+        Ok(node)
+    }
+
+    // runs f on the file at path with its real path (where the side effects go), after
+    // checking access; if f succeeds a WRITE changes the modification time, a READ the
+    // access time
+    fn with_file<R>(&self, path: &str, access: u32, f: impl FnOnce(&mut File, &str) -> Result<R, String>) -> Result<R, String> {
+        let node = self.find_file(path)?;
+        self.check(&node, access)?;
+        let real_path = self.make_real_path(node.clone());
+        match &mut *node.borrow_mut() {
+            FSItem::File(file) => {
+                let result = f(file, &real_path)?;
+                let now = SystemTime::now();
+                if access & WRITE != 0 {
+                    file.meta.modified = now;
+                }
+                if access & READ != 0 {
+                    file.meta.accessed = now;
+                }
+                Ok(result)
+            }
+            _ => unreachable!("find_file returns only files"),
+        }
+    }
+
+    // replaces the content of the file, creating it if it does not exist
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), String> {
+        if self.find(path).is_none() {
+            let (dir, name) = FileSystem::split_parent(path);
+            self.make_file(dir, name)?;
+        }
+        let side_effects = self.side_effects;
+        self.with_file(path, WRITE, |file, real_path| {
+            if side_effects {
+                fs::write(real_path, data).map_err(|e| e.to_string())?;
+            }
+            file.content = Content::Loaded(data.to_vec());
+            file.size = data.len();
+            Ok(())
+        })
+    }
+
+    pub fn append(&mut self, path: &str, data: &[u8]) -> Result<(), String> {
+        let side_effects = self.side_effects;
+        self.with_file(path, WRITE, |file, real_path| {
+            if side_effects {
+                let mut real = fs::OpenOptions::new().append(true).open(real_path).map_err(|e| e.to_string())?;
+                real.write_all(data).map_err(|e| e.to_string())?;
+                if !file.is_loaded() {
+                    // the backing file has already changed, it is read when needed
+                    file.size += data.len();
+                    return Ok(());
+                }
+            }
+            file.data()?.extend_from_slice(data);
+            file.size += data.len();
+            Ok(())
+        })
+    }
+
+    // the content of the file, read from the backing directory the first time
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, String> {
+        self.with_file(path, READ, |file, _| Ok(file.data()?.clone()))
+    }
+
+    // shortens the file to size bytes, or extends it with zeros
+    pub fn truncate(&mut self, path: &str, size: usize) -> Result<(), String> {
+        let side_effects = self.side_effects;
+        self.with_file(path, WRITE, |file, real_path| {
+            if side_effects {
+                let real = fs::OpenOptions::new().write(true).open(real_path).map_err(|e| e.to_string())?;
+                real.set_len(size as u64).map_err(|e| e.to_string())?;
+                if !file.is_loaded() {
+                    file.size = size;
+                    return Ok(());
+                }
+            }
+            file.data()?.resize(size, 0);
+            file.size = size;
+            Ok(())
+        })
+    }
+
+    pub fn file_size(&self, path: &str) -> Result<usize, String> {
+        self.with_file(path, 0, |file, _| Ok(file.size()))
+    }
+
+    // the permissions are checked only with a user set, the new items belong to the user
+    // (to root without one)
+    pub fn set_user(&mut self, user: Option<User>) {
+        self.user = user;
+    }
+
+    fn owner(&self) -> User {
+        self.user.unwrap_or(User::ROOT)
+    }
+
+    // the path of node in the tree, for the messages
+    fn full_path(node: &FSNode) -> String {
+        let node = node.borrow();
+        match node.abs_path().as_str() {
+            "/" => format!("/{}", node.name()),
+            abs => format!("{}/{}", abs, node.name()),
+        }
+    }
+
+    // with a user set: search (EXEC) permission on all the directories above node and
+    // access on node itself
+    fn check(&self, node: &FSNode, access: u32) -> Result<(), String> {
+        let Some(user) = self.user else { return Ok(()) };
+        let mut allowed = node.borrow().metadata().allows(user, access);
+        let mut current = node.borrow().parent().upgrade();
+        while let Some(dir) = current {
+            allowed &= dir.borrow().metadata().allows(user, EXEC);
+            current = dir.borrow().parent().upgrade();
+        }
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!("Permission denied: {}", FileSystem::full_path(node)))
+        }
+    }
+
+    fn stat_node(node: &FSNode) -> Stat {
+        let (kind, size) = match node.borrow().deref() {
+            FSItem::File(f) => (Kind::File, f.size),
+            FSItem::Directory(_) => (Kind::Directory, 0),
+            FSItem::SymLink(l) => (Kind::SymLink, l.target.len()),
+        };
+        Stat { kind, size, metadata: *node.borrow().metadata() }
+    }
+
+    // the metadata of the item at path, of the target if path is a symlink
+    pub fn stat(&self, path: &str) -> Result<Stat, String> {
+        let node = self.find_target(path)?;
+        self.check(&node, 0)?;
+        Ok(FileSystem::stat_node(&node))
+    }
+
+    // as stat, but of the link itself
+    pub fn lstat(&self, path: &str) -> Result<Stat, String> {
+        let node = self.find(path).ok_or_else(|| format!("Item {} not found", path))?;
+        self.check(&node, 0)?;
+        Ok(FileSystem::stat_node(&node))
+    }
+
+    // changes the mode bits of the item at path (of the target for a symlink); only the
+    // owner and root can do it
+    pub fn chmod(&mut self, path: &str, mode: u32) -> Result<(), String> {
+        let node = self.find_target(path)?;
+        self.check(&node, 0)?;
+        if let Some(user) = self.user
+            && user.uid != 0
+            && user.uid != node.borrow().metadata().uid
+        {
+            return Err(format!("Operation not permitted: {}", FileSystem::full_path(&node)));
+        }
+
+        #[cfg(unix)]
+        if self.side_effects {
+            use std::os::unix::fs::PermissionsExt;
+            let real_path = self.make_real_path(node.clone());
+            fs::set_permissions(&real_path, fs::Permissions::from_mode(mode & 0o7777)).map_err(|e| e.to_string())?;
+        }
+
+        node.borrow_mut().metadata_mut().mode = mode & 0o7777;
+        Ok(())
+    }
+
+}
+
+fn main() {
+    std::process::exit(shell::run(std::env::args().skip(1)));
 }
 
 #[cfg(test)]
@@ -491,18 +1099,210 @@ mod tests {
         
         // uncommento to delete all
         fs.delete("/test_dir").unwrap();
-        
-        assert!(true); 
+    }
+
+    // a directory in the system temp dir, removed at the end of the test
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("file_system_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // a copy of the structure of /etc/apt, which is not there on every system
+    fn create_apt_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(name);
+        fs::write(dir.0.join("sources.list"), "deb http://deb.debian.org/debian stable main\n").unwrap();
+        fs::create_dir(dir.0.join("apt.conf.d")).unwrap();
+        fs::write(dir.0.join("apt.conf.d/00proxy"), "").unwrap();
+        dir
     }
 
     #[test]
     fn test_from_file_system() {
-
-        // adjust to your system
-        let fs = FileSystem::from_file_system("/etc/apt");
+        let dir = create_apt_dir("from_fs");
+        let fs = FileSystem::from_file_system(dir.path());
         assert!(fs.find("/sources.list").is_some());
+        assert!(fs.find("/apt.conf.d/00proxy").is_some());
+        // base_path itself is the root, not one of its children
+        assert_eq!(fs.root.borrow().get_children().unwrap().len(), 2);
     }
 
-}
+    #[test]
+    fn test_write_read_append_truncate() {
+        let mut fs = create_file_system_with_structure();
+        assert_eq!(fs.read_file("/home/user/file.txt").unwrap(), b"");
+        fs.write_file("/home/user/file.txt", b"hello").unwrap();
+        fs.append("/home/user/file.txt", b" world").unwrap();
+        assert_eq!(fs.read_file("/home/user/file.txt").unwrap(), b"hello world");
+        assert_eq!(fs.file_size("/home/user/file.txt"), Ok(11));
+        fs.truncate("/home/user/file.txt", 5).unwrap();
+        assert_eq!(fs.read_file("/home/user/file.txt").unwrap(), b"hello");
+        fs.truncate("/home/user/file.txt", 7).unwrap();
+        assert_eq!(fs.read_file("/home/user/file.txt").unwrap(), b"hello\0\0");
+
+        // through a link, relative paths, new files
+        assert_eq!(fs.file_size("/home/link_user/file.txt"), Ok(7));
+        fs.make_link("/home/user", "link.txt", "./file.txt").unwrap();
+        assert_eq!(fs.read_file("/home/user/link.txt").unwrap(), b"hello\0\0");
+        fs.write_file("new.txt", b"in user1").unwrap();
+        assert_eq!(fs.read_file("/home/user1/new.txt").unwrap(), b"in user1");
+        fs.write_file("/top.txt", b"x").unwrap();
+        assert!(fs.find("/top.txt").is_some());
+
+        assert_eq!(fs.read_file("/home/user"), Err("/home/user is not a file".to_string()));
+        assert!(fs.append("/home/missing.txt", b"x").is_err());
+        assert!(fs.write_file("/nowhere/file.txt", b"x").is_err());
+    }
+
+    #[test]
+    fn test_contents_side_effects() {
+        let dir = TempDir::new("contents");
+        let mut fs = FileSystem::new();
+        fs.set_real_path(dir.path());
+        fs.set_side_effects(true);
+        fs.make_dir("/", "docs").unwrap();
+        fs.write_file("/docs/a.txt", b"abc").unwrap();
+        fs.append("/docs/a.txt", b"def").unwrap();
+        assert_eq!(fs::read(dir.0.join("docs/a.txt")).unwrap(), b"abcdef");
+        fs.truncate("/docs/a.txt", 2).unwrap();
+        assert_eq!(fs::read(dir.0.join("docs/a.txt")).unwrap(), b"ab");
+        assert_eq!(fs.read_file("/docs/a.txt").unwrap(), b"ab");
+    }
+
+    #[test]
+    fn test_lazy_and_eager_loading() {
+        let dir = create_apt_dir("loading");
+        let list_size = fs::metadata(dir.0.join("sources.list")).unwrap().len() as usize;
+
+        let mut lazy = FileSystem::from_file_system(dir.path());
+        assert_eq!(lazy.file_size("/sources.list"), Ok(list_size));
+        let is_loaded = |fs: &FileSystem| match fs.find("/sources.list").unwrap().borrow().deref() {
+            FSItem::File(f) => f.is_loaded(),
+            _ => unreachable!(),
+        };
+        assert!(!is_loaded(&lazy));
+        // without side effects the append happens in memory, after loading the file
+        lazy.append("/sources.list", b"#").unwrap();
+        assert!(is_loaded(&lazy));
+        assert_eq!(lazy.file_size("/sources.list"), Ok(list_size + 1));
+        assert_eq!(fs::metadata(dir.0.join("sources.list")).unwrap().len() as usize, list_size);
+
+        // with side effects an append goes to disk, the content stays there
+        let mut lazy = FileSystem::from_file_system(dir.path());
+        lazy.set_side_effects(true);
+        lazy.append("/sources.list", b"#").unwrap();
+        assert!(!is_loaded(&lazy));
+        assert_eq!(lazy.file_size("/sources.list"), Ok(list_size + 1));
+        // a rename moves the backing file, the content is still found
+        lazy.rename("/sources.list", "sources.bak").unwrap();
+        assert!(lazy.read_file("/sources.bak").unwrap().ends_with(b"main\n#"));
+
+        let eager = FileSystem::from_file_system_with(dir.path(), Loading::Eager);
+        assert!(eager.find("/sources.bak").is_some_and(|n| matches!(n.borrow().deref(), FSItem::File(f) if f.is_loaded())));
+        assert_eq!(eager.file_size("/sources.bak"), Ok(list_size + 1));
+    }
+
+
+    #[test]
+    fn test_stat_and_times() {
+        let mut fs = create_file_system_with_structure();
+        let stat = fs.stat("/home/user/file.txt").unwrap();
+        assert_eq!((stat.kind, stat.size, stat.metadata.mode), (Kind::File, 0, 0o644));
+        assert_eq!((stat.metadata.uid, stat.metadata.gid), (0, 0));
+        assert_eq!(fs.stat("/home/user").unwrap().metadata.mode, 0o755);
+        // stat follows the link, lstat does not
+        assert_eq!(fs.stat("/home/link_user").unwrap().kind, Kind::Directory);
+        let link = fs.lstat("/home/link_user").unwrap();
+        assert_eq!((link.kind, link.size, link.metadata.mode), (Kind::SymLink, 10, 0o777));
+        assert!(fs.stat("/home/nothing").is_err());
+
+        let created = stat.metadata.created;
+        fs.write_file("/home/user/file.txt", b"abc").unwrap();
+        let written = fs.stat("/home/user/file.txt").unwrap().metadata;
+        assert!(written.modified >= created && written.accessed == created);
+        fs.read_file("/home/user/file.txt").unwrap();
+        let read = fs.stat("/home/user/file.txt").unwrap().metadata;
+        assert!(read.accessed >= written.modified && read.modified == written.modified);
+
+        // the directory changes when its content does
+        let before = fs.stat("/home/user").unwrap().metadata.modified;
+        fs.rename("/home/user/file1.txt", "file2.txt").unwrap();
+        assert!(fs.stat("/home/user").unwrap().metadata.modified >= before);
+
+        fs.chmod("/home/link_user/file.txt", 0o100600).unwrap();
+        assert_eq!(fs.stat("/home/user/file.txt").unwrap().metadata.mode, 0o600);
+    }
 
-fn main() {}
+    #[test]
+    fn test_permissions() {
+        let mut fs = create_file_system_with_structure();
+        let alice = User { uid: 1000, gid: 100 };
+        let bob = User { uid: 1001, gid: 100 };
+        fs.chmod("/home", 0o777).unwrap();
+        fs.set_user(Some(alice));
+        fs.make_dir("/home", "alice").unwrap();
+        fs.write_file("/home/alice/notes.txt", b"mine").unwrap();
+        assert_eq!(fs.stat("/home/alice/notes.txt").unwrap().metadata.uid, 1000);
+        // root's files: readable, not writable
+        assert!(fs.read_file("/home/user/file.txt").is_ok());
+        assert_eq!(fs.write_file("/home/user/file.txt", b"x"), Err("Permission denied: /home/user/file.txt".to_string()));
+        assert!(fs.make_file("/home/user", "new.txt").is_err());
+        assert!(fs.delete("/home/user/file.txt").is_err());
+        assert_eq!(fs.chmod("/home/user/file.txt", 0o666), Err("Operation not permitted: /home/user/file.txt".to_string()));
+
+        // the group can read, the others nothing, also through the directory
+        fs.chmod("/home/alice/notes.txt", 0o640).unwrap();
+        fs.set_user(Some(bob));
+        assert_eq!(fs.read_file("/home/alice/notes.txt").unwrap(), b"mine");
+        assert!(fs.append("/home/alice/notes.txt", b"!").is_err());
+        fs.set_user(Some(alice));
+        fs.chmod("/home/alice", 0o700).unwrap();
+        fs.set_user(Some(bob));
+        assert_eq!(fs.read_file("/home/alice/notes.txt"), Err("Permission denied: /home/alice/notes.txt".to_string()));
+        assert!(fs.change_dir("/home/alice").is_err());
+        assert!(fs.stat("/home/alice/notes.txt").is_err());
+        assert!(fs.stat("/home/alice").is_ok());
+
+        // root can do everything, no user means no checks
+        fs.set_user(Some(User::ROOT));
+        fs.append("/home/alice/notes.txt", b"!").unwrap();
+        fs.set_user(None);
+        fs.chmod("/home/alice/notes.txt", 0).unwrap();
+        assert_eq!(fs.read_file("/home/alice/notes.txt").unwrap(), b"mine!");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_real_metadata() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        let dir = create_apt_dir("metadata");
+        let list = dir.0.join("sources.list");
+        fs::set_permissions(&list, fs::Permissions::from_mode(0o640)).unwrap();
+        let real = fs::metadata(&list).unwrap();
+
+        let mut vfs = FileSystem::from_file_system(dir.path());
+        let meta = vfs.stat("/sources.list").unwrap().metadata;
+        assert_eq!((meta.mode, meta.uid, meta.gid), (0o640, real.uid(), real.gid()));
+        assert_eq!(meta.modified, real.modified().unwrap());
+        assert_eq!(vfs.stat("/apt.conf.d").unwrap().kind, Kind::Directory);
+
+        vfs.set_side_effects(true);
+        vfs.chmod("/sources.list", 0o600).unwrap();
+        assert_eq!(fs::metadata(&list).unwrap().mode() & 0o7777, 0o600);
+    }
+}
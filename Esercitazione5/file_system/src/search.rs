@@ -0,0 +1,555 @@
+// searching the tree instead of resolving one path:
+// - glob("/src/**/*.rs"): * ? and [...] in a name, ** for any number of directories
+// - walk(path): every item under path, depth first or breadth first, with the absolute
+//   path it was reached by; the children in the order of their names
+// - search(path): a walk filtered by name, type, size and modification time
+// the links are followed only on request (follow_links); a link to one of the directories
+// the walk is already inside is not entered again, so the walk always ends
+
+use std::collections::VecDeque;
+use std::ops::{Bound, Deref, RangeBounds};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use crate::{EXEC, FSItem, FSNode, FileSystem, Kind, READ};
+
+// a name matches a glob pattern: * is any text, ? one character, [abc] [a-z] one character
+// of the class and [!...] (or [^...]) one not in it; a [ without its ] is just a [
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p = tokens(pattern);
+    let n: Vec<char> = name.chars().collect();
+    // the last * seen and the position in name it is matching up to
+    let (mut i, mut j, mut star) = (0, 0, None);
+    while j < n.len() {
+        if i < p.len() && p[i].matches(n[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == Token::Any {
+            star = Some((i, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            star = Some((si, sj + 1));
+            (i, j) = (si + 1, sj + 1);
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|t| *t == Token::Any)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Char(char),
+    One,
+    Any,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Token {
+    // matches one character (* is handled by glob_match)
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(x) => *x == c,
+            Token::One => true,
+            Token::Any => false,
+            Token::Class { negated, ranges } => ranges.iter().any(|&(a, b)| a <= c && c <= b) != *negated,
+        }
+    }
+}
+
+fn tokens(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => tokens.push(Token::Any),
+            '?' => tokens.push(Token::One),
+            '[' => {
+                if let Some((class, next)) = class(&chars, i + 1) {
+                    tokens.push(class);
+                    i = next;
+                    continue;
+                }
+                tokens.push(Token::Char('['));
+            }
+            c => tokens.push(Token::Char(c)),
+        }
+        i += 1;
+    }
+    tokens
+}
+
+// the class that starts at chars[i], after the [, and the position after its ]; a ] right
+// after [ or [! is part of the class
+fn class(chars: &[char], mut i: usize) -> Option<(Token, usize)> {
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < chars.len() && (chars[i] != ']' || i == start) {
+        match chars.get(i + 1..i + 3) {
+            Some(&['-', end]) if end != ']' => {
+                ranges.push((chars[i], end));
+                i += 3;
+            }
+            _ => {
+                ranges.push((chars[i], chars[i]));
+                i += 1;
+            }
+        }
+    }
+    (i < chars.len()).then_some((Token::Class { negated, ranges }, i + 1))
+}
+
+// "/" + "a" -> "/a", "" + "a" -> "a"
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), name)
+    }
+}
+
+fn name_of(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    DepthFirst,
+    BreadthFirst,
+}
+
+// the directories a walk is inside, from the deepest up
+struct Above {
+    dir: FSNode,
+    up: Option<Rc<Above>>,
+}
+
+impl Above {
+    fn contains(above: &Option<Rc<Above>>, dir: &FSNode) -> bool {
+        let mut current = above.as_ref();
+        while let Some(a) = current {
+            if Rc::ptr_eq(&a.dir, dir) {
+                return true;
+            }
+            current = a.up.as_ref();
+        }
+        false
+    }
+}
+
+struct Entry {
+    path: String,
+    node: FSNode,
+    depth: usize,
+    above: Option<Rc<Above>>,
+}
+
+pub struct Walk<'a> {
+    fs: &'a FileSystem,
+    order: Order,
+    follow_links: bool,
+    max_depth: Option<usize>,
+    pending: VecDeque<Entry>,
+    loops: Vec<String>,
+}
+
+impl<'a> Walk<'a> {
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    // a link is given as its target, and a link to a directory is entered
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    // the start is at depth 0, its children at 1...
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    // the links (followed) that were not entered because they lead back to a directory
+    // above them
+    pub fn loops(&self) -> &[String] {
+        &self.loops
+    }
+
+    // the children of entry, if it is a directory the walk can enter
+    fn children(&mut self, entry: &Entry) -> Vec<Entry> {
+        if self.max_depth.is_some_and(|max| entry.depth >= max) {
+            return vec![];
+        }
+        if !matches!(entry.node.borrow().deref(), FSItem::Directory(_)) || self.fs.check(&entry.node, READ | EXEC).is_err() {
+            return vec![];
+        }
+        if Above::contains(&entry.above, &entry.node) {
+            self.loops.push(entry.path.clone());
+            return vec![];
+        }
+        let above = Some(Rc::new(Above { dir: entry.node.clone(), up: entry.above.clone() }));
+        let dir = entry.node.borrow();
+        let mut children: Vec<(String, FSNode)> = dir.get_children().unwrap().iter()
+            .map(|c| (c.borrow().name().to_string(), c.clone()))
+            .collect();
+        children.sort_by(|a, b| a.0.cmp(&b.0));
+        children.into_iter()
+            .map(|(name, node)| Entry { path: join(&entry.path, &name), node, depth: entry.depth + 1, above: above.clone() })
+            .collect()
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = (String, FSNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut entry = match self.order {
+            Order::DepthFirst => self.pending.pop_back()?,
+            Order::BreadthFirst => self.pending.pop_front()?,
+        };
+        if self.follow_links {
+            // a broken link stays a link
+            let target = match entry.node.borrow().deref() {
+                FSItem::SymLink(link) => self.fs.follow_link(&entry.node.borrow().abs_path(), link),
+                _ => None,
+            };
+            if let Some(target) = target {
+                entry.node = target;
+            }
+        }
+        let children = self.children(&entry);
+        match self.order {
+            Order::DepthFirst => self.pending.extend(children.into_iter().rev()),
+            Order::BreadthFirst => self.pending.extend(children),
+        }
+        Some((entry.path, entry.node))
+    }
+}
+
+// a walk that gives only the items matching all the conditions
+pub struct Search<'a> {
+    walk: Walk<'a>,
+    name: Option<Regex>,
+    glob: Option<String>,
+    kind: Option<Kind>,
+    size: (Bound<usize>, Bound<usize>),
+    modified: (Bound<SystemTime>, Bound<SystemTime>),
+}
+
+impl<'a> Search<'a> {
+    pub fn order(mut self, order: Order) -> Self {
+        self.walk = self.walk.order(order);
+        self
+    }
+
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.walk = self.walk.follow_links(follow);
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.walk = self.walk.max_depth(depth);
+        self
+    }
+
+    // the regex is found in the name (anchor it with ^...$ for the whole name)
+    pub fn name(mut self, regex: Regex) -> Self {
+        self.name = Some(regex);
+        self
+    }
+
+    // the name matches the pattern as in glob_match, as find -name does
+    pub fn name_glob(mut self, pattern: &str) -> Self {
+        self.glob = Some(pattern.to_string());
+        self
+    }
+
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    // the size as stat gives it
+    pub fn size(mut self, range: impl RangeBounds<usize>) -> Self {
+        self.size = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    pub fn modified(mut self, range: impl RangeBounds<SystemTime>) -> Self {
+        self.modified = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    pub fn loops(&self) -> &[String] {
+        self.walk.loops()
+    }
+
+    fn matches(&self, path: &str, node: &FSNode) -> bool {
+        let name = name_of(path);
+        let stat = FileSystem::stat_node(node);
+        self.name.as_ref().is_none_or(|r| r.is_match(name))
+            && self.glob.as_ref().is_none_or(|g| glob_match(g, name))
+            && self.kind.is_none_or(|k| k == stat.kind)
+            && self.size.contains(&stat.size)
+            && self.modified.contains(&stat.metadata.modified)
+    }
+}
+
+impl Iterator for Search<'_> {
+    type Item = (String, FSNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, node) = self.walk.next()?;
+            if self.matches(&path, &node) {
+                return Some((path, node));
+            }
+        }
+    }
+}
+
+impl FileSystem {
+    // every item under path (path included, with its absolute path), depth first without
+    // following the links; nothing if path does not exist
+    pub fn walk(&self, path: &str) -> Walk<'_> {
+        let start = self.find(path).map(|node| Entry { path: FileSystem::full_path(&node), node, depth: 0, above: None });
+        Walk {
+            fs: self,
+            order: Order::DepthFirst,
+            follow_links: false,
+            max_depth: None,
+            pending: start.into_iter().collect(),
+            loops: Vec::new(),
+        }
+    }
+
+    // without conditions: all the items of walk(path)
+    pub fn search(&self, path: &str) -> Search<'_> {
+        Search {
+            walk: self.walk(path),
+            name: None,
+            glob: None,
+            kind: None,
+            size: (Bound::Unbounded, Bound::Unbounded),
+            modified: (Bound::Unbounded, Bound::Unbounded),
+        }
+    }
+
+    // the paths matching pattern, sorted; as in a shell the names starting with . match
+    // only a pattern starting with ., and ** does not enter the links. A relative pattern
+    // starts from the current directory and gives relative paths
+    pub fn glob(&self, pattern: &str) -> Vec<String> {
+        let parts: Vec<&str> = pattern.split('/').filter(|p| !p.is_empty()).collect();
+        let mut found = Vec::new();
+        if pattern.starts_with('/') {
+            self.glob_from(&parts, "/".to_string(), &self.root, &mut found);
+        } else {
+            self.glob_from(&parts, String::new(), &self.current, &mut found);
+        }
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    fn glob_from(&self, parts: &[&str], path: String, node: &FSNode, found: &mut Vec<String>) {
+        let Some((part, rest)) = parts.split_first() else {
+            found.push(if path.is_empty() { ".".to_string() } else { path });
+            return;
+        };
+        // the directory to look in, the target if node is a link
+        let target = match node.borrow().deref() {
+            FSItem::SymLink(link) => self.follow_link(&node.borrow().abs_path(), link),
+            _ => Some(node.clone()),
+        };
+        let Some(dir) = target else { return };
+        if self.check(&dir, EXEC).is_err() {
+            return;
+        }
+        let dir_item = dir.borrow();
+        let FSItem::Directory(d) = dir_item.deref() else { return };
+        let wildcard = part.contains(['*', '?', '[']);
+        match *part {
+            "." => self.glob_from(rest, join(&path, "."), &dir, found),
+            ".." => {
+                let parent = d.parent.upgrade().unwrap_or_else(|| dir.clone());
+                self.glob_from(rest, join(&path, ".."), &parent, found);
+            }
+            "**" => {
+                // no directories, then one more directory and ** again
+                self.glob_from(rest, path.clone(), &dir, found);
+                if self.check(&dir, READ).is_err() {
+                    return;
+                }
+                for child in &d.children {
+                    let item = child.borrow();
+                    if item.name().starts_with('.') {
+                        continue;
+                    }
+                    if matches!(item.deref(), FSItem::Directory(_)) {
+                        self.glob_from(parts, join(&path, item.name()), child, found);
+                    } else if rest.is_empty() {
+                        // a final ** is also every file
+                        found.push(join(&path, item.name()));
+                    }
+                }
+            }
+            _ if !wildcard => {
+                if let Some(child) = d.children.iter().find(|c| c.borrow().name() == *part) {
+                    self.glob_from(rest, join(&path, part), child, found);
+                }
+            }
+            _ => {
+                if self.check(&dir, READ).is_err() {
+                    return;
+                }
+                for child in &d.children {
+                    let name = child.borrow().name().to_string();
+                    if (!name.starts_with('.') || part.starts_with('.')) && glob_match(part, &name) {
+                        self.glob_from(rest, join(&path, &name), child, found);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::User;
+    use std::time::Duration;
+
+    // /src/main.rs, /src/lib/{mod.rs, util.rs, .hidden.rs}, /src/lib/deep/x.rs, /doc/a.md,
+    // /src/up -> /src (a loop), /docs -> /doc
+    fn project() -> FileSystem {
+        let mut fs = FileSystem::new();
+        fs.make_dir("/", "src").unwrap();
+        fs.make_dir("/", "doc").unwrap();
+        fs.make_dir("/src", "lib").unwrap();
+        fs.make_dir("/src/lib", "deep").unwrap();
+        fs.write_file("/src/main.rs", b"fn main() {}").unwrap();
+        fs.write_file("/src/lib/mod.rs", b"mod util;").unwrap();
+        fs.write_file("/src/lib/util.rs", b"").unwrap();
+        fs.write_file("/src/lib/.hidden.rs", b"").unwrap();
+        fs.write_file("/src/lib/deep/x.rs", b"// x").unwrap();
+        fs.write_file("/doc/a.md", b"# A").unwrap();
+        fs.make_link("/src", "up", "/src").unwrap();
+        fs.make_link("/", "docs", "/doc").unwrap();
+        fs
+    }
+
+    fn paths<I: Iterator<Item = (String, FSNode)>>(items: I) -> Vec<String> {
+        items.map(|(p, _)| p).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "main.rs") && glob_match("a*b*c", "aXbYbc") && glob_match("?", "é"));
+        assert!(!glob_match("*.rs", "main.rs.bak") && !glob_match("a?", "a"));
+        assert!(glob_match("[a-c]x", "bx") && !glob_match("[a-c]x", "dx"));
+        assert!(glob_match("[!a-c]x", "dx") && glob_match("[^a]", "b") && !glob_match("[!a]", "a"));
+        assert!(glob_match("[]]", "]") && glob_match("[a-]", "-"));
+        assert!(glob_match("[ab", "[ab") && !glob_match("[ab", "a"));
+    }
+
+    #[test]
+    fn test_glob() {
+        let mut fs = project();
+        assert_eq!(fs.glob("/src/**/*.rs"), ["/src/lib/deep/x.rs", "/src/lib/mod.rs", "/src/lib/util.rs", "/src/main.rs"]);
+        assert_eq!(fs.glob("/src/*/.*.rs"), ["/src/lib/.hidden.rs"]);
+        assert_eq!(fs.glob("/*"), ["/doc", "/docs", "/src"]);
+        assert_eq!(fs.glob("/docs/*.md"), ["/docs/a.md"]);
+        assert_eq!(fs.glob("/src/up/up/main.rs"), ["/src/up/up/main.rs"]);
+        assert_eq!(fs.glob("/**/a.md"), ["/doc/a.md"]);
+        assert!(fs.glob("/nothing/*").is_empty());
+        fs.change_dir("/src/lib").unwrap();
+        assert_eq!(fs.glob("[m-z]*.rs"), ["mod.rs", "util.rs"]);
+        assert_eq!(fs.glob("../*.rs"), ["../main.rs"]);
+        assert_eq!(fs.glob("**"), [".", "deep", "deep/x.rs", "mod.rs", "util.rs"]);
+    }
+
+    #[test]
+    fn test_walk_orders() {
+        let fs = project();
+        assert_eq!(paths(fs.walk("/src").max_depth(1)), ["/src", "/src/lib", "/src/main.rs", "/src/up"]);
+        assert_eq!(paths(fs.walk("/src/lib")), [
+            "/src/lib", "/src/lib/.hidden.rs", "/src/lib/deep", "/src/lib/deep/x.rs", "/src/lib/mod.rs", "/src/lib/util.rs",
+        ]);
+        assert_eq!(paths(fs.walk("/src/lib").order(Order::BreadthFirst)), [
+            "/src/lib", "/src/lib/.hidden.rs", "/src/lib/deep", "/src/lib/mod.rs", "/src/lib/util.rs", "/src/lib/deep/x.rs",
+        ]);
+        // the start is given by its absolute path
+        let mut fs = fs;
+        fs.change_dir("/src").unwrap();
+        assert_eq!(paths(fs.walk("lib/deep")), ["/src/lib/deep", "/src/lib/deep/x.rs"]);
+        assert_eq!(fs.walk("nothing").count(), 0);
+    }
+
+    #[test]
+    fn test_walk_follows_links_without_loops() {
+        let fs = project();
+        // not followed: /src/up is a link and nothing more
+        let (_, up) = fs.walk("/src").find(|(p, _)| p == "/src/up").unwrap();
+        assert!(matches!(up.borrow().deref(), FSItem::SymLink(_)));
+
+        let mut walk = fs.walk("/").follow_links(true);
+        let all = paths(walk.by_ref());
+        assert!(all.contains(&"/docs/a.md".to_string()) && all.contains(&"/src/up".to_string()));
+        assert!(!all.iter().any(|p| p.starts_with("/src/up/")));
+        assert_eq!(walk.loops(), ["/src/up"]);
+        // from inside the loop the walk goes once around it
+        assert_eq!(paths(fs.walk("/src/up").follow_links(true).max_depth(1)), ["/src/up", "/src/up/lib", "/src/up/main.rs", "/src/up/up"]);
+    }
+
+    #[test]
+    fn test_link_cycle() {
+        let mut fs = FileSystem::new();
+        fs.make_link("/", "a", "/b").unwrap();
+        fs.make_link("/", "b", "/a").unwrap();
+        let a = fs.find("/a").unwrap();
+        let a = a.borrow();
+        let FSItem::SymLink(link) = a.deref() else { panic!("not a link") };
+        assert!(fs.follow_link("/a", link).is_none());
+        assert!(fs.find("/a/x").is_none());
+        // the links of the cycle are given as links, like the broken ones
+        assert_eq!(paths(fs.walk("/").follow_links(true)), ["/", "/a", "/b"]);
+        assert!(fs.glob("/a/*").is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        let fs = project();
+        let rs = Regex::new(r"\.rs$").unwrap();
+        assert_eq!(paths(fs.search("/").name(rs.clone()).size(1..)), ["/src/lib/deep/x.rs", "/src/lib/mod.rs", "/src/main.rs"]);
+        assert_eq!(paths(fs.search("/").name(rs).max_depth(2)), ["/src/main.rs"]);
+        assert_eq!(paths(fs.search("/").kind(Kind::Directory)), ["/", "/doc", "/src", "/src/lib", "/src/lib/deep"]);
+        assert_eq!(paths(fs.search("/").kind(Kind::SymLink)), ["/docs", "/src/up"]);
+        // followed, the links are their targets
+        assert_eq!(paths(fs.search("/").follow_links(true).kind(Kind::SymLink)), Vec::<String>::new());
+        assert_eq!(paths(fs.search("/").follow_links(true).name_glob("*.md").order(Order::BreadthFirst)), ["/doc/a.md", "/docs/a.md"]);
+        assert_eq!(paths(fs.search("/").kind(Kind::File).size(..=3)), ["/doc/a.md", "/src/lib/.hidden.rs", "/src/lib/util.rs"]);
+
+        let now = SystemTime::now();
+        assert_eq!(fs.search("/").kind(Kind::File).modified(..=now).count(), 6);
+        assert_eq!(fs.search("/").modified(now + Duration::from_secs(60)..).count(), 0);
+    }
+
+    #[test]
+    fn test_permissions_limit_the_search() {
+        let mut fs = project();
+        fs.chmod("/src/lib", 0o700).unwrap();
+        fs.set_user(Some(User { uid: 1000, gid: 1000 }));
+        assert_eq!(paths(fs.walk("/src")), ["/src", "/src/lib", "/src/main.rs", "/src/up"]);
+        assert_eq!(fs.glob("/src/**/*.rs"), ["/src/main.rs"]);
+        assert!(fs.glob("/src/lib/mod.rs").is_empty());
+    }
+}
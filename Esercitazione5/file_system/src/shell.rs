@@ -0,0 +1,655 @@
+// an interactive shell on a FileSystem:
+//
+//   file_system [--dir DIR [--side-effects]]
+//
+// without --dir the file system starts empty, with it the tree of DIR is loaded with
+// from_file_system; --side-effects mirrors every change to DIR. Tab completes commands
+// and paths, the arrows go through the history.
+
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::{FSItem, FileSystem, Kind};
+
+const USAGE: &str = "\
+usage: file_system [--dir DIR [--side-effects]]
+
+options:
+  -d, --dir DIR          start from the tree of DIR
+  -s, --side-effects     apply the changes to DIR too
+  -h, --help             print this help";
+
+const HELP: &str = "\
+ls [-l] [PATH...]        list a directory
+cd [PATH]                change directory (/ without PATH)
+pwd                      print the current directory
+mkdir [-p] DIR...        create directories (-p: with the missing parents, no error if they exist)
+touch FILE...            create empty files, or update their times
+ln -s TARGET LINK        create a symbolic link
+mv SRC DST               move or rename, into DST if it is a directory
+rm [-r] PATH...          remove files and links (-r: directories too)
+cat FILE...              print files
+echo TEXT... [> FILE]    print TEXT, or write it to FILE (>> appends)
+tree [PATH]              draw the tree
+find [PATH] -name PAT    the paths under PATH whose name matches PAT (* and ?)
+stat PATH                show the metadata
+chmod MODE PATH          change the mode (octal)
+history                  the commands typed so far
+help                     this help
+exit                     quit";
+
+const COMMANDS: [&str; 18] = [
+    "cat", "cd", "chmod", "echo", "exit", "find", "help", "history", "ln", "ls", "mkdir", "mv", "pwd", "rm", "stat",
+    "touch", "tree", "quit",
+];
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub dir: Option<String>,
+    pub side_effects: bool,
+    pub help: bool,
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--dir" => config.dir = Some(args.next().ok_or_else(|| format!("{} needs an argument", arg))?),
+            "-s" | "--side-effects" => config.side_effects = true,
+            "-h" | "--help" => config.help = true,
+            a => return Err(format!("unknown argument {}", a)),
+        }
+    }
+    if config.side_effects && config.dir.is_none() {
+        return Err("--side-effects needs --dir".to_string());
+    }
+    Ok(config)
+}
+
+// the words of the line, '...' and "..." keep the spaces
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+// YYYY-MM-DD HH:MM in UTC
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, rest) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // from the days since 1970-01-01 to the date, counting in eras of 400 years from 0000-03-01
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, rest / 3600, rest % 3600 / 60)
+}
+
+// "a/b" + "c" -> "a/b/c", "/" + "c" -> "/c"
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+fn base_name(path: &str) -> &str {
+    path.trim_end_matches('/').rsplit('/').next().unwrap_or(path)
+}
+
+pub struct Shell {
+    fs: FileSystem,
+    history: Vec<String>,
+}
+
+impl Shell {
+    pub fn new(fs: FileSystem) -> Self {
+        Shell { fs, history: Vec::new() }
+    }
+
+    pub fn prompt(&self) -> String {
+        format!("{}$ ", self.fs.current_path())
+    }
+
+    // runs a command line; Ok(false) after exit, the errors start with the command name
+    pub fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> Result<bool, String> {
+        if !line.trim().is_empty() {
+            self.history.push(line.to_string());
+        }
+        let words = split_words(line)?;
+        let Some((command, args)) = words.split_first() else { return Ok(true) };
+        let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+        let result = match command.as_str() {
+            "exit" | "quit" => return Ok(false),
+            "ls" => self.ls(&args, out),
+            "cd" => self.fs.change_dir(args.first().copied().unwrap_or("/")),
+            "pwd" => writeln!(out, "{}", self.fs.current_path()).map_err(|e| e.to_string()),
+            "mkdir" => self.mkdir(&args),
+            "touch" => args.iter().try_for_each(|a| self.fs.touch(a)),
+            "ln" => self.ln(&args),
+            "mv" => self.mv(&args),
+            "rm" => self.rm(&args),
+            "cat" => self.cat(&args, out),
+            "echo" => self.echo(&args, out),
+            "tree" => self.tree(args.first().copied().unwrap_or("."), out),
+            "find" => self.find(&args, out),
+            "stat" => self.stat(&args, out),
+            "chmod" => self.chmod(&args),
+            "history" => self.history.iter().enumerate()
+                .try_for_each(|(i, l)| writeln!(out, "{:>4}  {}", i + 1, l))
+                .map_err(|e| e.to_string()),
+            "help" => writeln!(out, "{}", HELP).map_err(|e| e.to_string()),
+            _ => Err("command not found, try help".to_string()),
+        };
+        result.map(|_| true).map_err(|e| format!("{}: {}", command, e))
+    }
+
+    // the options (words starting with -) among the allowed ones, and the other words
+    fn options<'a>(args: &[&'a str], allowed: &str) -> Result<(String, Vec<&'a str>), String> {
+        let mut options = String::new();
+        let mut rest = Vec::new();
+        for arg in args {
+            match arg.strip_prefix('-') {
+                Some(o) if !o.is_empty() => {
+                    if let Some(c) = o.chars().find(|&c| !allowed.contains(c)) {
+                        return Err(format!("unknown option -{}", c));
+                    }
+                    options.push_str(o);
+                }
+                _ => rest.push(*arg),
+            }
+        }
+        Ok((options, rest))
+    }
+
+    fn long_line(&self, path: &str, name: &str) -> Result<String, String> {
+        let stat = self.fs.lstat(path)?;
+        let meta = stat.metadata;
+        let mut line = format!(
+            "{} {:>5} {:>5} {:>8} {} {}",
+            stat.mode_string(), meta.uid, meta.gid, stat.size, format_time(meta.modified), name
+        );
+        if stat.kind == Kind::SymLink {
+            line += &format!(" -> {}", self.fs.read_link(path)?);
+        }
+        Ok(line)
+    }
+
+    fn ls<W: Write>(&self, args: &[&str], out: &mut W) -> Result<(), String> {
+        let (options, paths) = Shell::options(args, "l")?;
+        let long = options.contains('l');
+        let paths = if paths.is_empty() { vec!["."] } else { paths };
+        for (i, path) in paths.iter().enumerate() {
+            let mut lines = Vec::new();
+            if self.fs.stat(path)?.kind == Kind::Directory {
+                let mut names = self.fs.list_dir(path)?;
+                names.sort();
+                for name in names {
+                    lines.push(if long { self.long_line(&join(path, &name), &name)? } else { name });
+                }
+            } else {
+                lines.push(if long { self.long_line(path, path)? } else { path.to_string() });
+            }
+            if paths.len() > 1 {
+                lines.insert(0, format!("{}{}:", if i > 0 { "\n" } else { "" }, path));
+            }
+            for line in lines {
+                writeln!(out, "{}", line).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn mkdir(&mut self, args: &[&str]) -> Result<(), String> {
+        let (options, dirs) = Shell::options(args, "p")?;
+        for dir in dirs {
+            if !options.contains('p') {
+                let (parent, name) = FileSystem::split_parent(dir);
+                self.fs.make_dir(parent, name)?;
+                continue;
+            }
+            // each missing directory of the path, from the top
+            let mut path = if dir.starts_with('/') { "/".to_string() } else { ".".to_string() };
+            for name in dir.split('/').filter(|n| !n.is_empty()) {
+                let next = join(&path, name);
+                match self.fs.stat(&next) {
+                    Ok(s) if s.kind == Kind::Directory => {}
+                    Ok(_) => return Err(format!("{} is not a directory", next)),
+                    Err(_) => self.fs.make_dir(&path, name)?,
+                }
+                path = next;
+            }
+        }
+        Ok(())
+    }
+
+    fn ln(&mut self, args: &[&str]) -> Result<(), String> {
+        let (options, args) = Shell::options(args, "s")?;
+        if !options.contains('s') {
+            return Err("only symbolic links are supported, use -s".to_string());
+        }
+        let [target, link] = args[..] else { return Err("usage: ln -s TARGET LINK".to_string()) };
+        let (dir, name) = FileSystem::split_parent(link);
+        self.fs.make_link(dir, name, target)
+    }
+
+    fn mv(&mut self, args: &[&str]) -> Result<(), String> {
+        let [src, dst] = args[..] else { return Err("usage: mv SRC DST".to_string()) };
+        if self.fs.stat(dst).is_ok_and(|s| s.kind == Kind::Directory) {
+            self.fs.move_item(src, dst, base_name(src))
+        } else {
+            let (dir, name) = FileSystem::split_parent(dst);
+            self.fs.move_item(src, dir, name)
+        }
+    }
+
+    fn rm(&mut self, args: &[&str]) -> Result<(), String> {
+        let (options, paths) = Shell::options(args, "r")?;
+        for path in paths {
+            if self.fs.lstat(path)?.kind == Kind::Directory && !options.contains('r') {
+                return Err(format!("{} is a directory, use -r", path));
+            }
+            self.fs.delete(path)?;
+        }
+        Ok(())
+    }
+
+    fn cat<W: Write>(&self, files: &[&str], out: &mut W) -> Result<(), String> {
+        for file in files {
+            out.write_all(&self.fs.read_file(file)?).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn echo<W: Write>(&mut self, args: &[&str], out: &mut W) -> Result<(), String> {
+        let redirect = args.iter().position(|&a| a == ">" || a == ">>");
+        let text = args[..redirect.unwrap_or(args.len())].join(" ") + "\n";
+        match redirect {
+            None => out.write_all(text.as_bytes()).map_err(|e| e.to_string()),
+            Some(i) => {
+                let [file] = args[i + 1..] else { return Err(format!("usage: echo TEXT... {} FILE", args[i])) };
+                if args[i] == ">" {
+                    self.fs.write_file(file, text.as_bytes())
+                } else {
+                    self.fs.append(file, text.as_bytes())
+                }
+            }
+        }
+    }
+
+    // as tree(1), the links are shown and not followed:
+    // /home
+    // ├── user
+    // │   └── file.txt
+    // └── link_user -> /home/user
+    fn tree<W: Write>(&self, path: &str, out: &mut W) -> Result<(), String> {
+        let mut lines = vec![path.to_string()];
+        let mut counts = (0, 0); // directories, files
+        let names = |p: &str| -> Result<Vec<String>, String> {
+            let mut names = self.fs.list_dir(p)?;
+            names.sort();
+            Ok(names)
+        };
+        // (path, prefix of the levels above, last child)
+        let first = names(path)?;
+        let mut to_visit: Vec<(String, String, bool)> = first.iter().enumerate().rev()
+            .map(|(i, n)| (join(path, n), String::new(), i + 1 == first.len()))
+            .collect();
+        while let Some((p, prefix, last)) = to_visit.pop() {
+            let stat = self.fs.lstat(&p)?;
+            let mut line = format!("{}{}{}", prefix, if last { "└── " } else { "├── " }, base_name(&p));
+            match stat.kind {
+                Kind::SymLink => {
+                    line += &format!(" -> {}", self.fs.read_link(&p)?);
+                    counts.1 += 1;
+                }
+                Kind::File => counts.1 += 1,
+                Kind::Directory => {
+                    counts.0 += 1;
+                    let prefix = prefix + if last { "    " } else { "│   " };
+                    // a directory that cannot be read is shown empty
+                    let children = names(&p).unwrap_or_default();
+                    to_visit.extend(children.iter().enumerate().rev()
+                        .map(|(i, n)| (join(&p, n), prefix.clone(), i + 1 == children.len())));
+                }
+            }
+            lines.push(line);
+        }
+        for line in lines {
+            writeln!(out, "{}", line).map_err(|e| e.to_string())?;
+        }
+        writeln!(out, "\n{} directories, {} files", counts.0, counts.1).map_err(|e| e.to_string())
+    }
+
+    fn find<W: Write>(&self, args: &[&str], out: &mut W) -> Result<(), String> {
+        let (start, pattern) = match args {
+            ["-name", pattern] => (".", *pattern),
+            [start, "-name", pattern] => (*start, *pattern),
+            _ => return Err("usage: find [PATH] -name PATTERN".to_string()),
+        };
+        // the walk gives absolute paths, they are shown from start as it was typed
+        let top = self.fs.find(start).map(|n| FileSystem::full_path(&n)).ok_or_else(|| format!("Item {} not found", start))?;
+        for (path, _) in self.fs.search(start).name_glob(pattern) {
+            let below = &path[top.len()..];
+            let shown = if top == "/" && !below.is_empty() { join(start, below) } else { format!("{}{}", start, below) };
+            writeln!(out, "{}", shown).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn stat<W: Write>(&self, args: &[&str], out: &mut W) -> Result<(), String> {
+        let [path] = args[..] else { return Err("usage: stat PATH".to_string()) };
+        let stat = self.fs.lstat(path)?;
+        let meta = stat.metadata;
+        let text = format!(
+            "  File: {}\n  Size: {}\t{:?}\n  Mode: {:04o}/{}\tUid: {}\tGid: {}\n\
+             Access: {}\nModify: {}\n Birth: {}",
+            path, stat.size, stat.kind, meta.mode, stat.mode_string(), meta.uid, meta.gid,
+            format_time(meta.accessed), format_time(meta.modified), format_time(meta.created)
+        );
+        writeln!(out, "{}", text).map_err(|e| e.to_string())
+    }
+
+    fn chmod(&mut self, args: &[&str]) -> Result<(), String> {
+        let [mode, path] = args[..] else { return Err("usage: chmod MODE PATH".to_string()) };
+        let mode = u32::from_str_radix(mode, 8).map_err(|_| format!("invalid mode {}", mode))?;
+        self.fs.chmod(path, mode)
+    }
+
+    // the candidates for the word that ends at pos: the commands for the first word, then
+    // the names in the directory of the word, found with find_full from the current
+    // directory; the directories (and the links to directories) end with /
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        if line[..start].trim().is_empty() {
+            let commands = COMMANDS.iter().filter(|c| c.starts_with(word)).map(|c| c.to_string() + " ");
+            return (start, commands.collect());
+        }
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => (&word[..=i], &word[i + 1..]),
+            None => ("", word),
+        };
+        // the final "." makes find_full follow dir when it is a link
+        let Some(node) = self.fs.find_full(&format!("{}.", dir), None) else { return (start, vec![]) };
+        let node = node.borrow();
+        let Some(children) = node.get_children() else { return (start, vec![]) };
+        let mut candidates: Vec<String> = children.iter()
+            .filter_map(|c| {
+                let c = c.borrow();
+                let name = c.name();
+                if !name.starts_with(prefix) {
+                    return None;
+                }
+                let is_dir = match c.deref() {
+                    FSItem::Directory(_) => true,
+                    FSItem::SymLink(link) => self.fs.follow_link(&c.abs_path(), link)
+                        .is_some_and(|t| matches!(t.borrow().deref(), FSItem::Directory(_))),
+                    FSItem::File(_) => false,
+                };
+                Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { " " }))
+            })
+            .collect();
+        candidates.sort();
+        (start, candidates)
+    }
+}
+
+impl Completer for Shell {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(Shell::complete(self, line, pos))
+    }
+}
+
+impl Hinter for Shell {
+    type Hint = String;
+}
+
+impl Highlighter for Shell {}
+
+impl Validator for Shell {}
+
+impl Helper for Shell {}
+
+// the REPL; exit codes: 0 ok, 1 the terminal failed, 2 bad arguments
+pub fn run<I: IntoIterator<Item = String>>(args: I) -> i32 {
+    let config = match parse_args(args) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("file_system: {}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    if config.help {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let fs = match &config.dir {
+        Some(dir) if !Path::new(dir).is_dir() => {
+            eprintln!("file_system: {} is not a directory", dir);
+            return 2;
+        }
+        Some(dir) => {
+            let mut fs = FileSystem::from_file_system(dir);
+            fs.set_side_effects(config.side_effects);
+            fs
+        }
+        None => FileSystem::new(),
+    };
+
+    let mut editor: Editor<Shell, DefaultHistory> = match Editor::new() {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("file_system: {}", e);
+            return 1;
+        }
+    };
+    editor.set_helper(Some(Shell::new(fs)));
+    let mut out = io::stdout();
+    loop {
+        let prompt = editor.helper().expect("set above").prompt();
+        match editor.readline(&prompt) {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                let shell = editor.helper_mut().expect("set above");
+                match shell.execute(&line, &mut out) {
+                    Ok(true) => {}
+                    Ok(false) => return 0,
+                    Err(e) => eprintln!("{}", e),
+                }
+                let _ = out.flush();
+            }
+            // ctrl-c drops the line, ctrl-d quits
+            Err(ReadlineError::Interrupted) => {}
+            Err(ReadlineError::Eof) => return 0,
+            Err(e) => {
+                eprintln!("file_system: {}", e);
+                return 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell() -> Shell {
+        let mut shell = Shell::new(FileSystem::new());
+        for line in ["mkdir -p /home/user/docs /tmp", "echo hello world > /home/user/a.txt", "touch /home/user/b.txt",
+            "ln -s /home/user /home/link"] {
+            run_line(&mut shell, line).unwrap();
+        }
+        shell
+    }
+
+    fn run_line(shell: &mut Shell, line: &str) -> Result<String, String> {
+        let mut out = Vec::new();
+        shell.execute(line, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut sh = shell();
+        assert_eq!(run_line(&mut sh, "ls /home/user").unwrap(), "a.txt\nb.txt\ndocs\n");
+        assert_eq!(run_line(&mut sh, "cat /home/link/a.txt").unwrap(), "hello world\n");
+        run_line(&mut sh, "cd /home/link/docs").unwrap();
+        assert_eq!(run_line(&mut sh, "pwd").unwrap(), "/home/user/docs\n");
+        assert_eq!(sh.prompt(), "/home/user/docs$ ");
+        run_line(&mut sh, "echo 'one  two' >> ../a.txt").unwrap();
+        assert_eq!(run_line(&mut sh, "cat ../a.txt").unwrap(), "hello world\none  two\n");
+
+        run_line(&mut sh, "mv ../a.txt .").unwrap();
+        run_line(&mut sh, "mv ../b.txt /tmp/c.txt").unwrap();
+        assert_eq!(run_line(&mut sh, "ls . /tmp").unwrap(), ".:\na.txt\n\n/tmp:\nc.txt\n");
+        assert_eq!(run_line(&mut sh, "mv /home /home/user"), Err("mv: Cannot move /home inside itself".to_string()));
+
+        assert_eq!(run_line(&mut sh, "rm /tmp"), Err("rm: /tmp is a directory, use -r".to_string()));
+        run_line(&mut sh, "rm -r /tmp").unwrap();
+        assert_eq!(run_line(&mut sh, "mkdir /home/user"), Err("mkdir: Item /home/user already exists".to_string()));
+        run_line(&mut sh, "mkdir -p /home/user/docs/x").unwrap();
+        assert_eq!(run_line(&mut sh, "find / -name '*.txt'").unwrap(), "/home/user/docs/a.txt\n");
+        assert_eq!(run_line(&mut sh, "find -name x").unwrap(), "./x\n");
+
+        let ls = run_line(&mut sh, "ls -l /home").unwrap();
+        let lines: Vec<&str> = ls.lines().collect();
+        assert!(lines[0].starts_with("lrwxrwxrwx     0     0       10 ") && lines[0].ends_with(" link -> /home/user"), "{}", ls);
+        assert!(lines[1].starts_with("drwxr-xr-x     0     0        0 ") && lines[1].ends_with(" user"), "{}", ls);
+        run_line(&mut sh, "chmod 750 a.txt").unwrap();
+        assert!(run_line(&mut sh, "stat a.txt").unwrap().contains("Mode: 0750/-rwxr-x---"));
+
+        assert_eq!(run_line(&mut sh, "ls -a"), Err("ls: unknown option -a".to_string()));
+        assert_eq!(run_line(&mut sh, "frobnicate"), Err("frobnicate: command not found, try help".to_string()));
+        assert_eq!(run_line(&mut sh, "echo 'open"), Err("unterminated quote".to_string()));
+        let history = run_line(&mut sh, "history").unwrap();
+        assert!(history.starts_with("   1  mkdir -p /home/user/docs /tmp\n") && history.ends_with("  history\n"), "{}", history);
+        assert!(!sh.execute("exit", &mut Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn test_bad_paths_and_names() {
+        let mut sh = shell();
+        // above the root there is the root
+        run_line(&mut sh, "cd ..").unwrap();
+        assert_eq!(run_line(&mut sh, "pwd").unwrap(), "/\n");
+        run_line(&mut sh, "cd ../../home").unwrap();
+        assert_eq!(run_line(&mut sh, "pwd").unwrap(), "/home\n");
+        run_line(&mut sh, "cd /").unwrap();
+        assert_eq!(sh.complete("ls ../", 6), (3, vec!["../home/".to_string(), "../tmp/".to_string()]));
+
+        for name in ["/", ".", "a/..", "/tmp/"] {
+            assert!(run_line(&mut sh, &format!("mkdir {}", name)).is_err(), "{}", name);
+        }
+        assert_eq!(run_line(&mut sh, "ln -s /home /tmp/.."), Err("ln: Invalid name \"..\"".to_string()));
+        assert_eq!(run_line(&mut sh, "ls /").unwrap(), "home\ntmp\n");
+
+        assert_eq!(run_line(&mut sh, "cd /home/user/a.txt"), Err("cd: /home/user/a.txt is not a directory".to_string()));
+        // a link to a directory is followed
+        run_line(&mut sh, "cd /home/link").unwrap();
+        assert_eq!(run_line(&mut sh, "ls").unwrap(), "a.txt\nb.txt\ndocs\n");
+    }
+
+    #[test]
+    fn test_link_loop() {
+        let mut sh = shell();
+        run_line(&mut sh, "ln -s /tmp/b /tmp/a").unwrap();
+        run_line(&mut sh, "ln -s /tmp/a /tmp/b").unwrap();
+        assert_eq!(run_line(&mut sh, "cat /tmp/a"), Err("cat: /tmp/a: too many levels of symbolic links".to_string()));
+        assert_eq!(run_line(&mut sh, "cat /tmp/a/x"), Err("cat: /tmp/a/x: too many levels of symbolic links".to_string()));
+        assert_eq!(run_line(&mut sh, "cd /tmp/b"), Err("cd: /tmp/b: too many levels of symbolic links".to_string()));
+        // the links of a loop are completed as files
+        assert_eq!(sh.complete("cat /tmp/", 9), (4, vec!["/tmp/a ".to_string(), "/tmp/b ".to_string()]));
+        assert_eq!(run_line(&mut sh, "cat /home/link/a.txt").unwrap(), "hello world\n");
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut sh = shell();
+        assert_eq!(run_line(&mut sh, "tree /home").unwrap(), "\
+/home
+├── link -> /home/user
+└── user
+    ├── a.txt
+    ├── b.txt
+    └── docs
+
+2 directories, 3 files
+");
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut sh = shell();
+        let complete = |sh: &Shell, line: &str| sh.complete(line, line.len());
+        assert_eq!(complete(&sh, "c"), (0, vec!["cat ".to_string(), "cd ".to_string(), "chmod ".to_string()]));
+        assert_eq!(complete(&sh, "ls /ho"), (3, vec!["/home/".to_string()]));
+        assert_eq!(complete(&sh, "cat /home/l"), (4, vec!["/home/link/".to_string()]));
+        assert_eq!(complete(&sh, "cat /home/link/"), (4, vec!["/home/link/a.txt ".to_string(), "/home/link/b.txt ".to_string(), "/home/link/docs/".to_string()]));
+        assert_eq!(complete(&sh, "cat /nothing/a").1, Vec::<String>::new());
+        run_line(&mut sh, "cd /home/user").unwrap();
+        assert_eq!(complete(&sh, "cat d"), (4, vec!["docs/".to_string()]));
+        // in the middle of the line
+        assert_eq!(sh.complete("rm a more", 4), (3, vec!["a.txt ".to_string()]));
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert_eq!(split_words(r#"echo "a  b" c'd e'  f"#).unwrap(), ["echo", "a  b", "cd e", "f"]);
+        assert_eq!(split_words("x ''").unwrap(), ["x", ""]);
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00");
+        assert_eq!(format_time(UNIX_EPOCH + std::time::Duration::from_secs(951_827_696)), "2000-02-29 12:34");
+        assert_eq!(parse_args(["-d".to_string(), "x".to_string(), "-s".to_string()]).unwrap(),
+            Config { dir: Some("x".to_string()), side_effects: true, help: false });
+        assert!(parse_args(["--side-effects".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_side_effects_from_dir() {
+        let dir = std::env::temp_dir().join(format!("file_system_shell_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("etc")).unwrap();
+        std::fs::write(dir.join("etc/hosts"), "127.0.0.1 localhost\n").unwrap();
+        let mut fs = FileSystem::from_file_system(dir.to_str().unwrap());
+        fs.set_side_effects(true);
+        let mut sh = Shell::new(fs);
+        assert_eq!(run_line(&mut sh, "cat /etc/hosts").unwrap(), "127.0.0.1 localhost\n");
+        run_line(&mut sh, "mkdir -p /var/log").unwrap();
+        run_line(&mut sh, "mv /etc/hosts /var/log").unwrap();
+        run_line(&mut sh, "echo more >> /var/log/hosts").unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("var/log/hosts")).unwrap(), "127.0.0.1 localhost\nmore\n");
+        assert!(!dir.join("etc/hosts").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}